@@ -72,18 +72,33 @@ impl Config {
                 println!("Welcome back, {}!\n", username);
             }
         } else {
-            let request_token = egg_mode::auth::request_token(&con_token, "oob").await.unwrap();
+            //with the loopback feature, twitter can send the verifier straight back to us, as long
+            //as http://127.0.0.1:8000/callback is one of your app's callback URLs
+            #[cfg(feature = "loopback")]
+            let tok_result = {
+                let login = egg_mode::auth::LoopbackLogin::start(con_token, 8000).await.unwrap();
 
-            println!("Go to the following URL, sign in, and give me the PIN that comes back:");
-            println!("{}", egg_mode::auth::authorize_url(&request_token));
+                println!("Go to the following URL and sign in:");
+                println!("{}", login.authorize_url());
 
-            let mut pin = String::new();
-            std::io::stdin().read_line(&mut pin).unwrap();
-            println!("");
+                login.finish().await.unwrap()
+            };
+
+            #[cfg(not(feature = "loopback"))]
+            let tok_result = {
+                let request_token = egg_mode::auth::request_token(&con_token, "oob").await.unwrap();
+
+                println!("Go to the following URL, sign in, and give me the PIN that comes back:");
+                println!("{}", egg_mode::auth::authorize_url(&request_token));
 
-            let tok_result = egg_mode::auth::access_token(con_token, &request_token, pin)
-                .await
-                .unwrap();
+                let mut pin = String::new();
+                std::io::stdin().read_line(&mut pin).unwrap();
+                println!("");
+
+                egg_mode::auth::access_token(con_token, &request_token, pin)
+                    .await
+                    .unwrap()
+            };
 
             token = tok_result.0;
             user_id = tok_result.1;
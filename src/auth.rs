@@ -163,6 +163,18 @@
 //! use a library like `dotenv` to load them in, so you can safely exclude them from source
 //! control.
 //!
+//! The same goes for the tokens your app receives for its users. To save them between runs, see
+//! [`TokenStore`], and the encrypted [`FileTokenStore`] available with the `token_store` feature.
+//!
+//! [`TokenStore`]: trait.TokenStore.html
+//! [`FileTokenStore`]: struct.FileTokenStore.html
+//!
+//! With the `loopback` feature enabled, apps that can't receive a redirect from a web server (like
+//! CLI tools) can skip the PIN entirely with a [`LoopbackLogin`], which listens for Twitter's
+//! redirect on a local port and completes the process as soon as the user accepts the app.
+//!
+//! [`LoopbackLogin`]: struct.LoopbackLogin.html
+//!
 //! ### Shortcut: Pre-Generated Access Token
 //!
 //! If you only want to sign in as yourself, there's a shortcut you can use to get an Access token.
@@ -181,13 +193,69 @@
 //! // token can be given to any egg_mode method that asks for a token
 //! ```
 //!
+//! ## OAuth 2.0 User Tokens
+//!
+//! API v2 endpoints (in the [`v2`] module) can also be called on behalf of a user with a token
+//! from the OAuth 2.0 [Authorization Code flow with PKCE][pkce]. Instead of a consumer token, this
+//! flow uses a client ID from the OAuth 2.0 settings of your app, which is kept in an
+//! [`OAuth2Client`] along with the URI to redirect the user back to. Rather than granting access
+//! to the whole account, the user grants a specific set of [`Scope`]s.
+//!
+//! [`v2`]: ../v2/index.html
+//! [pkce]: https://developer.twitter.com/en/docs/authentication/oauth-2-0/authorization-code
+//! [`OAuth2Client`]: struct.OAuth2Client.html
+//! [`Scope`]: enum.Scope.html
+//!
+//! The process has two steps, plus a third to keep the token fresh:
+//!
+//! 1. Direct the user to grant permission to your application by sending them to an [OAuth 2.0
+//!    authorize URL][oauth2_authorize_url], with a new [`PkceVerifier`].
+//! 2. Exchange the code given to your redirect URI for an [access token][oauth2_access_token].
+//! 3. Once the access token expires, [refresh it][oauth2_refresh_token].
+//!
+//! [oauth2_authorize_url]: fn.oauth2_authorize_url.html
+//! [`PkceVerifier`]: struct.PkceVerifier.html
+//! [oauth2_access_token]: fn.oauth2_access_token.html
+//! [oauth2_refresh_token]: fn.oauth2_refresh_token.html
+//!
+//! ### Example (OAuth 2.0 User Token)
+//!
+//! ```rust,no_run
+//! # #[tokio::main]
+//! # async fn main() {
+//! use egg_mode::auth::{OAuth2Client, PkceVerifier, Scope};
+//!
+//! let client = OAuth2Client::new("client id", "https://myapp.io/auth");
+//! let verifier = PkceVerifier::generate();
+//! let scopes = [Scope::TweetRead, Scope::UsersRead, Scope::OfflineAccess];
+//! let auth_url = egg_mode::auth::oauth2_authorize_url(&client, scopes, "state", &verifier);
+//!
+//! // send the user to auth_url; once they accept, they're redirected to
+//! // https://myapp.io/auth?state=state&code=...
+//!
+//! let code = "..."; // read the code from the redirect here
+//! let token = egg_mode::auth::oauth2_access_token(&client, &verifier, code).await.unwrap();
+//!
+//! // token can be given to the methods in the v2 module
+//!
+//! // later, once the token has expired:
+//! let token = egg_mode::auth::oauth2_refresh_token(&client, &token).await.unwrap();
+//! # }
+//! ```
+//!
+//! Rather than refreshing tokens by hand, you can put one in a [`SharedToken`], which refreshes
+//! itself whenever Twitter rejects it, and can tell your app about each new token so it can be
+//! saved.
+//!
+//! [`SharedToken`]: struct.SharedToken.html
+//!
 //! For more information on the individual steps of the authentication process, see the
 //! documentation for the functions in this module.
 
 use std::borrow::Cow;
 
 use hyper::Method;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Serializer, Deserialize};
 use serde_json;
 
 use crate::common::*;
@@ -196,7 +264,24 @@ use crate::{
     links,
 };
 
+#[cfg(any(test, feature = "token_store"))]
+mod file_store;
+#[cfg(any(test, feature = "loopback"))]
+mod loopback;
+mod oauth2;
+mod pool;
 pub(crate) mod raw;
+mod shared;
+mod store;
+
+#[cfg(any(test, feature = "token_store"))]
+pub use self::file_store::*;
+#[cfg(any(test, feature = "loopback"))]
+pub use self::loopback::*;
+pub use self::oauth2::*;
+pub use self::pool::*;
+pub use self::shared::*;
+pub use self::store::*;
 
 use raw::RequestBuilder;
 
@@ -255,13 +340,15 @@ impl KeyPair {
 /// A token that can be used to sign requests to Twitter.
 ///
 /// Conceptually, a Token represents your authorization to call the Twitter API. It can either be a
-/// [Bearer token], representing a "logged-out" view of Twitter coming from your app itself; or an
+/// [Bearer token], representing a "logged-out" view of Twitter coming from your app itself; an
 /// [Access token], representing a combination of your app's "consumer" key with a specific user
-/// granting access for your app to use the Twitter API on their behalf. For more information, see
-/// the [authentication documentation][auth].
+/// granting access for your app to use the Twitter API on their behalf; or an [OAuth 2.0 user
+/// token], which also represents a specific user, but only works with API v2. For more
+/// information, see the [authentication documentation][auth].
 ///
 /// [Bearer token]: index.html#bearer-tokens
 /// [Access token]: index.html#access-tokens
+/// [OAuth 2.0 user token]: index.html#oauth-20-user-tokens
 /// [auth]: index.html
 ///
 /// Once you have obtained a Token of either kind, the keys within may be saved and reused in the
@@ -273,7 +360,11 @@ impl KeyPair {
 ///
 /// [apps]: https://developer.twitter.com/en/apps
 /// [invalidate]: fn.invalidate_bearer.html
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// New kinds of tokens may be added to this enum in the future, so matching on it needs a wildcard
+/// arm.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
 pub enum Token {
     /// An OAuth Access token indicating the request is coming from a specific user.
     Access {
@@ -285,6 +376,55 @@ pub enum Token {
     /// An OAuth Bearer token indicating the request is coming from the application itself, not a
     /// particular user.
     Bearer(String),
+    /// An OAuth 2.0 user token indicating the request is coming from a specific user, obtained
+    /// with the [Authorization Code flow with PKCE][oauth2].
+    ///
+    /// [oauth2]: index.html#oauth-20-user-tokens
+    OAuth2(OAuth2Token),
+    /// A shared handle to an OAuth 2.0 user token, which refreshes itself when it expires.
+    ///
+    /// Since this refers to shared state, it's serialized as the `Token::OAuth2` it currently
+    /// holds (given by `SharedToken::current`), and is loaded back as one. See [`SharedToken`] for
+    /// details.
+    ///
+    /// [`SharedToken`]: struct.SharedToken.html
+    #[serde(skip)]
+    Shared(SharedToken),
+    /// A pool of tokens, which signs each request with the token that has the most calls left for
+    /// it.
+    ///
+    /// This is serialized as the list of tokens in the pool, and loaded back as a new pool with
+    /// those tokens. See [`TokenPool`] for details.
+    ///
+    /// [`TokenPool`]: struct.TokenPool.html
+    Pool(TokenPool),
+}
+
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, ser: S) -> std::result::Result<S::Ok, S::Error> {
+        /// The variants of `Token` that can be loaded back, which `Token::Shared` is saved as one of.
+        #[derive(Serialize)]
+        #[serde(rename = "Token")]
+        enum SavedToken<'a> {
+            Access {
+                consumer: &'a KeyPair,
+                access: &'a KeyPair,
+            },
+            Bearer(&'a str),
+            OAuth2(Cow<'a, OAuth2Token>),
+            Pool(&'a TokenPool),
+        }
+
+        match self {
+            Token::Access { consumer, access } => {
+                SavedToken::Access { consumer, access }.serialize(ser)
+            }
+            Token::Bearer(bearer) => SavedToken::Bearer(bearer).serialize(ser),
+            Token::OAuth2(token) => SavedToken::OAuth2(Cow::Borrowed(token)).serialize(ser),
+            Token::Shared(shared) => SavedToken::OAuth2(Cow::Owned(shared.current())).serialize(ser),
+            Token::Pool(pool) => SavedToken::Pool(pool).serialize(ser),
+        }
+    }
 }
 
 /// With the given consumer KeyPair, ask Twitter for a request KeyPair that can be used to request
@@ -421,7 +561,7 @@ pub async fn request_token<S: Into<String>>(con_token: &KeyPair, callback: S) ->
 pub fn authorize_url(request_token: &KeyPair) -> String {
     format!(
         "{}?oauth_token={}",
-        links::auth::AUTHORIZE,
+        links::resolve(links::auth::AUTHORIZE),
         request_token.key
     )
 }
@@ -462,7 +602,7 @@ pub fn authorize_url(request_token: &KeyPair) -> String {
 pub fn authenticate_url(request_token: &KeyPair) -> String {
     format!(
         "{}?oauth_token={}",
-        links::auth::AUTHENTICATE,
+        links::resolve(links::auth::AUTHENTICATE),
         request_token.key
     )
 }
@@ -0,0 +1,478 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A `TokenStore` that keeps its tokens in an encrypted file.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{fs, io};
+
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::Aes256Gcm;
+use fd_lock::RwLock;
+use rand::Rng;
+
+use crate::error::Result;
+
+use super::{Token, TokenStore};
+
+/// The bytes at the start of every file written by a `FileTokenStore`, including a format version.
+const MAGIC: &[u8; 8] = b"EGGTOKS\x01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// The number of PBKDF2 rounds used to turn a passphrase into a key.
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// The tokens in a store, by account name.
+type Tokens = BTreeMap<String, Token>;
+
+/// A `TokenStore` that keeps its tokens in a file, encrypted with a passphrase or key.
+///
+/// The tokens are saved as JSON, encrypted with AES-256-GCM. When the store is opened with a
+/// passphrase, the key is derived from it with PBKDF2-HMAC-SHA256 and a random salt, which is
+/// chosen when the file is created and kept at the start of it. On Unix, the file is only readable
+/// by its owner.
+///
+/// The file is read again for each call, and replaced as a whole each time a token is saved or
+/// removed. Saves and removals hold an advisory lock on a `.lock` file next to the store while
+/// they read and rewrite it, so several processes can share a file without losing each other's
+/// changes.
+///
+/// This is only available with the `token_store` feature enabled.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use egg_mode::auth::{FileTokenStore, TokenStore};
+///
+/// let store = FileTokenStore::open("tokens.bin", "correct horse battery staple").unwrap();
+///
+/// let token = egg_mode::Token::Bearer("bearer token".to_string());
+/// store.save("app", &token).unwrap();
+///
+/// let token = store.load("app").unwrap();
+/// ```
+pub struct FileTokenStore {
+    path: PathBuf,
+    secret: Secret,
+    /// The cipher for the salt in the file when it was last read, so the key doesn't need to be
+    /// derived again for every call.
+    cipher: Mutex<Option<([u8; SALT_LEN], Aes256Gcm)>>,
+    /// Held while the file is being rewritten, so that saves from this process don't overwrite
+    /// each other. Saves from other processes are kept out by the lock file.
+    lock: Mutex<()>,
+}
+
+/// What a `FileTokenStore` gets its key from.
+enum Secret {
+    /// A passphrase, which the key is derived from with the salt in the file.
+    Passphrase(String),
+    /// The key itself.
+    Key([u8; 32]),
+}
+
+impl FileTokenStore {
+    /// Opens the store at the given path, using a key derived from the given passphrase.
+    ///
+    /// If the file doesn't exist yet, it's created when the first token is saved. If it does
+    /// exist, this returns an error if it isn't a token store, but the passphrase isn't checked
+    /// until the first call, which returns an error if it's wrong.
+    pub fn open<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<FileTokenStore> {
+        FileTokenStore::new(path.as_ref(), Secret::Passphrase(passphrase.to_string()))
+    }
+
+    /// Opens the store at the given path, using the given 256-bit key directly.
+    ///
+    /// This is useful if your app already keeps a key somewhere safe, like the operating
+    /// system's keychain. Like `open`, this returns an error if the file exists but isn't a token
+    /// store.
+    pub fn open_with_key<P: AsRef<Path>>(path: P, key: [u8; 32]) -> Result<FileTokenStore> {
+        FileTokenStore::new(path.as_ref(), Secret::Key(key))
+    }
+
+    fn new(path: &Path, secret: Secret) -> Result<FileTokenStore> {
+        match fs::read(path) {
+            Ok(data) => {
+                split_file(&data)?;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(FileTokenStore {
+            path: path.to_path_buf(),
+            secret,
+            cipher: Mutex::new(None),
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Returns the cipher for a file with the given salt.
+    fn cipher(&self, salt: &[u8; SALT_LEN]) -> Aes256Gcm {
+        let mut cached = self.cipher.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((ref cached_salt, ref cipher)) = *cached {
+            if cached_salt == salt {
+                return cipher.clone();
+            }
+        }
+
+        let key = match self.secret {
+            Secret::Key(key) => key,
+            Secret::Passphrase(ref passphrase) => {
+                let mut key = [0u8; 32];
+                pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha256>>(
+                    passphrase.as_bytes(),
+                    salt,
+                    PBKDF2_ROUNDS,
+                    &mut key,
+                );
+                key
+            }
+        };
+        let cipher = Aes256Gcm::new((&key).into());
+        *cached = Some((*salt, cipher.clone()));
+        cipher
+    }
+
+    /// Reads and decrypts the tokens in the file.
+    fn read(&self) -> Result<Tokens> {
+        Ok(self
+            .read_file()?
+            .map_or_else(BTreeMap::new, |(_, tokens)| tokens))
+    }
+
+    /// Reads and decrypts the file, returning the salt in its header along with its tokens, or
+    /// `None` if it doesn't exist yet.
+    fn read_file(&self) -> Result<Option<([u8; SALT_LEN], Tokens)>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let FileParts {
+            header,
+            salt,
+            nonce,
+            ciphertext,
+        } = split_file(&data)?;
+        let plaintext = self
+            .cipher(&salt)
+            .decrypt(
+                (&nonce).into(),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map_err(|_| invalid_data("the token store could not be decrypted; wrong passphrase?"))?;
+
+        Ok(Some((salt, serde_json::from_slice(&plaintext)?)))
+    }
+
+    /// Encrypts the given tokens into the contents of a file with the given salt.
+    fn encrypt(&self, salt: &[u8; SALT_LEN], tokens: &Tokens) -> Result<Vec<u8>> {
+        let plaintext = serde_json::to_vec(tokens)?;
+        let nonce: [u8; NONCE_LEN] = rand::thread_rng().gen();
+
+        let mut data = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + plaintext.len() + 16);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(salt);
+        let ciphertext = self
+            .cipher(salt)
+            .encrypt(
+                (&nonce).into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: &data,
+                },
+            )
+            .map_err(|_| invalid_data("the tokens could not be encrypted"))?;
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    /// Reads the tokens, applies the given change to them, and writes them back, while holding
+    /// the lock file.
+    ///
+    /// If the file doesn't exist yet, it's created with a new salt.
+    fn update(&self, change: impl FnOnce(&mut Tokens)) -> Result<()> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut lock_file = RwLock::new(open_private(&self.sibling("lock"))?);
+        let _file_lock = lock_file.write()?;
+
+        let (salt, mut tokens) = match self.read_file()? {
+            Some(file) => file,
+            None => (rand::thread_rng().gen(), BTreeMap::new()),
+        };
+        change(&mut tokens);
+        let data = self.encrypt(&salt, &tokens)?;
+
+        // write to a temporary file first, so the store isn't lost if writing fails halfway
+        let suffix: u64 = rand::thread_rng().gen();
+        let tmp = self.sibling(&format!("{:016x}.tmp", suffix));
+        let written = write_private(&tmp, &data).and_then(|()| fs::rename(&tmp, &self.path));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        Ok(written?)
+    }
+
+    /// Returns the path of a file next to the store, named after it with the given extension.
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".");
+        path.push(extension);
+        PathBuf::from(path)
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, account: &str) -> Result<Option<Token>> {
+        Ok(self.read()?.remove(account))
+    }
+
+    fn save(&self, account: &str, token: &Token) -> Result<()> {
+        self.update(|tokens| {
+            tokens.insert(account.to_string(), token.clone());
+        })
+    }
+
+    fn remove(&self, account: &str) -> Result<()> {
+        self.update(|tokens| {
+            tokens.remove(account);
+        })
+    }
+
+    fn accounts(&self) -> Result<Vec<String>> {
+        Ok(self.read()?.into_keys().collect())
+    }
+}
+
+impl std::fmt::Debug for FileTokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("FileTokenStore")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// The parts of a store's file.
+struct FileParts<'a> {
+    /// The magic bytes and the salt, which are authenticated along with the ciphertext.
+    header: &'a [u8],
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+    ciphertext: &'a [u8],
+}
+
+/// Splits the contents of a store's file into its parts, or returns an error if they aren't a
+/// token store.
+fn split_file(data: &[u8]) -> Result<FileParts<'_>> {
+    let header_len = MAGIC.len() + SALT_LEN;
+    if data.len() < header_len + NONCE_LEN || !data.starts_with(MAGIC) {
+        return Err(invalid_data("the file is not a token store"));
+    }
+    let (header, rest) = data.split_at(header_len);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    Ok(FileParts {
+        header,
+        salt: header[MAGIC.len()..].try_into().unwrap(),
+        nonce: nonce.try_into().unwrap(),
+        ciphertext,
+    })
+}
+
+/// Returns options to open a file that only its owner can read, once it's created.
+fn private_options() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+/// Opens the file at the given path for writing, creating it if it doesn't exist, so it can be
+/// used as a lock file.
+fn open_private(path: &Path) -> io::Result<fs::File> {
+    private_options()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+/// Writes the given data to a new file at the given path, which only its owner can read.
+///
+/// This fails with `ErrorKind::AlreadyExists` if the file already exists, instead of replacing
+/// it.
+fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut file = private_options().write(true).create_new(true).open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+fn invalid_data(msg: &'static str) -> crate::error::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{OAuth2Client, OAuth2Token, SharedToken};
+    use crate::KeyPair;
+
+    /// Returns a path in the temp directory that's unique to this test run.
+    fn temp_path(name: &str) -> PathBuf {
+        let suffix: u64 = rand::thread_rng().gen();
+        std::env::temp_dir().join(format!("egg-mode-{}-{:x}.bin", name, suffix))
+    }
+
+    #[test]
+    fn round_trip_tokens() {
+        let path = temp_path("round-trip");
+        let store = FileTokenStore::open(&path, "passphrase").unwrap();
+        assert_eq!(store.accounts().unwrap(), Vec::<String>::new());
+
+        let access = Token::Access {
+            consumer: KeyPair::new("consumer key", "consumer secret"),
+            access: KeyPair::new("access key", "access secret"),
+        };
+        let bearer = Token::Bearer("bearer".to_string());
+        let shared = SharedToken::new(
+            OAuth2Client::new("client", "https://myapp.io/auth"),
+            OAuth2Token {
+                access_token: "oauth2".to_string(),
+                refresh_token: None,
+                expires_at: None,
+                scopes: vec![],
+                lineage: None,
+            },
+        );
+        store.save("user", &access).unwrap();
+        store.save("app", &bearer).unwrap();
+        store.save("shared", &shared.token()).unwrap();
+
+        // the tokens shouldn't be readable from the file
+        let data = fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("access secret"));
+
+        let store = FileTokenStore::open(&path, "passphrase").unwrap();
+        assert_eq!(store.accounts().unwrap(), ["app", "shared", "user"]);
+        match store.load("user").unwrap() {
+            Some(Token::Access { consumer, access }) => {
+                assert_eq!(consumer.secret, "consumer secret");
+                assert_eq!(access.key, "access key");
+                assert_eq!(access.secret, "access secret");
+            }
+            token => panic!("Not an access token: {:?}", token),
+        }
+        assert!(matches!(store.load("app").unwrap(), Some(Token::Bearer(b)) if b == "bearer"));
+        assert!(matches!(
+            store.load("shared").unwrap(),
+            Some(Token::OAuth2(ref t)) if t.access_token == "oauth2"
+        ));
+        assert!(store.load("nobody").unwrap().is_none());
+
+        store.remove("app").unwrap();
+        assert_eq!(store.accounts().unwrap(), ["shared", "user"]);
+
+        let wrong = FileTokenStore::open(&path, "wrong passphrase").unwrap();
+        assert!(wrong.load("user").is_err());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(store.sibling("lock")).unwrap();
+    }
+
+    #[test]
+    fn open_with_key() {
+        let path = temp_path("key");
+        let store = FileTokenStore::open_with_key(&path, [7; 32]).unwrap();
+        store.save("app", &Token::Bearer("bearer".to_string())).unwrap();
+
+        let store = FileTokenStore::open_with_key(&path, [7; 32]).unwrap();
+        assert!(store.load("app").unwrap().is_some());
+        let wrong = FileTokenStore::open_with_key(&path, [8; 32]).unwrap();
+        assert!(wrong.load("app").is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(store.sibling("lock")).unwrap();
+    }
+
+    #[test]
+    fn stores_share_new_file() {
+        let path = temp_path("shared");
+        let first = FileTokenStore::open(&path, "passphrase").unwrap();
+        let second = FileTokenStore::open(&path, "passphrase").unwrap();
+
+        first.save("first", &Token::Bearer("one".to_string())).unwrap();
+        second.save("second", &Token::Bearer("two".to_string())).unwrap();
+
+        assert_eq!(first.accounts().unwrap(), ["first", "second"]);
+        assert_eq!(second.accounts().unwrap(), ["first", "second"]);
+        let third = FileTokenStore::open(&path, "passphrase").unwrap();
+        assert!(matches!(third.load("first").unwrap(), Some(Token::Bearer(b)) if b == "one"));
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(first.sibling("lock")).unwrap();
+    }
+
+    #[test]
+    fn concurrent_saves_keep_every_token() {
+        let path = temp_path("concurrent");
+        let threads = (0..8)
+            .map(|thread| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let store = FileTokenStore::open_with_key(&path, [7; 32]).unwrap();
+                    for i in 0..5 {
+                        let token = Token::Bearer(format!("{}-{}", thread, i));
+                        store.save(&format!("{}-{}", thread, i), &token).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let store = FileTokenStore::open_with_key(&path, [7; 32]).unwrap();
+        assert_eq!(store.accounts().unwrap().len(), 40);
+        // only the store and its lock file are left behind
+        let dir = fs::read_dir(path.parent().unwrap()).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let files = dir
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&name))
+            .count();
+        assert_eq!(files, 2);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(store.sibling("lock")).unwrap();
+    }
+
+    #[test]
+    fn open_checks_existing_file() {
+        let path = temp_path("not-a-store");
+        fs::write(&path, "just some text").unwrap();
+        assert!(FileTokenStore::open(&path, "passphrase").is_err());
+        assert!(FileTokenStore::open_with_key(&path, [7; 32]).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A helper that receives the OAuth 1.0a callback on a local port, for apps without a web server.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use crate::error::{self, Result};
+
+use super::{access_token, authenticate_url, authorize_url, request_token, KeyPair, Token};
+
+/// The page shown to the user once Twitter redirects them back to the app.
+const DONE_PAGE: &str = "<!DOCTYPE html>\n<html><body>\
+    <p>You can close this window and return to the app.</p>\
+    </body></html>";
+
+/// The most bytes read from the head of a request to the callback URL.
+const HEAD_LIMIT: u64 = 8 * 1024;
+
+/// How long a connection to the callback URL has to send its request before it's dropped.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An in-progress OAuth 1.0a login that receives the verifier on a local port.
+///
+/// Without a web server to redirect the user back to, apps like CLI tools usually use PIN-based
+/// authorization, where the user needs to copy a PIN from their browser back into the app. A
+/// `LoopbackLogin` does away with that step: it listens for HTTP requests on `127.0.0.1`, and uses
+/// that address as the `callback` for the [request token], so that once the user accepts the app
+/// in their browser, Twitter redirects them to the app itself, with the verifier in the URL.
+///
+/// [request token]: fn.request_token.html
+///
+/// Twitter only redirects to callback URLs that have been added to your app's settings in the
+/// [Developer Portal][apps], so add `http://127.0.0.1:<port>/callback` there, with the port you
+/// give to `start`.
+///
+/// [apps]: https://developer.twitter.com/en/portal/projects-and-apps
+///
+/// This is only available with the `loopback` feature enabled.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// let con_token = egg_mode::KeyPair::new("consumer key", "consumer secret");
+/// let login = egg_mode::auth::LoopbackLogin::start(con_token, 8000).await.unwrap();
+///
+/// println!("Go to the following URL and sign in:");
+/// println!("{}", login.authorize_url());
+///
+/// let (token, user_id, screen_name) = login.finish().await.unwrap();
+///
+/// // token can be given to any egg_mode method that asks for a token
+/// // user_id and screen_name refer to the user who signed in
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LoopbackLogin {
+    listener: TcpListener,
+    con_token: KeyPair,
+    request_token: KeyPair,
+    callback: String,
+}
+
+impl LoopbackLogin {
+    /// Starts listening on the given port of `127.0.0.1`, and asks Twitter for a request token
+    /// that redirects back to it.
+    ///
+    /// Giving a port of 0 lets the operating system pick a free port, which is only useful if your
+    /// app's settings allow any callback URL.
+    pub async fn start(con_token: KeyPair, port: u16) -> Result<LoopbackLogin> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await?;
+        let callback = format!("http://{}/callback", listener.local_addr()?);
+        let request_token = request_token(&con_token, callback.clone()).await?;
+
+        Ok(LoopbackLogin {
+            listener,
+            con_token,
+            request_token,
+            callback,
+        })
+    }
+
+    /// Returns the callback URL Twitter will redirect the user to.
+    pub fn callback_url(&self) -> &str {
+        &self.callback
+    }
+
+    /// Returns the [Authorize] URL to send the user to.
+    ///
+    /// [Authorize]: fn.authorize_url.html
+    pub fn authorize_url(&self) -> String {
+        authorize_url(&self.request_token)
+    }
+
+    /// Returns the [Authenticate] URL to send the user to, for "Sign In With Twitter".
+    ///
+    /// [Authenticate]: fn.authenticate_url.html
+    pub fn authenticate_url(&self) -> String {
+        authenticate_url(&self.request_token)
+    }
+
+    /// Waits for Twitter to redirect the user back to the app, then exchanges the verifier for an
+    /// [access token].
+    ///
+    /// [access token]: fn.access_token.html
+    ///
+    /// Requests to the listener that don't carry this login's request token (like a browser
+    /// asking for a favicon) are ignored, as are connections that fail, send more than 8 KiB of
+    /// headers, or take longer than 10 seconds to send their request. If the user denies the app
+    /// access, this returns `Error::AccessDenied`.
+    ///
+    /// This waits for as long as it takes for the user to sign in. To give up after a while, wrap
+    /// it in `tokio::time::timeout`.
+    pub async fn finish(self) -> Result<(Token, u64, String)> {
+        loop {
+            let (conn, _) = self.listener.accept().await?;
+            match timeout(CONNECTION_TIMEOUT, self.handle(conn)).await {
+                Ok(Ok(Some(Callback::Verifier(verifier)))) => {
+                    return access_token(self.con_token, &self.request_token, verifier).await;
+                }
+                Ok(Ok(Some(Callback::Denied))) => return Err(error::Error::AccessDenied),
+                Ok(Ok(None)) => (),
+                Ok(Err(err)) => log::debug!("ignoring failed callback connection: {}", err),
+                Err(_) => log::debug!("ignoring callback connection that timed out"),
+            }
+        }
+    }
+
+    /// Reads one request from the listener, answers it, and returns what it says about the login,
+    /// if it's the callback for this login's request token.
+    async fn handle(&self, mut conn: TcpStream) -> Result<Option<Callback>> {
+        let query = match read_query(&mut conn).await? {
+            Some(query) => query,
+            None => {
+                respond(&mut conn, "404 Not Found", "").await?;
+                return Ok(None);
+            }
+        };
+
+        let mut token = None;
+        let mut verifier = None;
+        let mut denied = None;
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "oauth_token" => token = Some(value.into_owned()),
+                "oauth_verifier" => verifier = Some(value.into_owned()),
+                "denied" => denied = Some(value.into_owned()),
+                _ => (),
+            }
+        }
+
+        let key = &*self.request_token.key;
+        let callback = match (token, verifier) {
+            _ if denied.as_deref() == Some(key) => Callback::Denied,
+            (Some(token), Some(verifier)) if token == key => Callback::Verifier(verifier),
+            _ => {
+                respond(&mut conn, "404 Not Found", "").await?;
+                return Ok(None);
+            }
+        };
+
+        respond(&mut conn, "200 OK", DONE_PAGE).await?;
+        Ok(Some(callback))
+    }
+}
+
+/// What a request to the callback URL says about the login.
+enum Callback {
+    /// The user accepted the app, and Twitter gave this verifier.
+    Verifier(String),
+    /// The user denied the app access.
+    Denied,
+}
+
+/// Reads the head of an HTTP request, returning its query string if it's a `GET` to the callback
+/// path.
+///
+/// At most `HEAD_LIMIT` bytes are read; a request line that doesn't fit isn't treated as the
+/// callback.
+async fn read_query(conn: &mut TcpStream) -> Result<Option<String>> {
+    let mut reader = BufReader::new(conn.take(HEAD_LIMIT));
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+
+    // read the rest of the headers, so that closing the connection doesn't reset it
+    let mut header = String::new();
+    while reader.read_line(&mut header).await? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    if !line.ends_with('\n') {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let target = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => target,
+        _ => return Ok(None),
+    };
+    match target.split_once('?') {
+        Some(("/callback", query)) => Ok(Some(query.to_string())),
+        _ => Ok(None),
+    }
+}
+
+/// Writes a minimal HTTP response with the given status and HTML body, and closes the connection.
+async fn respond(conn: &mut TcpStream, status: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    conn.write_all(response.as_bytes()).await?;
+    conn.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTwitter;
+
+    use hyper::{Method, StatusCode};
+
+    /// Sends a `GET` request for the given path to the given address, and returns the response.
+    async fn get(addr: &str, path: &str) -> String {
+        let mut conn = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr);
+        conn.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        conn.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn receives_verifier() {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::POST,
+            "/oauth/request_token",
+            StatusCode::OK,
+            "oauth_token=request&oauth_token_secret=secret&oauth_callback_confirmed=true",
+        );
+        twitter.respond(
+            Method::POST,
+            "/oauth/access_token",
+            StatusCode::OK,
+            "oauth_token=access&oauth_token_secret=secret&user_id=6253282&screen_name=TwitterAPI",
+        );
+
+        let con_token = KeyPair::new("key", "secret");
+        let login = twitter
+            .run(LoopbackLogin::start(con_token, 0))
+            .await
+            .unwrap();
+        assert!(login.authorize_url().ends_with("?oauth_token=request"));
+        let addr = login.callback_url()["http://".len()..].trim_end_matches("/callback").to_string();
+
+        let browser = async {
+            let favicon = get(&addr, "/favicon.ico").await;
+            let stale = get(&addr, "/callback?oauth_token=other&oauth_verifier=nope").await;
+            let callback = get(&addr, "/callback?oauth_token=request&oauth_verifier=verifier").await;
+            (favicon, stale, callback)
+        };
+        let ((favicon, stale, callback), result) =
+            futures::join!(browser, twitter.run(login.finish()));
+
+        assert!(favicon.starts_with("HTTP/1.1 404"));
+        assert!(stale.starts_with("HTTP/1.1 404"));
+        assert!(callback.starts_with("HTTP/1.1 200"));
+        let (token, user_id, screen_name) = result.unwrap();
+        assert!(matches!(token, Token::Access { ref access, .. } if access.key == "access"));
+        assert_eq!(user_id, 6253282);
+        assert_eq!(screen_name, "TwitterAPI");
+    }
+
+    #[tokio::test]
+    async fn reports_denial() {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::POST,
+            "/oauth/request_token",
+            StatusCode::OK,
+            "oauth_token=request&oauth_token_secret=secret&oauth_callback_confirmed=true",
+        );
+
+        let con_token = KeyPair::new("key", "secret");
+        let login = twitter
+            .run(LoopbackLogin::start(con_token, 0))
+            .await
+            .unwrap();
+        let addr = login.callback_url()["http://".len()..].trim_end_matches("/callback").to_string();
+
+        let browser = async {
+            // a connection that closes without a request, and one with an oversized request line,
+            // shouldn't end the login
+            drop(TcpStream::connect(&addr).await.unwrap());
+            // the listener closes this one without reading all of it, which can reset it
+            let mut long = TcpStream::connect(&addr).await.unwrap();
+            let request = format!("GET /callback?x={} HTTP/1.1\r\n\r\n", "x".repeat(10_000));
+            let _ = long.write_all(request.as_bytes()).await;
+            let _ = long.read_to_end(&mut Vec::new()).await;
+            get(&addr, "/callback?denied=request").await
+        };
+        let (denied, result) = futures::join!(browser, twitter.run(login.finish()));
+
+        assert!(denied.starts_with("HTTP/1.1 200"));
+        assert!(matches!(result, Err(error::Error::AccessDenied)));
+    }
+}
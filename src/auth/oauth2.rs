@@ -0,0 +1,521 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The OAuth 2.0 Authorization Code flow with PKCE, for user tokens that work with API v2.
+
+use std::borrow::Cow;
+
+use chrono::{DateTime, Duration, Utc};
+use hyper::{Body, Method, Request};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::*;
+use crate::{
+    error::{self, Result},
+    links,
+};
+
+use super::raw::RequestBuilder;
+use super::{KeyPair, Token};
+
+/// The identity of an app that signs users in with OAuth 2.0.
+///
+/// The client ID (and, for confidential clients, the client secret) can be found in the "Keys and
+/// tokens" section of your app in the [Developer Portal][apps], once OAuth 2.0 has been enabled
+/// for it. The redirect URI needs to exactly match one of the callback URIs configured for your
+/// app.
+///
+/// [apps]: https://developer.twitter.com/en/portal/projects-and-apps
+///
+/// Apps that can keep a secret (like a website's backend) are "confidential clients", and should
+/// give their client secret with `with_secret`. Apps that can't (like a CLI tool or mobile app) are
+/// "public clients", and only need their client ID.
+///
+/// # Example
+///
+/// ```rust
+/// let client = egg_mode::auth::OAuth2Client::new("client id", "https://myapp.io/auth");
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuth2Client {
+    /// The client ID that identifies the app.
+    pub client_id: Cow<'static, str>,
+    /// The client secret given to confidential clients.
+    pub client_secret: Option<Cow<'static, str>>,
+    /// The URI Twitter redirects the user to after they authorize the app.
+    pub redirect_uri: Cow<'static, str>,
+}
+
+impl OAuth2Client {
+    /// Creates an `OAuth2Client` for a public client, with the given client ID and redirect URI.
+    pub fn new<I, R>(client_id: I, redirect_uri: R) -> OAuth2Client
+    where
+        I: Into<Cow<'static, str>>,
+        R: Into<Cow<'static, str>>,
+    {
+        OAuth2Client {
+            client_id: client_id.into(),
+            client_secret: None,
+            redirect_uri: redirect_uri.into(),
+        }
+    }
+
+    /// Sets the client secret, making this a confidential client.
+    pub fn with_secret<S: Into<Cow<'static, str>>>(self, client_secret: S) -> OAuth2Client {
+        OAuth2Client {
+            client_secret: Some(client_secret.into()),
+            ..self
+        }
+    }
+
+    /// Signs a request to the token endpoint with this client's credentials.
+    ///
+    /// Confidential clients authenticate with their ID and secret as HTTP Basic authentication.
+    /// Public clients give their ID in the request body instead, which needs to be in `params`.
+    fn request(&self, params: &ParamList) -> Request<Body> {
+        let request =
+            RequestBuilder::new(Method::POST, links::auth::OAUTH2_TOKEN).with_body_params(params);
+        match self.client_secret {
+            Some(ref secret) => request
+                .request_consumer_bearer(&KeyPair::new(self.client_id.clone(), secret.clone())),
+            None => request.request_unauthenticated(),
+        }
+    }
+}
+
+field_enum! {
+    /// The permissions an app can ask a user for when authorizing with OAuth 2.0.
+    ///
+    /// Each API v2 endpoint lists the scopes it requires in its documentation. Note that a token
+    /// will only come with a refresh token if `OfflineAccess` was requested.
+    pub enum Scope {
+        /// See tweets, including those from protected accounts the user follows.
+        TweetRead => "tweet.read",
+        /// Post and delete tweets for the user.
+        TweetWrite => "tweet.write",
+        /// Hide and unhide replies to the user's tweets.
+        TweetModerateWrite => "tweet.moderate.write",
+        /// See any account the user can see, including protected accounts.
+        UsersRead => "users.read",
+        /// See who the user follows and who follows them.
+        FollowsRead => "follows.read",
+        /// Follow and unfollow accounts for the user.
+        FollowsWrite => "follows.write",
+        /// Stay connected to the user's account until they revoke access, by receiving a refresh
+        /// token.
+        OfflineAccess => "offline.access",
+        /// See Spaces the user can see.
+        SpaceRead => "space.read",
+        /// See the accounts the user has muted.
+        MuteRead => "mute.read",
+        /// Mute and unmute accounts for the user.
+        MuteWrite => "mute.write",
+        /// See the tweets the user has liked, and who liked tweets the user can see.
+        LikeRead => "like.read",
+        /// Like and unlike tweets for the user.
+        LikeWrite => "like.write",
+        /// See lists the user can see, including their private lists.
+        ListRead => "list.read",
+        /// Create and manage lists for the user.
+        ListWrite => "list.write",
+        /// See the accounts the user has blocked.
+        BlockRead => "block.read",
+        /// Block and unblock accounts for the user.
+        BlockWrite => "block.write",
+        /// See the user's bookmarks.
+        BookmarkRead => "bookmark.read",
+        /// Bookmark and remove bookmarks from tweets for the user.
+        BookmarkWrite => "bookmark.write",
+    }
+}
+
+/// The secret that ties an OAuth 2.0 authorization request to the token request that completes it.
+///
+/// This is the "code verifier" from [PKCE] (Proof Key for Code Exchange). A hash of it is sent
+/// along with the [authorize URL][], and the verifier itself is sent when [exchanging the
+/// code][access] for a token, which proves that both requests came from the same place.
+///
+/// [PKCE]: https://datatracker.ietf.org/doc/html/rfc7636
+/// [authorize URL]: fn.oauth2_authorize_url.html
+/// [access]: fn.oauth2_access_token.html
+///
+/// A new verifier should be generated for every authorization attempt. If the user is redirected
+/// to a different process than the one that sent them to Twitter, the verifier can be saved with
+/// `Serialize` or `as_str`, and restored with `Deserialize` or `From<String>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PkceVerifier(String);
+
+impl PkceVerifier {
+    /// Generates a new random verifier.
+    pub fn generate() -> PkceVerifier {
+        let verifier = rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(64)
+            .map(char::from)
+            .collect();
+        PkceVerifier(verifier)
+    }
+
+    /// Returns the text of the verifier.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the "S256" code challenge for this verifier: the SHA-256 hash of the verifier,
+    /// encoded as URL-safe base64 without padding.
+    pub fn challenge(&self) -> String {
+        let hash = Sha256::digest(self.0.as_bytes());
+        base64::encode_config(hash, base64::URL_SAFE_NO_PAD)
+    }
+}
+
+impl From<String> for PkceVerifier {
+    fn from(verifier: String) -> PkceVerifier {
+        PkceVerifier(verifier)
+    }
+}
+
+/// The credentials given to an app when a user authorizes it with OAuth 2.0.
+///
+/// This is held by the [`Token::OAuth2`] variant. Unlike an OAuth 1.0a Access token, the access
+/// token here expires (two hours after it's issued, at the time of this writing). If the
+/// `OfflineAccess` scope was requested, it comes with a refresh token that can be given to
+/// [`oauth2_refresh_token`] to get a new one.
+///
+/// [`Token::OAuth2`]: enum.Token.html#variant.OAuth2
+/// [`oauth2_refresh_token`]: fn.oauth2_refresh_token.html
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuth2Token {
+    /// The token given as Bearer authorization on each request.
+    pub access_token: String,
+    /// The token that can be exchanged for a new access token, if the `OfflineAccess` scope was
+    /// granted.
+    pub refresh_token: Option<String>,
+    /// When the access token expires, if Twitter said.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The scopes the user granted to the app.
+    pub scopes: Vec<Scope>,
+    /// Identifies the series of refreshes this token belongs to.
+    ///
+    /// Each token refreshed from this one carries the same lineage, so egg-mode can keep tracking
+    /// the user's rate limits across refreshes. If this is `None`, the token is the first of its
+    /// lineage, which is then named after it.
+    #[serde(default)]
+    pub lineage: Option<String>,
+}
+
+impl OAuth2Token {
+    /// Returns the name of this token's lineage, for keying its rate limits.
+    ///
+    /// Unlike the `lineage` field, this is never empty: the first token of a lineage is named
+    /// after a fingerprint of its access token.
+    pub(crate) fn lineage_key(&self) -> String {
+        self.lineage
+            .clone()
+            .unwrap_or_else(|| super::raw::fingerprint(&self.access_token))
+    }
+
+    /// Returns whether the access token has expired.
+    pub fn is_expired(&self) -> bool {
+        matches!(self.expires_at, Some(at) if at <= Utc::now())
+    }
+}
+
+/// The response from the `POST 2/oauth2/token` endpoint.
+#[derive(Deserialize)]
+struct RawOAuth2Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+    #[serde(default)]
+    scope: String,
+}
+
+impl From<RawOAuth2Token> for OAuth2Token {
+    fn from(raw: RawOAuth2Token) -> OAuth2Token {
+        OAuth2Token {
+            access_token: raw.access_token,
+            refresh_token: raw.refresh_token,
+            expires_at: raw.expires_in.map(|secs| Utc::now() + Duration::seconds(secs)),
+            // scopes egg-mode doesn't know about yet are left out
+            scopes: raw.scope.split(' ').filter_map(|s| s.parse().ok()).collect(),
+            lineage: None,
+        }
+    }
+}
+
+/// With the given client, scopes, and verifier, return a URL that a user can access to authorize
+/// the app with OAuth 2.0.
+///
+/// # OAuth 2.0 Authentication
+///
+/// [Authentication overview](index.html#oauth-20-user-tokens)
+///
+/// 1. **Authorize**: Authenticate the user
+/// 2. [Access Token]: Exchange the authorization code for a token
+/// 3. [Refresh Token]: Get a new token once the old one expires
+///
+/// [Access Token]: fn.oauth2_access_token.html
+/// [Refresh Token]: fn.oauth2_refresh_token.html
+///
+/// # Authorize: Authenticate the user
+///
+/// When the user loads this URL, they can sign in with Twitter and grant your app the given
+/// `scopes` on their account. If they do, Twitter redirects them to the client's `redirect_uri`,
+/// with two query string parameters added: `code`, which can be given to [`oauth2_access_token`]
+/// to get a token, and `state`, which holds the `state` given here. Your app should check that the
+/// `state` matches the one it sent, to make sure the redirect came from an authorization it
+/// started. If the user denies the request, the redirect has an `error` parameter instead.
+///
+/// [`oauth2_access_token`]: fn.oauth2_access_token.html
+///
+/// The `verifier` should be a fresh [`PkceVerifier`], which needs to be kept until the code is
+/// exchanged for a token.
+///
+/// [`PkceVerifier`]: struct.PkceVerifier.html
+pub fn oauth2_authorize_url(
+    client: &OAuth2Client,
+    scopes: impl IntoIterator<Item = Scope>,
+    state: &str,
+    verifier: &PkceVerifier,
+) -> String {
+    let scopes = scopes
+        .into_iter()
+        .map(Scope::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "{}?response_type=code&client_id={}&redirect_uri={}&scope={}&state={}\
+         &code_challenge={}&code_challenge_method=S256",
+        links::resolve(links::auth::OAUTH2_AUTHORIZE),
+        percent_encode(&client.client_id),
+        percent_encode(&client.redirect_uri),
+        percent_encode(&scopes),
+        percent_encode(state),
+        verifier.challenge(),
+    )
+}
+
+/// With the given client, verifier, and authorization code, ask Twitter for an OAuth 2.0 token
+/// that can be used to sign further requests to the Twitter API.
+///
+/// # OAuth 2.0 Authentication
+///
+/// [Authentication overview](index.html#oauth-20-user-tokens)
+///
+/// 1. [Authorize]: Authenticate the user
+/// 2. **Access Token**: Exchange the authorization code for a token
+/// 3. [Refresh Token]: Get a new token once the old one expires
+///
+/// [Authorize]: fn.oauth2_authorize_url.html
+/// [Refresh Token]: fn.oauth2_refresh_token.html
+///
+/// # Access Token: Exchange the authorization code for a token
+///
+/// The `code` is the query string parameter Twitter added when redirecting the user back to your
+/// app, and the `verifier` needs to be the same one given to [`oauth2_authorize_url`]. Codes are
+/// only valid for a short time (30 seconds, at the time of this writing), so this should be
+/// called as soon as the user is redirected.
+///
+/// [`oauth2_authorize_url`]: fn.oauth2_authorize_url.html
+///
+/// On success, this returns a [`Token::OAuth2`].
+///
+/// [`Token::OAuth2`]: enum.Token.html#variant.OAuth2
+pub async fn oauth2_access_token<S: Into<String>>(
+    client: &OAuth2Client,
+    verifier: &PkceVerifier,
+    code: S,
+) -> Result<Token> {
+    let params = ParamList::new()
+        .add_param("grant_type", "authorization_code")
+        .add_param("code", code.into())
+        .add_param("redirect_uri", client.redirect_uri.clone())
+        .add_param("code_verifier", verifier.as_str().to_string())
+        .add_param("client_id", client.client_id.clone());
+
+    let token = request_with_json_response::<RawOAuth2Token>(client.request(&params)).await?;
+    Ok(Token::OAuth2(token.response.into()))
+}
+
+/// With the given client and OAuth 2.0 token, ask Twitter for a new token to replace it.
+///
+/// # OAuth 2.0 Authentication
+///
+/// [Authentication overview](index.html#oauth-20-user-tokens)
+///
+/// 1. [Authorize]: Authenticate the user
+/// 2. [Access Token]: Exchange the authorization code for a token
+/// 3. **Refresh Token**: Get a new token once the old one expires
+///
+/// [Authorize]: fn.oauth2_authorize_url.html
+/// [Access Token]: fn.oauth2_access_token.html
+///
+/// # Refresh Token: Get a new token once the old one expires
+///
+/// OAuth 2.0 access tokens expire, but if the user granted the `OfflineAccess` scope, the token
+/// comes with a refresh token that can be exchanged for a new one without involving the user.
+/// Refresh tokens can only be used once: Twitter issues a new refresh token with every new access
+/// token, so make sure to save the returned `Token` in place of the old one.
+///
+/// If the given `Token` is a `Token::Shared`, the token it currently holds is refreshed, but the
+/// handle itself isn't updated; use [`SharedToken::refresh`] for that.
+///
+/// [`SharedToken::refresh`]: struct.SharedToken.html#method.refresh
+///
+/// If the given `Token` is not a `Token::OAuth2` or `Token::Shared`, or it doesn't have a refresh
+/// token, this returns `Error::MissingValue("refresh_token")`.
+pub async fn oauth2_refresh_token(client: &OAuth2Client, token: &Token) -> Result<Token> {
+    match *token {
+        Token::OAuth2(ref token) => Ok(Token::OAuth2(refresh(client, token).await?)),
+        Token::Shared(ref shared) => Ok(Token::OAuth2(refresh(client, &shared.current()).await?)),
+        _ => Err(error::Error::MissingValue("refresh_token")),
+    }
+}
+
+/// Exchanges the refresh token in the given `OAuth2Token` for a new token.
+pub(crate) async fn refresh(client: &OAuth2Client, token: &OAuth2Token) -> Result<OAuth2Token> {
+    let refresh_token = token
+        .refresh_token
+        .as_ref()
+        .ok_or(error::Error::MissingValue("refresh_token"))?;
+
+    let params = ParamList::new()
+        .add_param("grant_type", "refresh_token")
+        .add_param("refresh_token", refresh_token.clone())
+        .add_param("client_id", client.client_id.clone());
+
+    let new_token = request_with_json_response::<RawOAuth2Token>(client.request(&params)).await?;
+    let mut new_token = OAuth2Token::from(new_token.response);
+    if new_token.refresh_token.is_none() {
+        new_token.refresh_token = Some(refresh_token.clone());
+    }
+    new_token.lineage = Some(token.lineage_key());
+    Ok(new_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::raw::RateLimitKey;
+    use crate::testing::MockTwitter;
+
+    use hyper::StatusCode;
+
+    #[test]
+    fn pkce_challenge() {
+        // the example from RFC 7636, Appendix B
+        let verifier = PkceVerifier::from("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        assert_eq!(verifier.challenge(), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+
+        let generated = PkceVerifier::generate();
+        assert_eq!(generated.as_str().len(), 64);
+        assert_ne!(generated, PkceVerifier::generate());
+    }
+
+    #[test]
+    fn parse_scopes() {
+        assert_eq!("offline.access".parse::<Scope>(), Ok(Scope::OfflineAccess));
+        assert_eq!(
+            serde_json::to_string(&Scope::TweetModerateWrite).unwrap(),
+            "\"tweet.moderate.write\""
+        );
+
+        let err = "tweet.delete".parse::<Scope>().unwrap_err();
+        assert_eq!(err.kind, "Scope");
+        assert_eq!(err.value, "tweet.delete");
+    }
+
+    #[test]
+    fn authorize_url() {
+        let client = OAuth2Client::new("client id", "http://127.0.0.1:8080/callback");
+        let verifier = PkceVerifier::from("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        let url = oauth2_authorize_url(
+            &client,
+            [Scope::TweetRead, Scope::OfflineAccess],
+            "state",
+            &verifier,
+        );
+
+        assert_eq!(
+            url,
+            "https://twitter.com/i/oauth2/authorize?response_type=code&client_id=client%20id\
+             &redirect_uri=http%3A%2F%2F127.0.0.1%3A8080%2Fcallback\
+             &scope=tweet.read%20offline.access&state=state\
+             &code_challenge=E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM\
+             &code_challenge_method=S256"
+        );
+    }
+
+    #[tokio::test]
+    async fn exchange_and_refresh() {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::POST,
+            "/2/oauth2/token",
+            StatusCode::OK,
+            r#"{"token_type":"bearer","expires_in":7200,"access_token":"access","scope":"tweet.read offline.access","refresh_token":"refresh"}"#,
+        );
+
+        let client = OAuth2Client::new("client", "https://myapp.io/auth");
+        let verifier = PkceVerifier::generate();
+        let token = twitter
+            .run(oauth2_access_token(&client, &verifier, "code"))
+            .await
+            .unwrap();
+        match token {
+            Token::OAuth2(ref token) => {
+                assert_eq!(token.access_token, "access");
+                assert_eq!(token.refresh_token.as_deref(), Some("refresh"));
+                assert_eq!(token.scopes, [Scope::TweetRead, Scope::OfflineAccess]);
+                assert!(!token.is_expired());
+            }
+            ref token => panic!("Not an OAuth 2.0 token: {:?}", token),
+        }
+
+        let client = client.with_secret("secret");
+        let refreshed = twitter
+            .run(oauth2_refresh_token(&client, &token))
+            .await
+            .unwrap();
+        // the refreshed token still counts against the same rate limits
+        assert_eq!(RateLimitKey::new(&refreshed), RateLimitKey::new(&token));
+        assert_ne!(RateLimitKey::new(&token), RateLimitKey::new(&Token::Bearer("access".into())));
+        let bearer = Token::Bearer("bearer".to_string());
+        assert!(matches!(
+            twitter.run(oauth2_refresh_token(&client, &bearer)).await,
+            Err(error::Error::MissingValue("refresh_token"))
+        ));
+
+        let requests = twitter.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].params["grant_type"], "authorization_code");
+        assert_eq!(requests[0].params["code"], "code");
+        assert_eq!(requests[0].params["code_verifier"], verifier.as_str());
+        assert_eq!(requests[0].params["redirect_uri"], "https://myapp.io/auth");
+        assert_eq!(requests[1].params["grant_type"], "refresh_token");
+        assert_eq!(requests[1].params["refresh_token"], "refresh");
+    }
+
+    #[test]
+    fn sign_with_oauth2_token() {
+        let token = Token::OAuth2(OAuth2Token {
+            access_token: "access".to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: vec![Scope::TweetRead],
+            lineage: None,
+        });
+        let request = get(links::v2::TWEETS, &token, None);
+        assert_eq!(request.headers()[hyper::header::AUTHORIZATION], "Bearer access");
+
+        let client = OAuth2Client::new("client", "https://myapp.io/auth");
+        let request = client.request(&ParamList::new());
+        assert!(!request.headers().contains_key(hyper::header::AUTHORIZATION));
+    }
+}
@@ -0,0 +1,313 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A pool of tokens that spreads requests across accounts according to their rate limits.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::service::{self, Method};
+
+use super::Token;
+
+/// A set of tokens that each request is signed with the best of, according to their rate limits.
+///
+/// Twitter's rate limits apply to each user (or to each app, for Bearer tokens), so a program that
+/// makes a lot of calls can go further by spreading them across several accounts. A `TokenPool`
+/// does this for you: when a request is signed with a `Token::Pool` (given by the `token` method),
+/// the pool picks the token with the most calls remaining for the method being called, as recorded
+/// by the process-wide [`RateLimitTracker`]. Tokens that have run out of calls are set aside until
+/// their rate-limit window resets. Tokens the tracker hasn't seen yet are assumed to have calls
+/// remaining, and ties are broken by taking turns.
+///
+/// [`RateLimitTracker`]: ../service/struct.RateLimitTracker.html
+///
+/// Since types like `CursorIter` and `Timeline` sign each page's request separately, giving them a
+/// `Token::Pool` spreads a long pagination run across the pool. Note that the pool only knows the
+/// rate limits for methods listed in the `service` module's `*Method` enums; requests to other
+/// endpoints (like those in the `v2` module) simply take turns through the pool.
+///
+/// Every token in the pool should be able to make the calls you use it for. In particular, calls
+/// that need a user context won't work with Bearer tokens, and calls that act on "the
+/// authenticated user" (like `tweet::home_timeline`) will act on whichever user each request
+/// happens to be signed with.
+///
+/// If every token in the pool has run out of calls for a method, the one whose window resets
+/// first is used. To wait for it instead of getting `Error::RateLimit` back, set a
+/// [`throttle`] or a [`RetryPolicy`].
+///
+/// [`throttle`]: ../service/fn.set_throttle.html
+/// [`RetryPolicy`]: ../raw/struct.RetryPolicy.html
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// use egg_mode::auth::TokenPool;
+/// # let (token1, token2): (egg_mode::Token, egg_mode::Token) = unimplemented!();
+///
+/// let pool = TokenPool::new(vec![token1, token2]);
+/// let token = pool.token();
+///
+/// let mut timeline = egg_mode::tweet::user_timeline("rustlang", false, true, &token);
+/// for _ in 0..10 {
+///     let (next, _feed) = timeline.older(None).await.unwrap();
+///     timeline = next;
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TokenPool {
+    inner: Arc<TokenPoolInner>,
+}
+
+#[derive(Debug)]
+struct TokenPoolInner {
+    tokens: Vec<Token>,
+    /// The index to start looking from for the next pick, so that ties take turns.
+    next: AtomicUsize,
+}
+
+impl TokenPool {
+    /// Creates a new `TokenPool` with the given tokens.
+    ///
+    /// # Panics
+    ///
+    /// If no tokens are given, this function will panic.
+    pub fn new<I: IntoIterator<Item = Token>>(tokens: I) -> TokenPool {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        assert!(!tokens.is_empty(), "TokenPool::new given no tokens");
+
+        TokenPool {
+            inner: Arc::new(TokenPoolInner {
+                tokens,
+                next: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns a `Token` that refers to this `TokenPool`, to give to egg-mode functions.
+    pub fn token(&self) -> Token {
+        Token::Pool(self.clone())
+    }
+
+    /// Returns the tokens in this pool.
+    pub fn tokens(&self) -> &[Token] {
+        &self.inner.tokens
+    }
+
+    /// Returns the token in this pool that should be used to call the given method next.
+    pub fn pick(&self, method: impl Into<Method>) -> Token {
+        self.pick_for(Some(method.into()))
+    }
+
+    /// Returns when the given method can next be called with any token in this pool, as a UTC Unix
+    /// timestamp, or `None` if it can be called right now.
+    pub fn available_at(&self, method: impl Into<Method>) -> Option<i32> {
+        let tracker = service::rate_limit_tracker();
+        let method = method.into();
+        self.inner
+            .tokens
+            .iter()
+            .map(|token| tracker.available_at(method, token))
+            .min()
+            .flatten()
+    }
+
+    /// Returns the token that should be used to call the given method next, or the next token in
+    /// turn if the method isn't known.
+    pub(crate) fn pick_for(&self, method: Option<Method>) -> Token {
+        let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        self.pick_from(start, method)
+    }
+
+    /// Returns the token `pick_for` would return right now, without taking a turn.
+    pub(crate) fn peek_for(&self, method: Option<Method>) -> Token {
+        self.pick_from(self.inner.next.load(Ordering::Relaxed), method)
+    }
+
+    /// Returns the token that should be used to call the given method, breaking ties in favor of
+    /// the token in the given turn.
+    fn pick_from(&self, turn: usize, method: Option<Method>) -> Token {
+        let tokens = &self.inner.tokens;
+        let start = turn % tokens.len();
+        let method = match method {
+            Some(method) => method,
+            None => return tokens[start].clone(),
+        };
+
+        let tracker = service::rate_limit_tracker();
+        let now = chrono::Utc::now().timestamp();
+        // the token with the most calls remaining, and the parked token that resets first
+        let mut best: Option<(usize, i32)> = None;
+        let mut soonest: Option<(usize, i32)> = None;
+        for idx in (start..tokens.len()).chain(0..start) {
+            let remaining = match tracker.get(method, &tokens[idx]) {
+                Some(limit) if i64::from(limit.reset) > now => {
+                    if limit.remaining <= 0 {
+                        if !matches!(soonest, Some((_, reset)) if reset <= limit.reset) {
+                            soonest = Some((idx, limit.reset));
+                        }
+                        continue;
+                    }
+                    limit.remaining
+                }
+                // the token hasn't been used for this method, or its window has reset
+                _ => i32::MAX,
+            };
+            if !matches!(best, Some((_, most)) if most >= remaining) {
+                best = Some((idx, remaining));
+            }
+        }
+
+        let (idx, _) = best.or(soonest).unwrap_or((start, 0));
+        tokens[idx].clone()
+    }
+}
+
+/// A `TokenPool` is serialized as the list of its tokens.
+impl Serialize for TokenPool {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        self.inner.tokens.serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenPool {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let tokens = Vec::<Token>::deserialize(de)?;
+        if tokens.is_empty() {
+            return Err(D::Error::custom("a TokenPool needs at least one token"));
+        }
+        Ok(TokenPool::new(tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::AUTHORIZATION;
+
+    use super::*;
+    use crate::auth::raw::RateLimitKey;
+    use crate::common::{with_retry_policy, RateLimit, RetryPolicy};
+    use crate::service::TweetMethod;
+    use crate::testing::MockTwitter;
+
+    fn pool(name: &str) -> TokenPool {
+        TokenPool::new(vec![
+            Token::Bearer(format!("{}-a", name)),
+            Token::Bearer(format!("{}-b", name)),
+        ])
+    }
+
+    #[test]
+    fn picks_most_remaining() {
+        let pool = pool("pool-pick");
+        let tracker = service::rate_limit_tracker();
+        let reset = chrono::Utc::now().timestamp() as i32 + 900;
+        let limit = |remaining| RateLimit {
+            limit: 900,
+            remaining,
+            reset,
+        };
+
+        // unknown tokens take turns
+        let first = pool.pick(TweetMethod::Show);
+        assert_ne!(format!("{:?}", first), format!("{:?}", pool.pick(TweetMethod::Show)));
+
+        tracker.record(TweetMethod::Show, &pool.tokens()[0], limit(5));
+        tracker.record(TweetMethod::Show, &pool.tokens()[1], limit(50));
+        for _ in 0..3 {
+            assert!(matches!(pool.pick(TweetMethod::Show), Token::Bearer(b) if b.ends_with("-b")));
+        }
+        assert_eq!(pool.available_at(TweetMethod::Show), None);
+
+        tracker.record(TweetMethod::Show, &pool.tokens()[1], limit(0));
+        assert!(matches!(pool.pick(TweetMethod::Show), Token::Bearer(b) if b.ends_with("-a")));
+
+        tracker.record(TweetMethod::Show, &pool.tokens()[0], limit(0));
+        assert_eq!(pool.available_at(TweetMethod::Show), Some(reset));
+    }
+
+    #[tokio::test]
+    async fn spreads_pagination() {
+        let pool = pool("pool-page");
+        let twitter = MockTwitter::new();
+        let timeline_path = "/1.1/statuses/user_timeline.json";
+        twitter.set_remaining_for(&pool.tokens()[0], timeline_path, 3);
+        twitter.set_remaining_for(&pool.tokens()[1], timeline_path, 5);
+        let token = pool.token();
+
+        twitter
+            .run(async {
+                let mut timeline = crate::tweet::user_timeline(783214, false, true, &token);
+                for _ in 0..4 {
+                    let (next, _) = timeline.older(None).await.unwrap();
+                    timeline = next;
+                }
+
+                let ids = crate::user::followers_ids(783214, &token);
+                let page = ids.call().await.unwrap();
+                assert_eq!(page.response.ids.len(), 4);
+            })
+            .await;
+
+        // a and b take turns until b has more calls left, then b takes over the timeline;
+        // followers/ids has its own rate limit, so the pool starts over with a
+        let calls = twitter
+            .requests()
+            .iter()
+            .map(|r| {
+                let auth = r.headers[AUTHORIZATION].to_str().unwrap();
+                auth["Bearer pool-page-".len()..].to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(calls, ["a", "b", "b", "b", "a"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_with_another_token() {
+        let pool = pool("pool-retry");
+        let twitter = MockTwitter::new();
+        twitter.set_remaining_for(&pool.tokens()[0], "/1.1/users/show.json", 0);
+        let token = pool.token();
+
+        let user = twitter
+            .run(with_retry_policy(
+                Some(RetryPolicy::new()),
+                crate::user::show(783214, &token),
+            ))
+            .await;
+        assert!(user.is_ok());
+
+        // a ran out of calls, so the retry goes out with b instead of waiting for a to reset
+        let calls = twitter
+            .requests()
+            .iter()
+            .map(|r| r.headers[AUTHORIZATION].to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(calls, ["Bearer pool-retry-a", "Bearer pool-retry-b"]);
+        // pools are keyed by their members, not by where they live in memory
+        let same_members = TokenPool::new(pool.tokens().to_vec()).token();
+        assert!(RateLimitKey::new(&token) == RateLimitKey::new(&same_members));
+    }
+
+    #[test]
+    fn round_trip_pool() {
+        let pool = TokenPool::new(vec![
+            Token::Bearer("one".to_string()),
+            Token::Bearer("two".to_string()),
+        ]);
+        let json = serde_json::to_string(&pool.token()).unwrap();
+        match serde_json::from_str(&json).unwrap() {
+            Token::Pool(pool) => assert_eq!(pool.tokens().len(), 2),
+            token => panic!("unexpected token: {:?}", token),
+        }
+
+        assert!(serde_json::from_str::<TokenPool>("[]").is_err());
+    }
+}
@@ -6,19 +6,22 @@
 
 use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use base64;
 use hmac::{Hmac, Mac, NewMac};
-use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use hyper::{Body, Method, Request};
 use rand::{self, Rng};
 use sha1::Sha1;
+use sha2::{Digest, Sha256};
 
 use crate::common::*;
+use crate::links;
 
-use super::{Token, KeyPair};
+use super::{KeyPair, SharedToken, Token, TokenPool};
 
 // n.b. this type is exported in `raw::auth` - these docs are public!
 /// Builder struct to assemble and sign an API request.
@@ -146,10 +149,17 @@ impl<'a> RequestBuilder<'a> {
     /// request token; all other calls must have two sets of keys (or be authenticated in a
     /// different way, i.e. a Bearer token).
     pub fn request_keys(self, consumer_key: &KeyPair, token: Option<&KeyPair>) -> Request<Body> {
-        let oauth = OAuthParams::from_keys(consumer_key.clone(), token.cloned())
-            .with_addon(self.addon.clone())
-            .sign_request(self.method.clone(), self.base_uri, self.params.as_ref());
-        self.request_authorization(oauth.to_string())
+        let resign = Resign {
+            consumer_key: consumer_key.clone(),
+            token: token.cloned(),
+            addon: self.addon.clone(),
+            method: self.method.clone(),
+            uri: self.base_uri.to_string(),
+            params: self.params.clone(),
+        };
+        let mut request = self.request_authorization(resign.authorization());
+        request.extensions_mut().insert(resign);
+        request
     }
 
     /// Formats this `RequestBuilder` into a complete `Request`, signing it with the given token.
@@ -159,11 +169,27 @@ impl<'a> RequestBuilder<'a> {
     ///
     /// If the given `Token` is a Bearer token, the request will be authenticated using OAuth 2.0,
     /// specifying the given Bearer token as authorization.
+    ///
+    /// If the given `Token` is an OAuth 2.0 user token, its access token will be given as Bearer
+    /// authorization the same way. If it's a shared token, its current access token is used, and
+    /// the request will refresh the token if needed when it's sent with `response_raw_bytes` (or
+    /// the other `response_*` functions).
+    ///
+    /// If the given `Token` is a pool of tokens, the request is signed with the token the pool
+    /// would pick for the endpoint being called. When the request is sent with
+    /// `response_raw_bytes` (or the other `response_*` functions), the pool picks a token again,
+    /// and again for each retry, so the request goes out with whichever token has the most calls
+    /// left at that point.
     pub fn request_token(self, token: &Token) -> Request<Body> {
-        match token {
-            Token::Access { consumer, access } => self.request_keys(consumer, Some(access)),
-            Token::Bearer(bearer) => self.request_authorization(format!("Bearer {}", bearer)),
-        }
+        let signer = Signer {
+            addon: self.addon.clone(),
+            method: self.method.clone(),
+            uri: self.base_uri.to_string(),
+            params: self.params.clone(),
+        };
+        let mut request = self.build(None);
+        signer.sign(&mut request, token);
+        request
     }
 
     /// Formats this `RequestBuilder` into a complete `Request`, with an Authorization header
@@ -181,19 +207,37 @@ impl<'a> RequestBuilder<'a> {
         self.request_authorization(bearer_request(consumer_key))
     }
 
-    /// Assembles the final `Request` with the given Authorization header. This is private to
-    /// require that a well-formed header is constructed given, as constructed from the other
-    /// `request_*` methods.
+    /// Formats this `RequestBuilder` into a complete `Request` without an Authorization header.
+    ///
+    /// This is only useful for endpoints that identify the caller some other way, like the `POST
+    /// 2/oauth2/token` endpoint when called by a public OAuth 2.0 client, which gives its client
+    /// ID as part of the request body.
+    pub fn request_unauthenticated(self) -> Request<Body> {
+        self.build(None)
+    }
+
+    /// Assembles the final `Request` with the given Authorization header.
     fn request_authorization(self, authorization: String) -> Request<Body> {
+        self.build(Some(authorization))
+    }
+
+    /// Assembles the final `Request`, with the given Authorization header if present. This is
+    /// private to require that a well-formed header is constructed given, as constructed from the
+    /// other `request_*` methods.
+    ///
+    /// The base URL is resolved against the configured `BaseUrls` here, after the OAuth signature
+    /// has been made against the original URL.
+    fn build(self, authorization: Option<String>) -> Request<Body> {
+        let base_uri = links::resolve(self.base_uri);
         let full_url = if let Some(query) = self.query {
-            format!("{}?{}", self.base_uri, query)
+            format!("{}?{}", base_uri, query)
         } else {
-            self.base_uri.to_string()
+            base_uri.into_owned()
         };
-        let request = Request::builder()
-            .method(self.method)
-            .uri(full_url)
-            .header(AUTHORIZATION, authorization);
+        let mut request = Request::builder().method(self.method).uri(full_url);
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
 
         if let Some((body, content)) = self.body {
             request.header(CONTENT_TYPE, content)
@@ -204,6 +248,168 @@ impl<'a> RequestBuilder<'a> {
     }
 }
 
+/// The information needed to sign a request again, with a fresh nonce and timestamp.
+///
+/// This is attached to the extensions of every request signed with OAuth 1.0a, so that a request
+/// that gets retried long after it was first signed (for example, after waiting for a rate limit
+/// to reset) isn't rejected by Twitter for carrying a stale timestamp.
+#[derive(Clone, Debug)]
+pub(crate) struct Resign {
+    consumer_key: KeyPair,
+    token: Option<KeyPair>,
+    addon: OAuthAddOn,
+    method: Method,
+    uri: String,
+    params: Option<ParamList>,
+}
+
+impl Resign {
+    /// Creates a new signature for the request, returning it as an Authorization header.
+    pub(crate) fn authorization(&self) -> String {
+        OAuthParams::from_keys(self.consumer_key.clone(), self.token.clone())
+            .with_addon(self.addon.clone())
+            .sign_request(self.method.clone(), &self.uri, self.params.as_ref())
+            .to_string()
+    }
+}
+
+/// The parts of a request that go into its signature, so it can be signed with a `Token` after
+/// it's been built.
+#[derive(Clone, Debug)]
+struct Signer {
+    addon: OAuthAddOn,
+    method: Method,
+    uri: String,
+    params: Option<ParamList>,
+}
+
+impl Signer {
+    /// Signs the given request with the given token.
+    ///
+    /// A request for a pool is signed with the token the pool would pick right now, and carries a
+    /// `PoolSigner` so the pool can pick again when the request is sent.
+    fn sign(&self, request: &mut Request<Body>, token: &Token) {
+        match token {
+            Token::Pool(pool) => {
+                let method = crate::service::Method::from_path(&self.uri);
+                self.sign_with(request, &pool.peek_for(method));
+                request.extensions_mut().insert(PoolSigner {
+                    pool: pool.clone(),
+                    signer: self.clone(),
+                });
+            }
+            token => self.sign_with(request, token),
+        }
+    }
+
+    /// Signs the given request with the given token, replacing whatever it was signed with before.
+    fn sign_with(&self, request: &mut Request<Body>, token: &Token) {
+        request.extensions_mut().remove::<Resign>();
+        request.extensions_mut().remove::<SharedToken>();
+        let authorization = match token {
+            Token::Access { consumer, access } => {
+                let resign = Resign {
+                    consumer_key: consumer.clone(),
+                    token: Some(access.clone()),
+                    addon: self.addon.clone(),
+                    method: self.method.clone(),
+                    uri: self.uri.clone(),
+                    params: self.params.clone(),
+                };
+                let authorization = resign.authorization();
+                request.extensions_mut().insert(resign);
+                authorization
+            }
+            Token::Bearer(bearer) => format!("Bearer {}", bearer),
+            Token::OAuth2(token) => format!("Bearer {}", token.access_token),
+            Token::Shared(shared) => {
+                request.extensions_mut().insert(shared.clone());
+                format!("Bearer {}", shared.current().access_token)
+            }
+            // a pool inside a pool picks its token as soon as the outer one picks it
+            Token::Pool(pool) => {
+                let method = crate::service::Method::from_path(&self.uri);
+                return self.sign_with(request, &pool.pick_for(method));
+            }
+        };
+        if let Ok(authorization) = HeaderValue::try_from(authorization) {
+            request.headers_mut().insert(AUTHORIZATION, authorization);
+        }
+        request.extensions_mut().insert(RateLimitKey::new(token));
+    }
+}
+
+/// The information needed to sign a request with a token from a pool, picked when it's sent.
+///
+/// This is attached to the extensions of every request signed with a `Token::Pool`, so that a
+/// request that gets retried after its token ran out of calls is sent again with a token that has
+/// calls left, instead of the same one.
+#[derive(Clone, Debug)]
+pub(crate) struct PoolSigner {
+    pool: TokenPool,
+    signer: Signer,
+}
+
+impl PoolSigner {
+    /// Signs the given request with the token the pool picks for it now.
+    pub(crate) fn sign(&self, request: &mut Request<Body>) {
+        let method = crate::service::Method::from_path(&self.signer.uri);
+        self.signer.sign_with(request, &self.pool.pick_for(method));
+    }
+}
+
+/// Identifies whose rate limits a request counts against.
+///
+/// This is attached to the extensions of every request signed with a `Token`, so that rate limits
+/// can be tracked separately for each user (or for each app, when using a Bearer token).
+///
+/// Since the tracker can be printed with `Debug`, secret tokens are only stored as a fingerprint.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct RateLimitKey(String);
+
+impl RateLimitKey {
+    pub(crate) fn new(token: &Token) -> RateLimitKey {
+        match token {
+            // the consumer and access keys identify the app and user without being secret
+            Token::Access { consumer, access } => {
+                RateLimitKey(format!("{}:{}", consumer.key, access.key))
+            }
+            Token::Bearer(bearer) => RateLimitKey(format!("Bearer {}", fingerprint(bearer))),
+            // OAuth 2.0 tokens are keyed by their lineage, so the key stays the same when they're
+            // refreshed
+            Token::OAuth2(token) => RateLimitKey(format!("OAuth2 {}", token.lineage_key())),
+            Token::Shared(shared) => {
+                RateLimitKey(format!("OAuth2 {}", shared.current().lineage_key()))
+            }
+            // a pool's requests are keyed by the token they're signed with, so this is only used
+            // when asking the tracker about the pool as a whole, which it never records
+            Token::Pool(pool) => {
+                let members = pool
+                    .tokens()
+                    .iter()
+                    .map(|token| RateLimitKey::new(token).0)
+                    .collect::<Vec<_>>();
+                RateLimitKey(format!("Pool [{}]", members.join(", ")))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for RateLimitKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RateLimitKey({})", self.0)
+    }
+}
+
+/// Returns a short, non-reversible identifier for the given secret.
+pub(crate) fn fingerprint(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 /// OAuth header set used to create an OAuth signature.
 #[derive(Clone, Debug)]
 struct OAuthParams {
@@ -467,7 +673,8 @@ pub fn post_json<B: serde::Serialize>(uri: &str, token: &Token, body: B) -> Requ
 
 #[cfg(test)]
 mod tests {
-    use super::bearer_request;
+    use super::{bearer_request, RateLimitKey};
+    use crate::auth::Token;
 
     #[test]
     fn bearer_header() {
@@ -479,4 +686,14 @@ mod tests {
 
         assert_eq!(output, "Basic eHZ6MWV2RlM0d0VFUFRHRUZQSEJvZzpMOHFxOVBaeVJnNmllS0dFS2hab2xHQzB2SldMdzhpRUo4OERSZHlPZw==");
     }
+
+    #[test]
+    fn rate_limit_key_hides_secrets() {
+        let bearer = Token::Bearer("AAAAAAAAAAAAAAAAAAAAAsecretbearer".to_string());
+        let key = RateLimitKey::new(&bearer);
+
+        assert!(!format!("{:?}", key).contains("secretbearer"));
+        assert_eq!(key, RateLimitKey::new(&bearer));
+        assert_ne!(key, RateLimitKey::new(&Token::Bearer("other".to_string())));
+    }
 }
@@ -0,0 +1,372 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A shared handle to an OAuth 2.0 token, which refreshes itself when it expires.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+use hyper::header::{HeaderValue, AUTHORIZATION};
+use hyper::{Body, Request, StatusCode};
+
+use crate::common::*;
+use crate::error::{Error, Result};
+
+use super::oauth2::{self, OAuth2Client, OAuth2Token};
+use super::raw::RateLimitKey;
+use super::Token;
+
+/// Twitter error code for "Invalid or expired token".
+const INVALID_TOKEN: i32 = 89;
+
+/// A hook that is called with each token a [`SharedToken`] receives when it refreshes itself.
+///
+/// Since a refresh token can only be used once, an app that saves its OAuth 2.0 tokens needs to
+/// save the new token every time it's refreshed, or it won't be able to refresh it again after a
+/// restart. Implement this trait (or give a closure, which implements it automatically) to write
+/// the new token to your own storage.
+///
+/// [`SharedToken`]: struct.SharedToken.html
+///
+/// This is called from within the request that triggered the refresh, so it shouldn't block for
+/// long. If your storage is asynchronous, spawn a task to write to it.
+pub trait PersistToken: Send + Sync {
+    /// Saves the given token, which is always a `Token::OAuth2`.
+    fn persist(&self, token: &Token);
+}
+
+impl<F> PersistToken for F
+where
+    F: Fn(&Token) + Send + Sync,
+{
+    fn persist(&self, token: &Token) {
+        self(token)
+    }
+}
+
+/// A shared handle to an OAuth 2.0 user token, which refreshes itself when it expires.
+///
+/// OAuth 2.0 access tokens expire a couple of hours after they're issued. Since egg-mode copies
+/// the `Token` it's given into types like `Timeline` and `CursorIter`, a plain `Token::OAuth2`
+/// would need to be swapped out everywhere it was copied to once it's refreshed. Instead, a
+/// `SharedToken` can be given to egg-mode as a `Token::Shared` (with the `token` method), and
+/// every copy of it refers to the same token.
+///
+/// When a request is signed with a `Token::Shared`, egg-mode refreshes the token before sending
+/// the request if it has expired, and refreshes it and sends the request again if Twitter rejects
+/// it as unauthorized. Only one refresh is made at a time, even if several requests find out
+/// their token has expired at once. This requires the token to have a refresh token, which is
+/// only given if the user granted the `OfflineAccess` scope.
+///
+/// Streams refresh their token the same way when they connect, so a `ReconnectingStream` picks up
+/// the refreshed token each time it reconnects.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// use egg_mode::auth::{OAuth2Client, PkceVerifier, SharedToken};
+/// use egg_mode::Token;
+///
+/// let client = OAuth2Client::new("client id", "https://myapp.io/auth");
+/// # let verifier = PkceVerifier::generate();
+/// let token = egg_mode::auth::oauth2_access_token(&client, &verifier, "code").await.unwrap();
+/// let token = match token {
+///     Token::OAuth2(token) => token,
+///     _ => unreachable!(),
+/// };
+///
+/// let shared = SharedToken::new(client, token).on_refresh(|token: &Token| {
+///     // save the new token here
+/// });
+/// let token = shared.token();
+///
+/// // token can be given to the methods in the v2 module, and will be refreshed as needed
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SharedToken {
+    inner: Arc<SharedTokenInner>,
+}
+
+struct SharedTokenInner {
+    client: OAuth2Client,
+    token: RwLock<OAuth2Token>,
+    persist: RwLock<Option<Arc<dyn PersistToken>>>,
+    /// Held while a refresh is in progress, so only one refresh happens at a time.
+    refreshing: futures::lock::Mutex<()>,
+}
+
+impl SharedToken {
+    /// Creates a new `SharedToken` with the given client and token.
+    ///
+    /// The client is used to refresh the token, so it needs to be the same one that the token was
+    /// issued to.
+    pub fn new(client: OAuth2Client, token: OAuth2Token) -> SharedToken {
+        SharedToken {
+            inner: Arc::new(SharedTokenInner {
+                client,
+                token: RwLock::new(token),
+                persist: RwLock::new(None),
+                refreshing: futures::lock::Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Sets the hook that is called with each new token, replacing the previous one.
+    ///
+    /// This affects every copy of this `SharedToken`.
+    pub fn on_refresh<P: PersistToken + 'static>(self, persist: P) -> SharedToken {
+        *self.inner.persist.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(persist));
+        self
+    }
+
+    /// Returns a `Token` that refers to this `SharedToken`, to give to egg-mode functions.
+    pub fn token(&self) -> Token {
+        Token::Shared(self.clone())
+    }
+
+    /// Returns the token this `SharedToken` currently holds.
+    pub fn current(&self) -> OAuth2Token {
+        self.inner.token.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Refreshes the token now, regardless of whether it has expired.
+    pub async fn refresh(&self) -> Result<()> {
+        let stale = self.current().access_token;
+        self.refresh_from(&stale).await
+    }
+
+    /// Refreshes the token, unless it was already refreshed since the given access token was
+    /// current.
+    async fn refresh_from(&self, stale: &str) -> Result<()> {
+        let _refreshing = self.inner.refreshing.lock().await;
+        let current = self.current();
+        if current.access_token != stale {
+            return Ok(());
+        }
+
+        let new_token = oauth2::refresh(&self.inner.client, &current).await?;
+        *self.inner.token.write().unwrap_or_else(|e| e.into_inner()) = new_token.clone();
+
+        let persist = self.inner.persist.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(persist) = persist {
+            persist.persist(&Token::OAuth2(new_token));
+        }
+        Ok(())
+    }
+
+    /// Sends the given request, which was signed with this token, refreshing the token first if
+    /// it has expired, or afterward if Twitter rejects it.
+    pub(crate) async fn send(&self, request: Request<Body>) -> Result<(Headers, Vec<u8>)> {
+        self.send_with(request, send_with_policy).await
+    }
+
+    /// Opens a stream with the given request, which was signed with this token, refreshing the
+    /// token the same way as `send`.
+    pub(crate) async fn connect(&self, request: Request<Body>) -> Result<hyper::Response<Body>> {
+        self.send_with(request, |request| async move {
+            let resp = get_response(request).await?;
+            if resp.status() == StatusCode::UNAUTHORIZED {
+                return Err(Error::BadStatus(resp.status()));
+            }
+            Ok(resp)
+        })
+        .await
+    }
+
+    /// Sends the given request with the given function, signed with the current token, and
+    /// again with a refreshed token if the first one is rejected.
+    async fn send_with<T, F, Fut>(&self, request: Request<Body>, mut send: F) -> Result<T>
+    where
+        F: FnMut(Request<Body>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        // the body needs to be buffered in case the request needs to be sent again
+        let (parts, body) = request.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let key = parts.extensions.get::<RateLimitKey>();
+
+        let mut token = self.current();
+        let mut refreshed = false;
+        if token.is_expired() && token.refresh_token.is_some() {
+            self.refresh_from(&token.access_token).await?;
+            token = self.current();
+            refreshed = true;
+        }
+
+        loop {
+            let mut request = Request::new(Body::from(body.clone()));
+            *request.method_mut() = parts.method.clone();
+            *request.uri_mut() = parts.uri.clone();
+            *request.version_mut() = parts.version;
+            *request.headers_mut() = parts.headers.clone();
+            if let Some(key) = key {
+                request.extensions_mut().insert(key.clone());
+            }
+            if let Ok(auth) = HeaderValue::try_from(format!("Bearer {}", token.access_token)) {
+                request.headers_mut().insert(AUTHORIZATION, auth);
+            }
+
+            match send(request).await {
+                Err(ref err)
+                    if is_unauthorized(err) && !refreshed && token.refresh_token.is_some() =>
+                {
+                    self.refresh_from(&token.access_token).await?;
+                    token = self.current();
+                    refreshed = true;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SharedToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedToken")
+            .field("client", &self.inner.client)
+            .field("token", &self.current())
+            .finish()
+    }
+}
+
+/// Returns whether the given error means the token the request was signed with wasn't accepted.
+fn is_unauthorized(err: &Error) -> bool {
+    match err {
+        Error::BadStatus(status) => *status == StatusCode::UNAUTHORIZED,
+        Error::TwitterError(_, errors) => errors.errors.iter().any(|e| e.code == INVALID_TOKEN),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use hyper::Method;
+
+    use super::*;
+    use crate::auth::Scope;
+    use crate::common::tests::load_file;
+    use crate::testing::MockTwitter;
+
+    /// Returns a `MockTwitter` that serves tweet lookups, and no longer accepts the access token
+    /// `old`.
+    fn expiring() -> MockTwitter {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::GET,
+            "/2/tweets",
+            StatusCode::OK,
+            load_file("sample_payloads/v2-tweets-lookup.json"),
+        );
+        twitter.expire_token("old");
+        twitter
+    }
+
+    /// Returns the Authorization header of every request the given `MockTwitter` received.
+    fn auth_headers(twitter: &MockTwitter) -> Vec<String> {
+        twitter
+            .requests()
+            .iter()
+            .map(|r| {
+                let auth = r.headers.get(AUTHORIZATION);
+                auth.map_or("", |h| h.to_str().unwrap()).to_string()
+            })
+            .collect()
+    }
+
+    fn shared(expires_at: Option<chrono::DateTime<chrono::Utc>>) -> SharedToken {
+        SharedToken::new(
+            OAuth2Client::new("client", "https://myapp.io/auth"),
+            OAuth2Token {
+                access_token: "old".to_string(),
+                refresh_token: Some("refresh".to_string()),
+                expires_at,
+                scopes: vec![Scope::TweetRead, Scope::OfflineAccess],
+                lineage: None,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn refreshes_on_unauthorized() {
+        let twitter = expiring();
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let shared = {
+            let saved = saved.clone();
+            shared(None).on_refresh(move |token: &Token| saved.lock().unwrap().push(token.clone()))
+        };
+
+        let token = shared.token();
+        let fields = crate::v2::Fields::new();
+        let resp = twitter
+            .run(crate::v2::tweet::lookup([1261326399320715264], &fields, &token))
+            .await
+            .unwrap();
+        assert!(!resp.data.is_empty());
+
+        let current = shared.current();
+        assert_ne!(current.access_token, "old");
+        assert_ne!(current.refresh_token.as_deref(), Some("refresh"));
+        let auth = auth_headers(&twitter);
+        assert_eq!(auth.len(), 3);
+        assert_eq!(auth[0], "Bearer old");
+        assert_eq!(auth[2], format!("Bearer {}", current.access_token));
+
+        let saved = saved.lock().unwrap();
+        match saved[..] {
+            [Token::OAuth2(ref token)] => assert_eq!(token.access_token, current.access_token),
+            ref saved => panic!("unexpected saved tokens: {:?}", saved),
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshes_expired_token_first() {
+        let twitter = expiring();
+        let shared = shared(Some(chrono::Utc::now() - chrono::Duration::minutes(1)));
+
+        let token = shared.token();
+        let fields = crate::v2::Fields::new();
+        twitter
+            .run(crate::v2::tweet::lookup([1261326399320715264], &fields, &token))
+            .await
+            .unwrap();
+
+        let auth = auth_headers(&twitter);
+        assert_eq!(auth.len(), 2);
+        assert_eq!(auth[1], format!("Bearer {}", shared.current().access_token));
+    }
+
+    #[tokio::test]
+    async fn refreshes_stream_connections() {
+        use futures::TryStreamExt;
+
+        let twitter = expiring();
+        let shared = shared(None);
+
+        let mut stream = crate::stream::sample(&shared.token());
+        let msg = twitter.run(stream.try_next()).await.unwrap();
+        assert!(msg.is_some());
+
+        let auth = auth_headers(&twitter);
+        assert_eq!(auth.len(), 3);
+        assert_eq!(auth[0], "Bearer old");
+        assert_eq!(auth[2], format!("Bearer {}", shared.current().access_token));
+    }
+
+    #[test]
+    fn serializes_as_current_token() {
+        let token = shared(None).token();
+        let json = serde_json::to_string(&token).unwrap();
+        match serde_json::from_str(&json).unwrap() {
+            Token::OAuth2(saved) => assert_eq!(saved.access_token, "old"),
+            token => panic!("unexpected token: {:?}", token),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A way to keep tokens under a name for each account.
+
+use crate::error::Result;
+
+use super::Token;
+
+/// A place to keep tokens between runs of an app, under a name for each account.
+///
+/// Since a `Token` is as privileged as a password, it shouldn't be written to disk as plain text.
+/// Implement this trait to keep tokens wherever your app keeps its secrets, or (with the
+/// `token_store` feature) use a [`FileTokenStore`], which keeps them in an encrypted file.
+///
+/// [`FileTokenStore`]: struct.FileTokenStore.html
+///
+/// A `TokenStore` pairs well with a [`SharedToken`], whose refreshed tokens can be saved back to
+/// the store as they're issued:
+///
+/// ```rust,no_run
+/// # fn example(store: std::sync::Arc<dyn egg_mode::auth::TokenStore + Send + Sync>,
+/// #            shared: egg_mode::auth::SharedToken) {
+/// use egg_mode::Token;
+///
+/// let shared = shared.on_refresh(move |token: &Token| {
+///     if let Err(err) = store.save("me", token) {
+///         eprintln!("couldn't save the refreshed token: {}", err);
+///     }
+/// });
+/// # }
+/// ```
+///
+/// [`SharedToken`]: struct.SharedToken.html
+pub trait TokenStore {
+    /// Loads the token saved for the given account, if there is one.
+    fn load(&self, account: &str) -> Result<Option<Token>>;
+
+    /// Saves the given token for the given account, replacing any token already saved for it.
+    ///
+    /// Since a `Token::Shared` can't be saved directly, implementations should save the token it
+    /// currently holds, as a `Token::OAuth2`.
+    fn save(&self, account: &str, token: &Token) -> Result<()>;
+
+    /// Removes the token saved for the given account, if there is one.
+    fn remove(&self, account: &str) -> Result<()>;
+
+    /// Returns the names of the accounts with saved tokens.
+    fn accounts(&self) -> Result<Vec<String>>;
+}
@@ -57,6 +57,11 @@
 //! the format Twitter uses for timestamps, and `serde_via_string` uses `Display` and `FromStr` to
 //! save a string representation of the original type.
 //!
+//! `field_enum!` defines an enum of the string values Twitter accepts for some parameter, like
+//! the OAuth 2.0 scopes or the API v2 `fields`. Each variant is written next to its string once,
+//! and the macro generates `as_str`, `Display`, `FromStr`, and serde impls from that one list, so
+//! they can't drift apart.
+//!
 //! `merge_by` and its companion type `MergeBy` is a copy of the iterator adapter of the same name
 //! from itertools, because i didn't want to add another dependency onto the great towering pile
 //! that is my dep tree. `>_>`
@@ -87,6 +92,32 @@
 //! `rate_headers` is an infra function that takes the `Headers` and returns an empty `Response`
 //! with the rate-limit info parsed out. It's only exported for a couple functions in `list` which
 //! need to get that info even on an error.
+//!
+//! ## `Transport`
+//!
+//! The `transport` module holds the `Transport` trait, which is what actually sends requests out
+//! over the network. There's a single process-wide transport held behind a lock, which defaults
+//! to one shared `hyper::Client` so that connections get pooled between calls. `get_response` and
+//! `raw_request` grab the current transport with `current_transport` and hand it the request.
+//! `with_transport` wraps a future so that a different transport is used only while it's being
+//! polled. It's all re-exported in `raw` so people can swap in their own client or a test double.
+//!
+//! The process-wide value and the per-future override both come from `ScopedSetting`, in the
+//! `scoped` module. It keeps the global value behind a lock and the override in a thread-local
+//! that gets swapped in and out around each poll of the wrapped future. The retry policy and the
+//! throttle setting in `service` use it the same way.
+//!
+//! ## `RetryPolicy`
+//!
+//! The `retry` module holds the opt-in `RetryPolicy`, which is set process-wide (or scoped to a
+//! future with `with_retry_policy`) the same way as the transport. When one is set, `raw_request`
+//! hands the request to `retry_request` instead of calling `send_request` directly. That buffers
+//! the body so the request can be rebuilt for each attempt, and uses the `Resign` extension that
+//! `auth::raw` attaches to OAuth-signed requests to give each retry a fresh signature.
+//!
+//! The streams that promise to wait out rate limits (`Timeline::watch` and the search streams)
+//! wrap each page load in `wait_for_rate_limit`. That defers to the current policy if there is one,
+//! and otherwise waits as long as the default policy would.
 
 use std::borrow::Cow;
 use std::collections::HashMap;
@@ -98,12 +129,97 @@ use hyper::header::{HeaderMap, HeaderValue};
 use percent_encoding::{utf8_percent_encode, AsciiSet, PercentEncode};
 
 mod response;
+mod retry;
+mod scoped;
+mod transport;
 
 pub use crate::auth::raw::{get, post, post_json};
 
 pub use crate::common::response::*;
+pub use crate::common::retry::{
+    retry_policy, set_retry_policy, with_retry_policy, RetryPolicy, WithRetryPolicy,
+};
+pub(crate) use crate::common::retry::wait_for_rate_limit;
+pub(crate) use crate::common::scoped::{Scoped, ScopedSetting};
+pub use crate::common::transport::*;
 use crate::{error, list, user};
 
+/// Defines an enum of the string values Twitter accepts for some parameter, along with `as_str`,
+/// `Display`, `FromStr`, `Serialize`, and `Deserialize` impls that all use the name given for each
+/// value.
+macro_rules! field_enum {
+    (
+        $(#[$attr:meta])*
+        pub enum $name:ident {
+            $(
+                $(#[$var_attr:meta])*
+                $var:ident => $val:literal,
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $(
+                $(#[$var_attr])*
+                $var,
+            )*
+        }
+
+        impl $name {
+            /// Returns the name Twitter uses for this value.
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$var => $val,)*
+                }
+            }
+        }
+
+        #[allow(unused_qualifications)]
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        #[allow(unused_qualifications)]
+        impl std::str::FromStr for $name {
+            type Err = crate::error::UnknownValueError;
+
+            fn from_str(s: &str) -> std::result::Result<$name, Self::Err> {
+                match s {
+                    $($val => Ok($name::$var),)*
+                    _ => Err(crate::error::UnknownValueError {
+                        kind: stringify!($name),
+                        value: s.to_string(),
+                    }),
+                }
+            }
+        }
+
+        #[allow(unused_qualifications)]
+        impl serde::Serialize for $name {
+            fn serialize<S>(&self, ser: S) -> std::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                ser.serialize_str(self.as_str())
+            }
+        }
+
+        #[allow(unused_qualifications)]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D>(de: D) -> std::result::Result<$name, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let s = String::deserialize(de)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
 /// Macro to create a `Serialize`/`Deserialize` implementation allowing for deserialization via the
 /// given "raw" struct or via a "round-trip" using the type's own serialization.
 ///
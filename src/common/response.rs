@@ -1,10 +1,12 @@
 //! Infrastructure types related to packaging rate-limit information alongside responses from
 //! Twitter.
 
+use crate::auth::raw::{PoolSigner, RateLimitKey};
+use crate::auth::SharedToken;
 use crate::error::Error::{self, *};
 use crate::error::{Result, TwitterErrors};
+use crate::service;
 
-use hyper::client::{ResponseFuture, HttpConnector};
 use hyper::{self, Body, Request};
 use serde::{de::DeserializeOwned, Deserialize};
 use serde_json;
@@ -12,6 +14,8 @@ use serde_json;
 use std::convert::TryFrom;
 
 use super::Headers;
+use super::retry::{retry_policy, retry_request};
+use super::transport::{current_transport, TransportFuture};
 
 const X_RATE_LIMIT_LIMIT: &'static str = "X-Rate-Limit-Limit";
 const X_RATE_LIMIT_REMAINING: &'static str = "X-Rate-Limit-Remaining";
@@ -144,51 +148,61 @@ impl<T: Iterator> Iterator for ResponseIter<T> {
     }
 }
 
-#[cfg(not(any(feature = "native_tls", feature = "rustls", feature = "rustls_webpki")))]
-compile_error!("Crate `egg_mode` must be compiled with exactly one of the three \
-feature flags `native_tls`, `rustls` or `rustls_webpki` enabled, you attempted to \
-compile `egg_mode` with none of them enabled");
-
-#[cfg(any(
-    all(feature = "native_tls", any(feature = "rustls", feature = "rustls_webpki")),
-    all(feature = "rustls", any(feature = "native_tls", feature = "rustls_webpki")),
-    all(feature = "rustls_webpki", any(feature = "native_tls", feature = "rustls")),
-))]
-compile_error!("features `egg_mode/native_tls`, `egg_mode/rustls` and \
-`egg_mode/rustls_webpki` are mutually exclusive, you attempted to compile `egg_mode` \
-with more than one of these feature flags enabled at the same time");
-
-#[cfg(feature = "native_tls")]
-fn new_https_connector() -> hyper_tls::HttpsConnector<HttpConnector> {
-    hyper_tls::HttpsConnector::new()
-}
-
-#[cfg(feature = "rustls")]
-fn new_https_connector() -> hyper_rustls::HttpsConnector<HttpConnector> {
-    hyper_rustls::HttpsConnector::with_native_roots()
-}
-
-#[cfg(feature = "rustls_webpki")]
-fn new_https_connector() -> hyper_rustls::HttpsConnector<HttpConnector> {
-    hyper_rustls::HttpsConnector::with_webpki_roots()
-}
-
 // n.b. this function is re-exported in the `raw` module - these docs are public!
-/// Converts the given request into a raw `ResponseFuture` from hyper.
-pub fn get_response(request: Request<Body>) -> ResponseFuture {
-    let connector = new_https_connector();
-    let client = hyper::Client::builder().build(connector);
-    client.request(request)
+/// Sends the given request through the current `Transport`, returning the raw response future.
+pub fn get_response(request: Request<Body>) -> TransportFuture {
+    current_transport().send(request)
 }
 
 // n.b. this function is re-exported in the `raw` module - these docs are public!
 /// Loads the given request, parses the headers and response for potential errors given by Twitter,
 /// and returns the headers and raw bytes returned from the response.
-pub async fn raw_request(request: Request<Body>) -> Result<(Headers, Vec<u8>)> {
-    let connector = new_https_connector();
-    let client = hyper::Client::builder().build(connector);
-    let resp = client.request(request).await?;
+///
+/// If a `RetryPolicy` is currently set, the request is retried according to that policy when it
+/// fails for a transient reason.
+///
+/// If the request was signed with a `Token::Shared`, the token is refreshed if it has expired, or
+/// if Twitter rejects the request as unauthorized.
+pub async fn raw_request(mut request: Request<Body>) -> Result<(Headers, Vec<u8>)> {
+    if let Some(pool) = request.extensions().get::<PoolSigner>().cloned() {
+        // let the pool pick a token now, rather than when the request was built
+        pool.sign(&mut request);
+    }
+    if let Some(shared) = request.extensions().get::<SharedToken>().cloned() {
+        // boxed, since refreshing the token sends a request of its own
+        return Box::pin(shared.send(request)).await;
+    }
+    send_with_policy(request).await
+}
+
+/// Sends the given request, retrying it according to the current `RetryPolicy`, if any.
+pub(crate) async fn send_with_policy(request: Request<Body>) -> Result<(Headers, Vec<u8>)> {
+    match retry_policy() {
+        Some(policy) => retry_request(&policy, request).await,
+        None => send_request(request).await,
+    }
+}
+
+/// Sends the given request once, and parses the response for potential errors given by Twitter.
+pub(crate) async fn send_request(request: Request<Body>) -> Result<(Headers, Vec<u8>)> {
+    let method = service::Method::from_path(request.uri().path());
+    let key = request.extensions().get::<RateLimitKey>().cloned();
+    // if the request fails before Twitter answers it, dropping the reservation gives its call back
+    let _reservation = match (method, &key, service::throttle()) {
+        (Some(method), Some(key), Some(max_wait)) => Some(
+            service::rate_limit_tracker()
+                .acquire(key, method, max_wait)
+                .await?,
+        ),
+        _ => None,
+    };
+    let resp = get_response(request).await?;
     let (parts, body) = resp.into_parts();
+    if let (Some(method), Some(key)) = (method, key) {
+        if let Ok(rate_limit) = RateLimit::try_from(&parts.headers) {
+            service::rate_limit_tracker().record_for_key(key, method, rate_limit);
+        }
+    }
     let body: Vec<_> = hyper::body::to_bytes(body).await?.to_vec();
     if let Ok(errors) = serde_json::from_slice::<TwitterErrors>(&body) {
         if errors.errors.iter().any(|e| e.code == 88)
@@ -0,0 +1,498 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! An opt-in policy to automatically retry requests that fail for transient reasons.
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::header::{HeaderValue, AUTHORIZATION};
+use hyper::{Body, Method, Request};
+use rand::Rng;
+
+use crate::auth::raw::{PoolSigner, RateLimitKey, Resign};
+use crate::error::{Error, Result};
+
+use super::response::send_request;
+use super::{Headers, Scoped, ScopedSetting};
+
+/// Twitter error code for "Over capacity".
+const OVER_CAPACITY: i32 = 130;
+/// Twitter error code for "Internal error".
+const INTERNAL_ERROR: i32 = 131;
+
+// n.b. this type is re-exported in the `raw` module - these docs are public!
+/// A policy describing how egg-mode should retry requests that fail for transient reasons.
+///
+/// By default, egg-mode sends each request once, and hands any error straight back to you. Once a
+/// `RetryPolicy` is given to [`set_retry_policy`] (or to [`with_retry_policy`] for a single task),
+/// every function that returns a `Response` - which is nearly every function in the `tweet`,
+/// `user`, `list`, `direct`, and `search` modules - will instead retry its request when:
+///
+/// * Twitter reports that the rate limit for the endpoint has been reached (error code 88, given
+///   back as `Error::RateLimit`). The request is held until the rate-limit window resets, as long
+///   as that's no longer than `max_rate_limit_wait` away.
+/// * The network connection failed (`Error::NetError`), Twitter responded with a server error
+///   (a 5xx status, or error codes 130 and 131), or Twitter responded with a 429 status without
+///   saying when the rate limit resets. These are retried with an exponential backoff, starting at
+///   `initial_backoff` and doubling with each attempt until it reaches `max_backoff`.
+///
+/// Since a request that failed with a server error may have been processed anyway, requests that
+/// aren't idempotent (like the `POST` requests used to post or delete tweets) are only retried
+/// when the rate limit was hit, unless `retry_non_idempotent` is set.
+///
+/// Requests signed with an access token are signed again before each retry, so that a request
+/// that has waited for the rate limit to reset isn't rejected for having a stale timestamp.
+///
+/// Streams are not affected by the retry policy.
+///
+/// [`set_retry_policy`]: fn.set_retry_policy.html
+/// [`with_retry_policy`]: fn.with_retry_policy.html
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use egg_mode::raw::{set_retry_policy, RetryPolicy};
+///
+/// set_retry_policy(Some(
+///     RetryPolicy::new()
+///         .max_attempts(5)
+///         .backoff(Duration::from_millis(500), Duration::from_secs(30)),
+/// ));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    wait_for_rate_limit: bool,
+    max_rate_limit_wait: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a new `RetryPolicy` with the default settings.
+    ///
+    /// By default, a request is attempted at most 4 times. Rate limits are waited out if they
+    /// reset within 16 minutes (slightly longer than one rate-limit window), and other errors are
+    /// retried after a backoff that starts at 1 second and tops out at 32 seconds, with jitter.
+    /// Requests that aren't idempotent are not retried after server or network errors.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            wait_for_rate_limit: true,
+            max_rate_limit_wait: Duration::from_secs(16 * 60),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(32),
+            jitter: true,
+            retry_non_idempotent: false,
+        }
+    }
+
+    /// Sets the maximum number of times a request will be sent, including the first attempt.
+    ///
+    /// Setting this to 1 or less effectively disables retries.
+    pub fn max_attempts(self, max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            ..self
+        }
+    }
+
+    /// Sets whether to wait for the rate limit to reset when Twitter reports that it's been
+    /// reached. If this is `false`, `Error::RateLimit` is returned immediately.
+    pub fn wait_for_rate_limit(self, wait_for_rate_limit: bool) -> Self {
+        RetryPolicy {
+            wait_for_rate_limit,
+            ..self
+        }
+    }
+
+    /// Sets the longest amount of time to wait for a rate limit to reset. If the rate limit
+    /// resets further in the future than this, `Error::RateLimit` is returned immediately.
+    pub fn max_rate_limit_wait(self, max_rate_limit_wait: Duration) -> Self {
+        RetryPolicy {
+            max_rate_limit_wait,
+            ..self
+        }
+    }
+
+    /// Sets the delay before the first retry after a server or network error, and the longest
+    /// delay that repeated failures can back off to.
+    pub fn backoff(self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        RetryPolicy {
+            initial_backoff,
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// Sets whether to randomize backoff delays. With jitter, each delay is a random duration
+    /// between half and all of the backoff, which keeps many clients that failed at once from
+    /// retrying all at once.
+    pub fn jitter(self, jitter: bool) -> Self {
+        RetryPolicy { jitter, ..self }
+    }
+
+    /// Sets whether requests that aren't idempotent, like posting a tweet, should be retried
+    /// after server or network errors. Note that this can cause such an action to happen twice,
+    /// if Twitter processed the request before the error occurred.
+    pub fn retry_non_idempotent(self, retry_non_idempotent: bool) -> Self {
+        RetryPolicy {
+            retry_non_idempotent,
+            ..self
+        }
+    }
+
+    /// Returns how long to wait before sending a request again after it failed with the given
+    /// error on the given attempt, or `None` if the error should be returned instead.
+    fn delay(&self, err: &Error, attempt: u32, method: &Method) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let transient = match err {
+            Error::RateLimit(reset) => {
+                if !self.wait_for_rate_limit {
+                    return None;
+                }
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                // wait an extra second, in case our clock is slightly behind Twitter's
+                let wait = Duration::from_secs((*reset as i64 - now + 1).max(1) as u64);
+                return if wait <= self.max_rate_limit_wait {
+                    Some(wait)
+                } else {
+                    None
+                };
+            }
+            Error::NetError(_) => true,
+            Error::BadStatus(status) => {
+                status.is_server_error() || *status == hyper::StatusCode::TOO_MANY_REQUESTS
+            }
+            Error::TwitterError(_, errors) => errors
+                .errors
+                .iter()
+                .any(|e| e.code == OVER_CAPACITY || e.code == INTERNAL_ERROR),
+            _ => false,
+        };
+
+        if !transient || !(self.retry_non_idempotent || method.is_idempotent()) {
+            return None;
+        }
+
+        let factor = 2u32.saturating_pow(attempt - 1);
+        let backoff = self
+            .initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |b| b.min(self.max_backoff));
+        if self.jitter {
+            Some(backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0)))
+        } else {
+            Some(backoff)
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+thread_local! {
+    static SCOPED_RETRY_POLICY: RefCell<Option<Option<RetryPolicy>>> = const { RefCell::new(None) };
+}
+
+lazy_static::lazy_static! {
+    static ref RETRY_POLICY: ScopedSetting<Option<RetryPolicy>> =
+        ScopedSetting::new(None, &SCOPED_RETRY_POLICY);
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Sets the `RetryPolicy` that egg-mode will use for all further requests, or disables retries if
+/// given `None`.
+pub fn set_retry_policy(policy: Option<RetryPolicy>) {
+    RETRY_POLICY.set(policy);
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Returns the `RetryPolicy` egg-mode is currently using, if any.
+///
+/// If this is called from within a future wrapped by [`with_retry_policy`], the policy given there
+/// is returned. Otherwise, this returns the policy given to [`set_retry_policy`].
+///
+/// [`with_retry_policy`]: fn.with_retry_policy.html
+/// [`set_retry_policy`]: fn.set_retry_policy.html
+pub fn retry_policy() -> Option<RetryPolicy> {
+    RETRY_POLICY.get()
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Runs the given future, retrying any requests it makes according to the given `RetryPolicy`.
+///
+/// Unlike [`set_retry_policy`], this only affects requests sent while the given future is being
+/// polled. Giving `None` disables retries for the given future, even if a policy has been set for
+/// the whole process.
+///
+/// [`set_retry_policy`]: fn.set_retry_policy.html
+pub fn with_retry_policy<F: Future>(policy: Option<RetryPolicy>, fut: F) -> WithRetryPolicy<F> {
+    WithRetryPolicy(RETRY_POLICY.scope(policy, fut))
+}
+
+// n.b. this type is re-exported in the `raw` module - these docs are public!
+/// A future that retries its requests according to a specific `RetryPolicy`.
+///
+/// This type is returned by [`with_retry_policy`]. See that function's documentation for details.
+///
+/// [`with_retry_policy`]: fn.with_retry_policy.html
+#[must_use = "futures do nothing unless polled"]
+pub struct WithRetryPolicy<F>(Scoped<Option<RetryPolicy>, F>);
+
+impl<F: Future> Future for WithRetryPolicy<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+/// Sends the given request, retrying it according to the given policy.
+pub(crate) async fn retry_request(
+    policy: &RetryPolicy,
+    request: Request<Body>,
+) -> Result<(Headers, Vec<u8>)> {
+    // the body needs to be buffered so it can be sent more than once
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    let resign = parts.extensions.get::<Resign>();
+    let pool = parts.extensions.get::<PoolSigner>();
+    let key = parts.extensions.get::<RateLimitKey>();
+
+    let mut attempt = 1;
+    loop {
+        let mut request = Request::new(Body::from(body.clone()));
+        *request.method_mut() = parts.method.clone();
+        *request.uri_mut() = parts.uri.clone();
+        *request.version_mut() = parts.version;
+        *request.headers_mut() = parts.headers.clone();
+        if let Some(key) = key {
+            request.extensions_mut().insert(key.clone());
+        }
+        if let Some(pool) = pool.filter(|_| attempt > 1) {
+            // the token the last attempt was sent with may have run out of calls
+            pool.sign(&mut request);
+        } else if let Some(resign) = resign.filter(|_| attempt > 1) {
+            if let Ok(auth) = HeaderValue::try_from(resign.authorization()) {
+                request.headers_mut().insert(AUTHORIZATION, auth);
+            }
+        }
+
+        let err = match send_request(request).await {
+            Ok(resp) => return Ok(resp),
+            Err(err) => err,
+        };
+        match policy.delay(&err, attempt, &parts.method) {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => return Err(err),
+        }
+        attempt += 1;
+    }
+}
+
+/// Runs the given call, waiting for the rate limit to reset and making it again whenever it fails
+/// with `Error::RateLimit`, as far as the current `RetryPolicy` allows.
+///
+/// If a policy is set, `raw_request` has already waited out the rate limit as long as the policy
+/// allows, so the call is only made once. Otherwise, the default `RetryPolicy` decides how long to
+/// wait and how many attempts to make. Other errors are returned right away.
+pub(crate) async fn wait_for_rate_limit<T, F, Fut>(mut call: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    if retry_policy().is_some() {
+        return call().await;
+    }
+
+    let policy = RetryPolicy::new();
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(err @ Error::RateLimit(_)) => match policy.delay(&err, attempt, &Method::GET) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(err),
+            },
+            result => return result,
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::common::{raw_request, with_transport, Transport, TransportFuture};
+
+    /// A transport that fails with the given responses before succeeding, and keeps the
+    /// Authorization header of every request it receives.
+    #[derive(Clone, Default)]
+    struct FlakyTransport {
+        failures: Arc<Mutex<Vec<(u16, String)>>>,
+        auth: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Transport for FlakyTransport {
+        fn send(&self, request: Request<Body>) -> TransportFuture {
+            let auth = request.headers()[AUTHORIZATION].to_str().unwrap().to_string();
+            self.auth.lock().unwrap().push(auth);
+            let (status, body) = self
+                .failures
+                .lock()
+                .unwrap()
+                .pop()
+                .unwrap_or((200, "{}".to_string()));
+            Box::pin(async move {
+                Ok(hyper::Response::builder()
+                    .status(status)
+                    .header("X-Rate-Limit-Reset", "0")
+                    .body(Body::from(body))
+                    .unwrap())
+            })
+        }
+    }
+
+    fn request(method: Method) -> Request<Body> {
+        let token = crate::auth::Token::Access {
+            consumer: crate::KeyPair::new("key", "secret"),
+            access: crate::KeyPair::new("key", "secret"),
+        };
+        crate::auth::raw::RequestBuilder::new(method, "https://api.twitter.com/1.1/test.json")
+            .request_token(&token)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_server_errors() {
+        let transport = FlakyTransport::default();
+        transport
+            .failures
+            .lock()
+            .unwrap()
+            .extend(vec![(503, String::new()), (500, String::new())]);
+
+        let policy = RetryPolicy::new();
+        let resp = with_transport(
+            Arc::new(transport.clone()),
+            with_retry_policy(Some(policy), raw_request(request(Method::GET))),
+        )
+        .await;
+
+        assert!(resp.is_ok());
+        assert_eq!(transport.auth.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_rate_limit() {
+        let transport = FlakyTransport::default();
+        let body = r#"{"errors":[{"code":88,"message":"Rate limit exceeded"}]}"#;
+        transport
+            .failures
+            .lock()
+            .unwrap()
+            .push((429, body.to_string()));
+
+        let policy = RetryPolicy::new().retry_non_idempotent(false);
+        let resp = with_transport(
+            Arc::new(transport.clone()),
+            with_retry_policy(Some(policy), raw_request(request(Method::POST))),
+        )
+        .await;
+
+        assert!(resp.is_ok());
+        let auth = transport.auth.lock().unwrap();
+        assert_eq!(auth.len(), 2);
+        assert_ne!(auth[0], auth[1], "retried request should be signed again");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_on_non_idempotent_or_exhausted_requests() {
+        let transport = FlakyTransport::default();
+        transport.failures.lock().unwrap().push((500, String::new()));
+        let resp = with_transport(
+            Arc::new(transport.clone()),
+            with_retry_policy(Some(RetryPolicy::new()), raw_request(request(Method::POST))),
+        )
+        .await;
+        assert!(matches!(resp, Err(Error::BadStatus(s)) if s.as_u16() == 500));
+
+        let transport = FlakyTransport::default();
+        transport
+            .failures
+            .lock()
+            .unwrap()
+            .extend(vec![(500, String::new()); 3]);
+        let resp = with_transport(
+            Arc::new(transport.clone()),
+            with_retry_policy(
+                Some(RetryPolicy::new().max_attempts(2)),
+                raw_request(request(Method::GET)),
+            ),
+        )
+        .await;
+        assert!(matches!(resp, Err(Error::BadStatus(_))));
+        assert_eq!(transport.auth.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_rate_limit_within_policy() {
+        let now = chrono::Utc::now().timestamp() as i32;
+
+        let mut calls = 0;
+        let resp = wait_for_rate_limit(|| {
+            calls += 1;
+            let result = match calls {
+                1 | 2 => Err(Error::RateLimit(now + 60)),
+                _ => Ok(()),
+            };
+            async move { result }
+        })
+        .await;
+        assert!(resp.is_ok());
+        assert_eq!(calls, 3);
+
+        // the default policy doesn't wait longer than a rate-limit window
+        let mut calls = 0;
+        let resp = wait_for_rate_limit(|| {
+            calls += 1;
+            async move { Err::<(), _>(Error::RateLimit(now + 60 * 60)) }
+        })
+        .await;
+        assert!(matches!(resp, Err(Error::RateLimit(_))));
+        assert_eq!(calls, 1);
+
+        // once a policy is set, it already covered the request
+        let mut calls = 0;
+        let policy = Some(RetryPolicy::new().wait_for_rate_limit(false));
+        let resp = with_retry_policy(
+            policy,
+            wait_for_rate_limit(|| {
+                calls += 1;
+                async move { Err::<(), _>(Error::RateLimit(now + 60)) }
+            }),
+        )
+        .await;
+        assert!(matches!(resp, Err(Error::RateLimit(_))));
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,97 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A process-wide setting that can be overridden for the duration of a single future.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::RwLock;
+use std::task::{Context, Poll};
+use std::thread::LocalKey;
+
+/// A setting with a process-wide value, which can be overridden while a given future is polled.
+///
+/// The override is kept in a thread-local, which each setting declares for itself and hands to
+/// `new`:
+///
+/// ```rust,ignore
+/// thread_local! {
+///     static SCOPED_THING: RefCell<Option<Thing>> = const { RefCell::new(None) };
+/// }
+///
+/// lazy_static::lazy_static! {
+///     static ref THING: ScopedSetting<Thing> = ScopedSetting::new(Thing::default(), &SCOPED_THING);
+/// }
+/// ```
+pub(crate) struct ScopedSetting<T: 'static> {
+    global: RwLock<T>,
+    scoped: &'static LocalKey<RefCell<Option<T>>>,
+}
+
+impl<T: Clone + 'static> ScopedSetting<T> {
+    /// Creates a new setting with the given process-wide value, keeping overrides in the given
+    /// thread-local.
+    pub(crate) fn new(global: T, scoped: &'static LocalKey<RefCell<Option<T>>>) -> Self {
+        ScopedSetting {
+            global: RwLock::new(global),
+            scoped,
+        }
+    }
+
+    /// Replaces the process-wide value of this setting.
+    pub(crate) fn set(&self, value: T) {
+        *self.global.write().unwrap_or_else(|e| e.into_inner()) = value;
+    }
+
+    /// Returns the value of this setting for the future being polled, or the process-wide value if
+    /// it hasn't been overridden.
+    pub(crate) fn get(&self) -> T {
+        if let Some(value) = self.scoped.with(|v| v.borrow().clone()) {
+            return value;
+        }
+
+        self.global
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Wraps the given future so that this setting has the given value while it's being polled.
+    pub(crate) fn scope<F: Future>(&'static self, value: T, fut: F) -> Scoped<T, F> {
+        Scoped {
+            setting: self,
+            value,
+            fut: Box::pin(fut),
+        }
+    }
+}
+
+/// A future that overrides a `ScopedSetting` while it's being polled.
+pub(crate) struct Scoped<T: 'static, F> {
+    setting: &'static ScopedSetting<T>,
+    value: T,
+    fut: Pin<Box<F>>,
+}
+
+impl<T: Clone + Unpin + 'static, F: Future> Future for Scoped<T, F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        /// Restores the previously-scoped value, even if the inner future panics.
+        struct Restore<T: 'static>(&'static LocalKey<RefCell<Option<T>>>, Option<T>);
+
+        impl<T: 'static> Drop for Restore<T> {
+            fn drop(&mut self) {
+                let prev = self.1.take();
+                self.0.with(|v| *v.borrow_mut() = prev);
+            }
+        }
+
+        let scoped = self.setting.scoped;
+        let value = self.value.clone();
+        let _restore = Restore(scoped, scoped.with(|v| v.replace(Some(value))));
+        self.fut.as_mut().poll(cx)
+    }
+}
@@ -0,0 +1,226 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Infrastructure to send requests to Twitter through a shared, replaceable HTTP client.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use hyper::{Body, Request};
+
+use crate::common::{Scoped, ScopedSetting};
+use crate::error::Result;
+
+// n.b. this type is re-exported in the `raw` module - these docs are public!
+/// The `Future` returned by a `Transport` when it sends a request.
+pub type TransportFuture = Pin<Box<dyn Future<Output = Result<hyper::Response<Body>>> + Send>>;
+
+// n.b. this trait is re-exported in the `raw` module - these docs are public!
+/// A means of sending HTTP requests to Twitter.
+///
+/// Every request egg-mode makes, from loading a single tweet to opening a stream, is ultimately
+/// handed to a `Transport` to be sent. By default, egg-mode uses a single `hyper::Client`, shared
+/// across the whole process, configured with the TLS implementation selected by the crate
+/// features. This allows connections to be pooled and kept alive between calls.
+///
+/// If you need to customize how requests are sent - to route them through a proxy, set timeouts,
+/// or substitute a test double that never touches the network - you can implement this trait and
+/// hand it to [`set_transport`]. This trait is also implemented for any `hyper::Client` with a
+/// suitable connector, so you can also hand over a `Client` that you've configured yourself.
+///
+/// [`set_transport`]: fn.set_transport.html
+pub trait Transport: Send + Sync {
+    /// Sends the given request, returning a `Future` that resolves to the response from the
+    /// server.
+    fn send(&self, request: Request<Body>) -> TransportFuture;
+}
+
+impl<C> Transport for hyper::Client<C, Body>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    fn send(&self, request: Request<Body>) -> TransportFuture {
+        let resp = self.request(request);
+        Box::pin(async move { Ok(resp.await?) })
+    }
+}
+
+#[cfg(not(any(feature = "native_tls", feature = "rustls", feature = "rustls_webpki")))]
+compile_error!(
+    "Crate `egg_mode` must be compiled with exactly one of the three \
+feature flags `native_tls`, `rustls` or `rustls_webpki` enabled, you attempted to \
+compile `egg_mode` with none of them enabled"
+);
+
+#[cfg(any(
+    all(
+        feature = "native_tls",
+        any(feature = "rustls", feature = "rustls_webpki")
+    ),
+    all(
+        feature = "rustls",
+        any(feature = "native_tls", feature = "rustls_webpki")
+    ),
+    all(
+        feature = "rustls_webpki",
+        any(feature = "native_tls", feature = "rustls")
+    ),
+))]
+compile_error!(
+    "features `egg_mode/native_tls`, `egg_mode/rustls` and \
+`egg_mode/rustls_webpki` are mutually exclusive, you attempted to compile `egg_mode` \
+with more than one of these feature flags enabled at the same time"
+);
+
+#[cfg(feature = "native_tls")]
+fn new_https_connector() -> hyper_tls::HttpsConnector<HttpConnector> {
+    hyper_tls::HttpsConnector::new()
+}
+
+#[cfg(feature = "rustls")]
+fn new_https_connector() -> hyper_rustls::HttpsConnector<HttpConnector> {
+    hyper_rustls::HttpsConnector::with_native_roots()
+}
+
+#[cfg(feature = "rustls_webpki")]
+fn new_https_connector() -> hyper_rustls::HttpsConnector<HttpConnector> {
+    hyper_rustls::HttpsConnector::with_webpki_roots()
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Creates a new instance of the `Transport` egg-mode uses by default.
+///
+/// This is a `hyper::Client` using the TLS implementation selected by egg-mode's crate features.
+/// It can be useful to restore the default after calling [`set_transport`].
+///
+/// [`set_transport`]: fn.set_transport.html
+pub fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(hyper::Client::builder().build::<_, Body>(new_https_connector()))
+}
+
+thread_local! {
+    static SCOPED_TRANSPORT: RefCell<Option<Arc<dyn Transport>>> = const { RefCell::new(None) };
+}
+
+lazy_static::lazy_static! {
+    static ref TRANSPORT: ScopedSetting<Arc<dyn Transport>> =
+        ScopedSetting::new(default_transport(), &SCOPED_TRANSPORT);
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Sets the `Transport` that egg-mode will use to send all further requests.
+///
+/// This replaces the transport for the whole process, including requests that have been created
+/// but not yet sent. Requests that are already in flight will complete with the transport they
+/// were started with.
+pub fn set_transport(transport: impl Transport + 'static) {
+    set_shared_transport(Arc::new(transport));
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Sets the `Transport` that egg-mode will use to send all further requests, from a transport
+/// that is already shared.
+///
+/// This behaves the same as [`set_transport`], but allows you to keep a handle to the transport
+/// yourself, for example to inspect the requests given to a test double.
+///
+/// [`set_transport`]: fn.set_transport.html
+pub fn set_shared_transport(transport: Arc<dyn Transport>) {
+    TRANSPORT.set(transport);
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Returns a handle to the `Transport` egg-mode is currently using to send requests.
+///
+/// If this is called from within a future wrapped by [`with_transport`], the transport given there
+/// is returned. Otherwise, this returns the transport set by [`set_transport`], or the default
+/// transport if none has been set.
+///
+/// [`with_transport`]: fn.with_transport.html
+/// [`set_transport`]: fn.set_transport.html
+pub fn current_transport() -> Arc<dyn Transport> {
+    TRANSPORT.get()
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Runs the given future, sending any requests it makes through the given `Transport`.
+///
+/// Unlike [`set_transport`], this only affects requests sent while the given future is being
+/// polled, leaving the rest of the process alone. This allows different parts of an application -
+/// or different tests running at the same time - to use different transports alongside their own
+/// `Token`s.
+///
+/// Note that requests are sent when their futures or streams are first polled, not when they are
+/// created, so any `TwitterStream`, `CursorIter`, or other lazy request should be polled from
+/// within the given future for it to use this transport.
+///
+/// [`set_transport`]: fn.set_transport.html
+pub fn with_transport<F: Future>(transport: Arc<dyn Transport>, fut: F) -> WithTransport<F> {
+    WithTransport(TRANSPORT.scope(transport, fut))
+}
+
+// n.b. this type is re-exported in the `raw` module - these docs are public!
+/// A future that sends all of its requests through a specific `Transport`.
+///
+/// This type is returned by [`with_transport`]. See that function's documentation for details.
+///
+/// [`with_transport`]: fn.with_transport.html
+#[must_use = "futures do nothing unless polled"]
+pub struct WithTransport<F>(Scoped<Arc<dyn Transport>, F>);
+
+impl<F: Future> Future for WithTransport<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::raw_request;
+
+    struct CannedTransport(&'static str);
+
+    impl Transport for CannedTransport {
+        fn send(&self, _request: Request<Body>) -> TransportFuture {
+            let body = self.0;
+            Box::pin(async move { Ok(hyper::Response::new(Body::from(body))) })
+        }
+    }
+
+    #[tokio::test]
+    async fn scoped_transports_nest() {
+        // n.b. this doesn't call `set_transport`, since that would swap the transport out from
+        // under any other test running at the same time
+        let outer: Arc<dyn Transport> = Arc::new(CannedTransport("{\"id\": 1}"));
+        let inner: Arc<dyn Transport> = Arc::new(CannedTransport("{\"id\": 2}"));
+
+        let (during, after) = with_transport(outer.clone(), async {
+            let during = with_transport(inner.clone(), async { current_transport() }).await;
+            (during, current_transport())
+        })
+        .await;
+
+        assert!(Arc::ptr_eq(&during, &inner));
+        assert!(Arc::ptr_eq(&after, &outer));
+    }
+
+    #[tokio::test]
+    async fn requests_use_scoped_transport() {
+        let req = Request::get("https://api.twitter.com/1.1/statuses/show.json")
+            .body(Body::empty())
+            .unwrap();
+        let transport = Arc::new(CannedTransport("{\"id\": 1}"));
+        let (_, body) = with_transport(transport, raw_request(req)).await.unwrap();
+
+        assert_eq!(body, b"{\"id\": 1}");
+    }
+}
@@ -10,7 +10,7 @@
 //! what types come out of functions that return `CursorIter`.
 
 use futures::Stream;
-use serde::{de::DeserializeOwned, Deserialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -194,6 +194,38 @@ impl Cursor for ListCursor {
 /// re-initiate the late network call; this way, you can wait for your network connection to return
 /// or for your rate limit to refresh and try again with the same state.
 ///
+/// ## Checkpoints
+///
+/// To pick up a long pagination run where it left off after a restart, save a [`CursorCheckpoint`]
+/// from `checkpoint` as you go, then give it to `with_checkpoint` on a new `CursorIter` from the
+/// same call. The new `CursorIter` continues with the item after the last one yielded before the
+/// checkpoint was taken, even if that was in the middle of a page:
+///
+/// [`CursorCheckpoint`]: struct.CursorCheckpoint.html
+///
+/// ```rust,no_run
+/// # use egg_mode::Token;
+/// # #[tokio::main]
+/// # async fn main() {
+/// # let token: Token = unimplemented!();
+/// # fn load() -> Option<String> { None }
+/// # fn save(_: &str) {}
+/// use futures::TryStreamExt;
+/// use egg_mode::cursor::CursorCheckpoint;
+///
+/// let mut ids = egg_mode::user::followers_ids("rustlang", &token).with_page_size(5000);
+/// if let Some(saved) = load() {
+///     let checkpoint: CursorCheckpoint = serde_json::from_str(&saved).unwrap();
+///     ids = ids.with_checkpoint(checkpoint);
+/// }
+///
+/// while let Some(id) = ids.try_next().await.unwrap() {
+///     println!("{}", id.response);
+///     save(&serde_json::to_string(&ids.checkpoint()).unwrap());
+/// }
+/// # }
+/// ```
+///
 /// ## Manual paging
 ///
 /// The `Stream` implementation works by loading in a page of results (with size set by the
@@ -255,6 +287,34 @@ where
     pub next_cursor: i64,
     loader: Option<FutureResponse<T>>,
     iter: Option<Box<dyn Iterator<Item = Response<T::Item>> + Send>>,
+    /// The cursor that loaded the page in `iter`.
+    page_cursor: i64,
+    /// The number of items from the page in `iter` that have been yielded (or skipped).
+    consumed: usize,
+    /// The number of items left in `iter`.
+    remaining: usize,
+    /// The number of items to skip from the next page loaded, when resuming from a checkpoint.
+    skip: usize,
+}
+
+/// A saved position in a `CursorIter`, which can be used to resume it later.
+///
+/// A `CursorCheckpoint` is given by `CursorIter::checkpoint`, and can be given back to
+/// `CursorIter::with_checkpoint` on a `CursorIter` for the same call. It can be serialized, so it
+/// can be saved to disk and used to resume in a later run. See the [`CursorIter`] docs for
+/// details.
+///
+/// [`CursorIter`]: struct.CursorIter.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorCheckpoint {
+    /// The cursor for the page to resume from. A value of zero means the `CursorIter` was
+    /// finished.
+    pub cursor: i64,
+    /// The number of items in that page that were already yielded.
+    pub skip: usize,
+    /// The page size the `CursorIter` was using, since a different page size would change what's
+    /// in the page.
+    pub page_size: Option<i32>,
 }
 
 impl<T> CursorIter<T>
@@ -277,6 +337,10 @@ where
                 next_cursor: -1,
                 loader: None,
                 iter: None,
+                page_cursor: -1,
+                consumed: 0,
+                remaining: 0,
+                skip: 0,
                 ..self
             }
         } else {
@@ -284,6 +348,54 @@ where
         }
     }
 
+    ///Returns a checkpoint of the current position in this `CursorIter`, which can be given to
+    ///`with_checkpoint` to resume from this point later.
+    ///
+    ///The checkpoint is taken from the items yielded by the `Stream` implementation; paging
+    ///manually with `call` only updates it once `next_cursor` is changed.
+    pub fn checkpoint(&self) -> CursorCheckpoint {
+        if self.remaining > 0 {
+            CursorCheckpoint {
+                cursor: self.page_cursor,
+                skip: self.consumed,
+                page_size: self.page_size,
+            }
+        } else {
+            CursorCheckpoint {
+                cursor: self.next_cursor,
+                skip: self.skip,
+                page_size: self.page_size,
+            }
+        }
+    }
+
+    ///Resumes this `CursorIter` from the given checkpoint, so that its `Stream` implementation
+    ///starts with the item after the last one yielded before the checkpoint was taken.
+    ///
+    ///The checkpoint should come from a `CursorIter` for the same call, with the same parameters.
+    ///If this call takes a page size, the one saved in the checkpoint replaces the current one, so
+    ///that the resumed page lines up with the items that were already yielded. Calling this
+    ///function will invalidate any current results, if any were previously loaded.
+    pub fn with_checkpoint(self, checkpoint: CursorCheckpoint) -> CursorIter<T> {
+        let finished = checkpoint.cursor == 0;
+        let page_size = match (self.page_size, checkpoint.page_size) {
+            (Some(_), Some(saved)) => Some(saved),
+            (current, _) => current,
+        };
+        CursorIter {
+            page_size,
+            previous_cursor: -1,
+            next_cursor: checkpoint.cursor,
+            loader: None,
+            iter: None,
+            page_cursor: checkpoint.cursor,
+            consumed: 0,
+            remaining: 0,
+            skip: if finished { 0 } else { checkpoint.skip },
+            ..self
+        }
+    }
+
     ///Loads the next page of results.
     ///
     ///This is intended to be used as part of this struct's Iterator implementation. It is provided
@@ -316,6 +428,10 @@ where
             next_cursor: -1,
             loader: None,
             iter: None,
+            page_cursor: -1,
+            consumed: 0,
+            remaining: 0,
+            skip: 0,
         }
     }
 }
@@ -341,7 +457,13 @@ where
                     let resp = Response::map(resp, |r| r.into_inner());
                     let rate = resp.rate_limit_status;
 
-                    let mut iter = Box::new(resp.response.into_iter().map(move |item| Response {
+                    let skip = std::mem::take(&mut self.skip);
+                    let len = resp.response.len();
+                    self.consumed = skip.min(len);
+                    self.remaining = len - self.consumed;
+
+                    let items = resp.response.into_iter().skip(skip);
+                    let mut iter = Box::new(items.map(move |item| Response {
                         rate_limit_status: rate,
                         response: item,
                     }));
@@ -349,8 +471,15 @@ where
                     self.iter = Some(iter);
 
                     match first {
-                        Some(item) => return Poll::Ready(Some(Ok(item))),
-                        None => return Poll::Ready(None),
+                        Some(item) => {
+                            self.consumed += 1;
+                            self.remaining -= 1;
+                            return Poll::Ready(Some(Ok(item)));
+                        }
+                        None if skip == 0 => return Poll::Ready(None),
+                        // the rest of the page was yielded before the checkpoint, so move on to
+                        // the next one
+                        None => (),
                     }
                 }
                 Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
@@ -359,13 +488,83 @@ where
 
         if let Some(ref mut results) = self.iter {
             if let Some(item) = results.next() {
+                self.consumed += 1;
+                self.remaining -= 1;
                 return Poll::Ready(Some(Ok(item)));
             } else if self.next_cursor == 0 {
                 return Poll::Ready(None);
             }
+        } else if self.next_cursor == 0 {
+            // resumed from a checkpoint of a finished cursor
+            return Poll::Ready(None);
         }
 
+        self.page_cursor = self.next_cursor;
         self.loader = Some(Box::pin(self.call()));
         self.poll_next(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, TryStreamExt};
+
+    use super::*;
+    use crate::testing::MockTwitter;
+
+    /// Returns a `MockTwitter` that serves the IDs 1 through 7 as followers.
+    fn followers() -> MockTwitter {
+        let twitter = MockTwitter::new();
+        twitter.set_cursor_items("/1.1/followers/ids.json", "ids", (1..=7).map(Into::into));
+        twitter
+    }
+
+    #[tokio::test]
+    async fn resume_from_checkpoint() {
+        let twitter = followers();
+        let token = MockTwitter::token();
+
+        let mut ids = user::followers_ids(783214, &token).with_page_size(3);
+        let first = twitter
+            .run(ids.by_ref().take(4).try_collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(
+            first.iter().map(|id| id.response).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+
+        let checkpoint = ids.checkpoint();
+        assert_eq!(checkpoint.cursor, 3);
+        assert_eq!(checkpoint.skip, 1);
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let checkpoint: CursorCheckpoint = serde_json::from_str(&json).unwrap();
+
+        // the checkpoint's page size replaces the default one
+        let rest = user::followers_ids(783214, &token).with_checkpoint(checkpoint);
+        assert_eq!(rest.page_size, Some(3));
+        let rest = twitter.run(rest.try_collect::<Vec<_>>()).await.unwrap();
+        assert_eq!(
+            rest.iter().map(|id| id.response).collect::<Vec<_>>(),
+            [5, 6, 7]
+        );
+
+        // a checkpoint taken at the end of a page resumes from the next one
+        let mut ids = user::followers_ids(783214, &token).with_page_size(3);
+        twitter
+            .run(ids.by_ref().take(3).try_collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(ids.checkpoint().cursor, 3);
+        assert_eq!(ids.checkpoint().skip, 0);
+
+        // and a finished one yields nothing
+        let done = CursorCheckpoint {
+            cursor: 0,
+            skip: 0,
+            page_size: None,
+        };
+        let ids = user::followers_ids(783214, &token).with_checkpoint(done);
+        assert_eq!(ids.collect::<Vec<_>>().await.len(), 0);
+    }
+}
@@ -87,6 +87,17 @@ pub struct MediaError {
     pub message: String,
 }
 
+/// Represents a string that isn't one of the values of an enum egg-mode parses from text, like an
+/// OAuth 2.0 `Scope` or an API v2 `TweetField`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown {kind}: {value:?}")]
+pub struct UnknownValueError {
+    /// The name of the enum that was being parsed.
+    pub kind: &'static str,
+    /// The string that was given.
+    pub value: String,
+}
+
 /// A set of errors that can occur when interacting with Twitter.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -119,6 +130,9 @@ pub enum Error {
     ///rate-limit window will open.
     #[error("Rate limit reached, hold until {}", _0)]
     RateLimit(i32),
+    ///The user declined to give the app access to their account while signing in.
+    #[error("User denied access to the app")]
+    AccessDenied,
     ///An attempt to upload a video or gif successfully uploaded the file, but failed in
     ///post-processing. The enclosed value contains the error message from Twitter.
     #[error("Error processing media: {}", _0)]
@@ -77,7 +77,17 @@
 //!   certificates to verify the connection, instead of using your operating system's root
 //!   certificates.
 //!
-//! Keep in mind that these features are mutually exclusive - if you enable more than one, a
+//! In addition, there are features that aren't related to TLS:
+//!
+//! * `testing`: Off by default. With this feature on, egg-mode includes the `testing` module, which
+//!   contains an in-process fake of the Twitter API that can be used to test code that uses
+//!   egg-mode without network access.
+//! * `loopback`: Off by default. With this feature on, egg-mode includes `auth::LoopbackLogin`,
+//!   which lets apps without a web server sign users in without asking them to copy a PIN.
+//! * `token_store`: Off by default. With this feature on, egg-mode includes
+//!   `auth::FileTokenStore`, which saves tokens to an encrypted file.
+//!
+//! Keep in mind that the TLS features are mutually exclusive - if you enable more than one, a
 //! compile error will result. If you need to use `rustls` or `rustls_webpki`, remember to set
 //! `default-features = false` in your Cargo.toml.
 //!
@@ -126,12 +136,16 @@
 //!   their profile information, blocking or muting them, or showing the relationship between two
 //!   users.
 //! * `search`: Due to the complexity of searching for tweets, it gets its own module.
+//! * `saved_search`: This module lets you load, save, and delete the search queries a user has
+//!   saved to their account, and run them with the `search` module.
 //! * `direct`: Here you can work with a user's Direct Messages, either by loading DMs they've sent
 //!   or received, or by sending new ones.
 //! * `list`: This module lets you act on lists, from creating and deleting them, adding and
 //!   removing users, or loading the posts made by their members.
 //! * `media`: This module lets you upload images, GIFs, and videos to Twitter so you can attach
 //!   them to tweets.
+//! * `v2`: This module holds the endpoints egg-mode supports from version 2 of the Twitter API,
+//!   which returns tweets and users in a different shape than the rest of egg-mode.
 //!
 //! ## Secondary actions
 //!
@@ -140,6 +154,8 @@
 //!
 //! * `place`: Here are actions that look up physical locations that can be attached to tweets, as
 //!   well at the `Place` struct that appears on tweets with locations attached.
+//! * `trends`: This module loads the topics trending worldwide or in a given location, as well as
+//!   the locations Twitter has trends for.
 //! * `service`: These are some miscellaneous methods that show information about the Twitter
 //!   service as a whole, like loading the maximum length of t.co URLs or loading the current Terms
 //!   of Service or Privacy Policy.
@@ -173,11 +189,16 @@ pub mod list;
 pub mod media;
 pub mod place;
 pub mod raw;
+pub mod saved_search;
 pub mod search;
 pub mod service;
 pub mod stream;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trends;
 pub mod tweet;
 pub mod user;
+pub mod v2;
 
 pub use crate::auth::{Token, KeyPair};
 pub use crate::common::{Response, ResponseIter, RateLimit};
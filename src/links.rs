@@ -2,6 +2,187 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+//! The URLs of every endpoint egg-mode calls, and the machinery to point them somewhere else.
+//!
+//! The constants in here are all written against Twitter's real hosts. Before a request goes out,
+//! `resolve` swaps the host for whatever's been configured with `set_base_urls` (or
+//! `with_base_urls`), so everything can be pointed at a mock server or a proxy without the rest of
+//! the crate having to care.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::common::{Scoped, ScopedSetting};
+
+const API_BASE: &str = "https://api.twitter.com";
+const UPLOAD_BASE: &str = "https://upload.twitter.com";
+const STREAM_BASE: &str = "https://stream.twitter.com";
+const WEB_BASE: &str = "https://twitter.com";
+
+// n.b. this type is re-exported in the `raw` module - these docs are public!
+/// The base URLs that egg-mode sends its requests to.
+///
+/// Twitter serves its API from three hosts: the main REST API, the media upload API, and the
+/// Streaming API. By default, egg-mode uses Twitter's own hosts for each of these, but you can
+/// hand a different set to [`set_base_urls`] (or to [`with_base_urls`] for a single task) to
+/// redirect every request egg-mode makes, for example to a local mock server during integration
+/// tests, or to an egress proxy. The pages egg-mode sends users to in their browser, like the OAuth
+/// 2.0 authorization page, have a base URL of their own.
+///
+/// [`set_base_urls`]: fn.set_base_urls.html
+/// [`with_base_urls`]: fn.with_base_urls.html
+///
+/// Each base URL is given as a scheme and authority, optionally with a path prefix, like
+/// `http://localhost:8080` or `https://proxy.example.com/twitter-api`. The path of the endpoint
+/// being called (e.g. `/1.1/statuses/show.json`) is appended to it.
+///
+/// Note that requests signed with OAuth 1.0a are still signed against Twitter's own URLs. This
+/// allows a proxy to forward the request to Twitter unchanged.
+///
+/// # Example
+///
+/// ```rust
+/// use egg_mode::raw::{set_base_urls, BaseUrls};
+///
+/// // send everything to a mock server running locally
+/// set_base_urls(BaseUrls::all("http://127.0.0.1:8080"));
+/// # set_base_urls(BaseUrls::default());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseUrls {
+    /// The base URL for the REST API, by default `https://api.twitter.com`. This is also used for
+    /// the authentication endpoints.
+    pub api: String,
+    /// The base URL for media uploads, by default `https://upload.twitter.com`.
+    pub upload: String,
+    /// The base URL for the Streaming API, by default `https://stream.twitter.com`.
+    pub stream: String,
+    /// The base URL for pages a user opens in their browser, by default `https://twitter.com`.
+    /// This is used for the OAuth 2.0 authorization page returned by `oauth2_authorize_url`.
+    pub web: String,
+}
+
+impl BaseUrls {
+    /// Creates a `BaseUrls` that sends requests for all three APIs to the same base URL.
+    ///
+    /// This leaves the `web` base URL at its default, so users are still sent to Twitter to
+    /// authorize an app.
+    pub fn all(base: impl Into<String>) -> BaseUrls {
+        let base = base.into();
+        BaseUrls {
+            api: base.clone(),
+            upload: base.clone(),
+            stream: base,
+            web: WEB_BASE.to_string(),
+        }
+    }
+}
+
+impl Default for BaseUrls {
+    fn default() -> BaseUrls {
+        BaseUrls {
+            api: API_BASE.to_string(),
+            upload: UPLOAD_BASE.to_string(),
+            stream: STREAM_BASE.to_string(),
+            web: WEB_BASE.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static SCOPED_BASE_URLS: RefCell<Option<Option<BaseUrls>>> = const { RefCell::new(None) };
+}
+
+lazy_static::lazy_static! {
+    // `None` stands for Twitter's own hosts, so requests don't need to be rewritten
+    static ref BASE_URLS: ScopedSetting<Option<BaseUrls>> =
+        ScopedSetting::new(None, &SCOPED_BASE_URLS);
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Sets the base URLs that egg-mode will send all further requests to.
+///
+/// This applies to the whole process, including requests that have already been created but not
+/// yet sent. To go back to sending requests to Twitter, call this with `BaseUrls::default()`.
+pub fn set_base_urls(urls: BaseUrls) {
+    BASE_URLS.set(Some(urls).filter(|urls| *urls != BaseUrls::default()));
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Returns the base URLs that egg-mode is currently sending requests to.
+///
+/// If this is called from within a future wrapped by [`with_base_urls`], the base URLs given there
+/// are returned. Otherwise, this returns the ones set by [`set_base_urls`], or Twitter's own hosts
+/// if none have been set.
+///
+/// [`with_base_urls`]: fn.with_base_urls.html
+/// [`set_base_urls`]: fn.set_base_urls.html
+pub fn base_urls() -> BaseUrls {
+    BASE_URLS.get().unwrap_or_default()
+}
+
+// n.b. this function is re-exported in the `raw` module - these docs are public!
+/// Runs the given future, sending any requests it makes to the given base URLs.
+///
+/// Unlike [`set_base_urls`], this only affects requests built while the given future is being
+/// polled, leaving the rest of the process alone. This allows different tests running at the same
+/// time to point egg-mode at their own mock servers.
+///
+/// [`set_base_urls`]: fn.set_base_urls.html
+pub fn with_base_urls<F: Future>(urls: BaseUrls, fut: F) -> WithBaseUrls<F> {
+    let urls = Some(urls).filter(|urls| *urls != BaseUrls::default());
+    WithBaseUrls(BASE_URLS.scope(urls, fut))
+}
+
+// n.b. this type is re-exported in the `raw` module - these docs are public!
+/// A future that sends all of its requests to a specific set of base URLs.
+///
+/// This type is returned by [`with_base_urls`]. See that function's documentation for details.
+///
+/// [`with_base_urls`]: fn.with_base_urls.html
+#[must_use = "futures do nothing unless polled"]
+pub struct WithBaseUrls<F>(Scoped<Option<BaseUrls>, F>);
+
+impl<F: Future> Future for WithBaseUrls<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+/// Rewrites the given URL to use the configured base URLs, if it points at one of Twitter's hosts.
+/// URLs pointing anywhere else are returned as-is.
+pub fn resolve(url: &str) -> Cow<'_, str> {
+    match BASE_URLS.get() {
+        Some(bases) => Cow::Owned(bases.resolve(url).into_owned()),
+        None => Cow::Borrowed(url),
+    }
+}
+
+impl BaseUrls {
+    /// Rewrites the given URL to use these base URLs, if it points at one of Twitter's hosts.
+    fn resolve<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        for &(host, base) in &[
+            (API_BASE, &self.api),
+            (UPLOAD_BASE, &self.upload),
+            (STREAM_BASE, &self.stream),
+            (WEB_BASE, &self.web),
+        ] {
+            if let Some(path) = url.strip_prefix(host) {
+                if path.is_empty() || path.starts_with('/') || path.starts_with('?') {
+                    return Cow::Owned(format!("{}{}", base.trim_end_matches('/'), path));
+                }
+            }
+        }
+
+        Cow::Borrowed(url)
+    }
+}
+
 pub mod auth {
     pub const REQUEST_TOKEN: &'static str = "https://api.twitter.com/oauth/request_token";
     pub const ACCESS_TOKEN: &'static str = "https://api.twitter.com/oauth/access_token";
@@ -9,6 +190,8 @@ pub mod auth {
     pub const INVALIDATE_BEARER: &'static str = "https://api.twitter.com/oauth2/invalidate_token";
     pub const AUTHORIZE: &'static str = "https://api.twitter.com/oauth/authorize";
     pub const AUTHENTICATE: &'static str = "https://api.twitter.com/oauth/authenticate";
+    pub const OAUTH2_AUTHORIZE: &'static str = "https://twitter.com/i/oauth2/authorize";
+    pub const OAUTH2_TOKEN: &'static str = "https://api.twitter.com/2/oauth2/token";
     pub const VERIFY_CREDENTIALS: &'static str =
         "https://api.twitter.com/1.1/account/verify_credentials.json";
 }
@@ -61,6 +244,7 @@ pub mod statuses {
         "https://api.twitter.com/1.1/statuses/retweeters/ids.json";
     pub const LIKES_OF: &'static str = "https://api.twitter.com/1.1/favorites/list.json";
     pub const SEARCH: &'static str = "https://api.twitter.com/1.1/search/tweets.json";
+    pub const PREMIUM_SEARCH_STEM: &'static str = "https://api.twitter.com/1.1/tweets/search";
     pub const RETWEET_STEM: &'static str = "https://api.twitter.com/1.1/statuses/retweet";
     pub const UNRETWEET_STEM: &'static str = "https://api.twitter.com/1.1/statuses/unretweet";
     pub const LIKE: &'static str = "https://api.twitter.com/1.1/favorites/create.json";
@@ -69,6 +253,13 @@ pub mod statuses {
     pub const DELETE_STEM: &'static str = "https://api.twitter.com/1.1/statuses/destroy";
 }
 
+pub mod saved_searches {
+    pub const LIST: &'static str = "https://api.twitter.com/1.1/saved_searches/list.json";
+    pub const SHOW_STEM: &'static str = "https://api.twitter.com/1.1/saved_searches/show";
+    pub const CREATE: &'static str = "https://api.twitter.com/1.1/saved_searches/create.json";
+    pub const DESTROY_STEM: &'static str = "https://api.twitter.com/1.1/saved_searches/destroy";
+}
+
 pub mod media {
     pub const UPLOAD: &'static str = "https://upload.twitter.com/1.1/media/upload.json";
     pub const METADATA: &'static str = "https://upload.twitter.com/1.1/media/metadata/create.json";
@@ -107,6 +298,12 @@ pub mod place {
     pub const SEARCH: &'static str = "https://api.twitter.com/1.1/geo/search.json";
 }
 
+pub mod trends {
+    pub const PLACE: &'static str = "https://api.twitter.com/1.1/trends/place.json";
+    pub const AVAILABLE: &'static str = "https://api.twitter.com/1.1/trends/available.json";
+    pub const CLOSEST: &'static str = "https://api.twitter.com/1.1/trends/closest.json";
+}
+
 pub mod direct {
     pub const SHOW: &'static str = "https://api.twitter.com/1.1/direct_messages/events/show.json";
     pub const LIST: &'static str = "https://api.twitter.com/1.1/direct_messages/events/list.json";
@@ -128,3 +325,79 @@ pub mod stream {
     pub const SAMPLE: &'static str = "https://stream.twitter.com/1.1/statuses/sample.json";
     pub const FILTER: &'static str = "https://stream.twitter.com/1.1/statuses/filter.json";
 }
+
+pub mod v2 {
+    pub const TWEETS: &'static str = "https://api.twitter.com/2/tweets";
+    pub const USERS_STEM: &'static str = "https://api.twitter.com/2/users";
+    pub const SEARCH_ALL: &'static str = "https://api.twitter.com/2/tweets/search/all";
+    pub const SEARCH_STREAM: &'static str = "https://api.twitter.com/2/tweets/search/stream";
+    pub const SEARCH_STREAM_RULES: &'static str =
+        "https://api.twitter.com/2/tweets/search/stream/rules";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_base_urls() {
+        // n.b. this doesn't call `set_base_urls`, since that would redirect the requests of any
+        // other test running at the same time
+        assert_eq!(BaseUrls::default().resolve(statuses::SHOW), statuses::SHOW);
+
+        let bases = BaseUrls {
+            api: "http://127.0.0.1:8080/".to_string(),
+            upload: "http://127.0.0.1:8081".to_string(),
+            ..BaseUrls::default()
+        };
+        assert_eq!(
+            bases.resolve(statuses::SHOW),
+            "http://127.0.0.1:8080/1.1/statuses/show.json"
+        );
+        assert_eq!(
+            bases.resolve(media::UPLOAD),
+            "http://127.0.0.1:8081/1.1/media/upload.json"
+        );
+        assert_eq!(bases.resolve(stream::SAMPLE), stream::SAMPLE);
+        assert_eq!(
+            bases.resolve(auth::OAUTH2_AUTHORIZE),
+            auth::OAUTH2_AUTHORIZE
+        );
+        assert_eq!(
+            BaseUrls::all("http://127.0.0.1:8080").resolve(auth::OAUTH2_AUTHORIZE),
+            auth::OAUTH2_AUTHORIZE
+        );
+
+        let bases = BaseUrls {
+            web: "http://127.0.0.1:8082".to_string(),
+            ..BaseUrls::default()
+        };
+        assert_eq!(
+            bases.resolve(auth::OAUTH2_AUTHORIZE),
+            "http://127.0.0.1:8082/i/oauth2/authorize"
+        );
+        assert_eq!(bases.resolve(statuses::SHOW), statuses::SHOW);
+        assert_eq!(
+            bases.resolve("https://api.twitter.com.example.com/1.1/x.json"),
+            "https://api.twitter.com.example.com/1.1/x.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn scoped_base_urls_nest() {
+        let outer = BaseUrls::all("http://127.0.0.1:8080");
+        let inner = BaseUrls::all("http://127.0.0.1:9090");
+
+        let (before, nested, after) = with_base_urls(outer.clone(), async {
+            let before = resolve(users::SHOW).into_owned();
+            let nested = with_base_urls(inner, async { resolve(users::SHOW).into_owned() }).await;
+            (before, nested, resolve(users::SHOW).into_owned())
+        })
+        .await;
+
+        assert_eq!(before, "http://127.0.0.1:8080/1.1/users/show.json");
+        assert_eq!(nested, "http://127.0.0.1:9090/1.1/users/show.json");
+        assert_eq!(after, before);
+        assert_eq!(base_urls(), BaseUrls::default());
+    }
+}
@@ -43,8 +43,8 @@
 //! the response:
 //!
 //! * At the most hands-off end, there's [`response_future`,] which is a small wrapper that just
-//!   starts the request and hands off the `ResponseFuture` from `hyper` to give you the most power
-//!   over handling the response data.
+//!   starts the request and hands off the response future from the current `Transport` to give
+//!   you the most power over handling the response data.
 //! * In the middle, there's [`response_raw_bytes`], which wraps the `ResponseFuture` to return the
 //!   headers and response body after inspecting the rate-limit headers and response code, and
 //!   after inspecting the response to see whether it returned error data from Twitter.
@@ -63,6 +63,33 @@
 //! wrappers in egg-mode. See the documentation for these functions to see their assumptions and
 //! requirements.
 //!
+//! All of these functions, as well as every other function in egg-mode that calls Twitter, send
+//! their requests through a [`Transport`]. By default this is a single `hyper::Client` shared by
+//! the whole process, but you can replace it with [`set_transport`] to route requests through a
+//! proxy, apply timeouts, or use a test double in place of the network. To only use a different
+//! transport for a specific task, wrap it with [`with_transport`] instead.
+//!
+//! [`Transport`]: trait.Transport.html
+//! [`set_transport`]: fn.set_transport.html
+//! [`with_transport`]: fn.with_transport.html
+//!
+//! Requests that fail for a transient reason, like hitting a rate limit or a server error, can be
+//! retried automatically by giving a [`RetryPolicy`] to [`set_retry_policy`] or
+//! [`with_retry_policy`]. This applies to every function that uses `response_raw_bytes`,
+//! `response_json`, or `response_empty`, including the ones in this module.
+//!
+//! [`RetryPolicy`]: struct.RetryPolicy.html
+//! [`set_retry_policy`]: fn.set_retry_policy.html
+//! [`with_retry_policy`]: fn.with_retry_policy.html
+//!
+//! Similarly, every request is sent to one of Twitter's API hosts, unless you give a different set
+//! of [`BaseUrls`] to [`set_base_urls`] (or to [`with_base_urls`] for a single task). This can be
+//! used to point egg-mode at a local mock server or an egress proxy.
+//!
+//! [`BaseUrls`]: struct.BaseUrls.html
+//! [`set_base_urls`]: fn.set_base_urls.html
+//! [`with_base_urls`]: fn.with_base_urls.html
+//!
 //! If you need the ability to assemble a request in a way that `request_get`, `request_post`, or
 //! `request_post_json` don't allow, the `RequestBuilder` type available in the `auth` submodule
 //! provides the lowest-level control over how a request is built and signed. For more information,
@@ -154,6 +181,14 @@ pub use crate::common::raw_request as response_raw_bytes;
 pub use crate::common::request_with_json_response as response_json;
 pub use crate::common::request_with_empty_response as response_empty;
 
+pub use crate::common::{Transport, TransportFuture, WithTransport};
+pub use crate::common::{current_transport, default_transport, set_shared_transport, set_transport};
+pub use crate::common::with_transport;
+
+pub use crate::common::{retry_policy, set_retry_policy, with_retry_policy, RetryPolicy, WithRetryPolicy};
+
+pub use crate::links::{base_urls, set_base_urls, with_base_urls, BaseUrls, WithBaseUrls};
+
 /// Converts the given request into a `TwitterStream`.
 ///
 /// This function can be used for endpoints that open a persistent stream, like `GET
@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::common::*;
+use crate::error::Result;
+use crate::{auth, links};
+
+use super::SavedSearch;
+
+///Loads all the searches saved by the authenticated user.
+pub async fn list(token: &auth::Token) -> Result<Response<Vec<SavedSearch>>> {
+    let req = get(links::saved_searches::LIST, token, None);
+    request_with_json_response(req).await
+}
+
+///Loads the saved search with the given ID. The authenticated user must own the saved search.
+pub async fn show(id: u64, token: &auth::Token) -> Result<Response<SavedSearch>> {
+    let url = format!("{}/{}.json", links::saved_searches::SHOW_STEM, id);
+    let req = get(&url, token, None);
+    request_with_json_response(req).await
+}
+
+///Saves the given search query to the authenticated user's account.
+///
+///Twitter allows each user to save up to 25 searches. On success, the future returned by this
+///function yields the new saved search.
+pub async fn create<S: Into<CowStr>>(
+    query: S,
+    token: &auth::Token,
+) -> Result<Response<SavedSearch>> {
+    let params = ParamList::new().add_param("query", query);
+    let req = post(links::saved_searches::CREATE, token, Some(&params));
+    request_with_json_response(req).await
+}
+
+///Deletes the saved search with the given ID. The authenticated user must own the saved search.
+///
+///On success, the future returned by this function yields the deleted saved search.
+pub async fn delete(id: u64, token: &auth::Token) -> Result<Response<SavedSearch>> {
+    let url = format!("{}/{}.json", links::saved_searches::DESTROY_STEM, id);
+    let req = post(&url, token, None);
+    request_with_json_response(req).await
+}
@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Structs and functions for working with a user's saved searches.
+//!
+//! Twitter lets each user save up to 25 search queries to their account, so they can be run again
+//! later from any client. egg-mode represents each of these as a [`SavedSearch`], which you can
+//! load with `list` or `show`, add with `create`, and remove with `delete`.
+//!
+//! [`SavedSearch`]: struct.SavedSearch.html
+//!
+//! A saved search only holds its query text; to load the tweets it matches, call
+//! `SavedSearch::search` to start a [`SearchBuilder`] with its query, and add any other search
+//! parameters from there:
+//!
+//! [`SearchBuilder`]: ../search/struct.SearchBuilder.html
+//!
+//! ```rust,no_run
+//! # use egg_mode::Token;
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let token: Token = unimplemented!();
+//! use egg_mode::search::ResultType;
+//!
+//! let saved = egg_mode::saved_search::list(&token).await.unwrap();
+//!
+//! for saved in &saved.response {
+//!     let results = saved
+//!         .search()
+//!         .result_type(ResultType::Recent)
+//!         .call(&token)
+//!         .await
+//!         .unwrap();
+//!     println!("{}: {} tweets", saved.name, results.statuses.len());
+//! }
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+use crate::search::{self, SearchBuilder};
+
+mod fun;
+
+pub use self::fun::*;
+
+///Represents a search query saved to the authenticated user's account.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SavedSearch {
+    ///Numeric ID of this saved search.
+    pub id: u64,
+    ///The name of this saved search, as shown in Twitter's clients. Twitter sets this to the same
+    ///text as the query.
+    pub name: String,
+    ///The search query this saved search represents.
+    pub query: String,
+    ///UTC timestamp of when this search was saved.
+    #[serde(with = "serde_datetime")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl SavedSearch {
+    ///Begins setting up a tweet search with this saved search's query.
+    pub fn search(&self) -> SearchBuilder {
+        search::search(self.query.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SavedSearch;
+    use crate::common::tests::load_file;
+
+    #[test]
+    fn parse_saved_search_sample() {
+        let content = load_file("sample_payloads/saved_search_list.json");
+        let saved = ::serde_json::from_str::<Vec<SavedSearch>>(&content).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].id, 9569704);
+        assert_eq!(saved[1].query, "@twitterapi");
+        assert_eq!(saved[1].created_at.timestamp(), 1372120867);
+    }
+}
@@ -35,6 +35,31 @@
 //! change what is searched for when you call `older` or `newer`; the `SearchResult` keeps its
 //! search arguments in a separate private field.
 //!
+//! If you'd rather not page through the results yourself, `SearchBuilder::into_stream` returns a
+//! `Stream` of tweets that loads older pages as needed, waiting out the rate limit if it's reached
+//! along the way. `into_stream_until` does the same, but stops once it reaches a given
+//! [`StopAt`] bound:
+//!
+//! [`StopAt`]: ../tweet/enum.StopAt.html
+//!
+//! ```rust,no_run
+//! # use egg_mode::Token;
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let token: Token = unimplemented!();
+//! use egg_mode::search;
+//! use egg_mode::tweet::StopAt;
+//! use futures::TryStreamExt;
+//!
+//! let tweets = search::search("rustlang")
+//!     .count(100)
+//!     .into_stream_until(&token, StopAt::Count(500))
+//!     .try_collect::<Vec<_>>()
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
 //! The search parameter given in the initial call to `search` has several options itself. A full
 //! reference is available in [Twitter's Search API documentation][search-doc]. This listing by
 //! itself does not include the search by Place ID, as mentioned on [a separate Tweets by Place
@@ -43,15 +68,72 @@
 //!
 //! [search-doc]: https://developer.twitter.com/en/docs/tweets/search/api-reference/get-search-tweets
 //! [search-place]: https://developer.twitter.com/en/docs/tweets/search/guides/tweets-by-place
+//!
+//! Instead of writing the query string by hand, you can build it out of a [`Query`], which checks
+//! each operator before rendering the query, and give it to `search_query`:
+//!
+//! [`Query`]: enum.Query.html
+//!
+//! ```rust,no_run
+//! # use egg_mode::Token;
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let token: Token = unimplemented!();
+//! use egg_mode::search::{self, Filter, Query};
+//!
+//! let query = Query::from_user("rustlang").and(!Query::filter(Filter::Retweets));
+//! let search = search::search_query(&query)
+//!     .unwrap()
+//!     .call(&token)
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! ## Historical search
+//!
+//! The standard search above only covers the last 7 days of tweets. To search further back, the
+//! premium 30-day and full-archive search APIs are available through `archive_search`, which
+//! returns an `ArchiveSearchBuilder`. These APIs are paid products tied to a "dev environment"
+//! set up in Twitter's Developer Portal, whose label is given alongside the query. Their results
+//! are paged with a `next` token instead of tweet IDs, and `ArchiveSearchBuilder::into_stream`
+//! follows those tokens for you:
+//!
+//! ```rust,no_run
+//! # use egg_mode::Token;
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let token: Token = unimplemented!();
+//! use chrono::TimeZone;
+//! use egg_mode::search::{self, ArchiveProduct};
+//! use futures::TryStreamExt;
+//!
+//! let tweets = search::archive_search("rustlang", ArchiveProduct::FullArchive, "dev")
+//!     .from_date(chrono::Utc.with_ymd_and_hms(2015, 5, 1, 0, 0, 0).unwrap())
+//!     .to_date(chrono::Utc.with_ymd_and_hms(2015, 6, 1, 0, 0, 0).unwrap())
+//!     .max_results(500)
+//!     .into_stream(&token)
+//!     .try_collect::<Vec<_>>()
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! For API v2's full-archive search, see `v2::tweet::search_all`.
 
 use std::fmt;
 
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Deserializer};
 
 use crate::common::*;
-use crate::tweet::Tweet;
+use crate::tweet::{StopAt, StreamBound, Tweet};
 use crate::{auth, error, links};
 
+mod query;
+
+pub use self::query::*;
+
 ///Begin setting up a tweet search with the given query.
 pub fn search<S: Into<CowStr>>(query: S) -> SearchBuilder {
     SearchBuilder {
@@ -66,6 +148,14 @@ pub fn search<S: Into<CowStr>>(query: S) -> SearchBuilder {
     }
 }
 
+///Begin setting up a tweet search with the given `Query`.
+///
+///This checks the query and renders it to a string, returning an error if it has an invalid part
+///or is too long for the search API.
+pub fn search_query(query: &Query) -> Result<SearchBuilder, QueryError> {
+    Ok(search(query.build()?))
+}
+
 ///Represents what kind of tweets should be included in search results.
 #[derive(Debug, Copy, Clone)]
 pub enum ResultType {
@@ -89,6 +179,7 @@ impl fmt::Display for ResultType {
 }
 
 ///Represents a radius around a given location to return search results for.
+#[derive(Debug, Copy, Clone)]
 pub enum Distance {
     ///A radius given in miles.
     Miles(f32),
@@ -176,7 +267,91 @@ impl SearchBuilder {
 
     ///Finalize the search terms and return the first page of responses.
     pub async fn call(self, token: &auth::Token) -> Result<Response<SearchResult>, error::Error> {
-        let params = ParamList::new()
+        let params = self.into_params();
+        let req = get(links::statuses::SEARCH, token, Some(&params));
+        let mut resp = request_with_json_response::<SearchResult>(req).await?;
+
+        resp.response.params = Some(params);
+        Ok(resp)
+    }
+
+    ///Converts this search into a `Stream` of tweets, which automatically loads older pages of
+    ///results as needed until there are no more to load.
+    ///
+    ///If the rate limit is reached while loading a page, the stream waits for it to reset and
+    ///tries again, rather than returning `Error::RateLimit`, as long as the current `RetryPolicy`
+    ///allows (or the default one, if none is set).
+    pub fn into_stream(
+        self,
+        token: &auth::Token,
+    ) -> impl Stream<Item = Result<Response<Tweet>, error::Error>> {
+        self.stream_until(token, None)
+    }
+
+    ///Converts this search into a `Stream` of tweets like `into_stream`, which stops once it
+    ///reaches the given bound.
+    ///
+    ///Since a date bound stops the stream at the first tweet older than it, it's only useful with
+    ///the default `Recent` result type, where results are ordered newest-first.
+    pub fn into_stream_until(
+        self,
+        token: &auth::Token,
+        bound: StopAt,
+    ) -> impl Stream<Item = Result<Response<Tweet>, error::Error>> {
+        self.stream_until(token, Some(bound))
+    }
+
+    ///Helper function to build the `Stream` for `into_stream` and `into_stream_until`.
+    fn stream_until(
+        self,
+        token: &auth::Token,
+        bound: Option<StopAt>,
+    ) -> impl Stream<Item = Result<Response<Tweet>, error::Error>> {
+        let bound = StreamBound::new(bound);
+        let mut params = self.into_params();
+        if let Some(since_id) = bound.since_id() {
+            params.add_param_ref("since_id", since_id.to_string());
+        }
+
+        let token = token.clone();
+        stream::try_unfold(Some((params, bound, None)), move |state| {
+            let token = token.clone();
+            async move {
+                let (mut params, mut bound, oldest) = match state {
+                    Some(state) => state,
+                    None => return Ok::<_, error::Error>(None),
+                };
+                let page = load_search_page(&params, &token).await?;
+
+                // a tweet on the boundary between pages could be returned with both of them
+                let mut page = Response::map(page, |page| page.statuses)
+                    .into_iter()
+                    .filter(|tweet| !matches!(oldest, Some(oldest) if tweet.id >= oldest))
+                    .collect::<Vec<_>>();
+                let oldest = match page.iter().map(|tweet| tweet.id).min() {
+                    Some(oldest) => oldest,
+                    None => return Ok(None),
+                };
+
+                let done = bound.trim(&mut page);
+                params.add_param_ref("max_id", (oldest - 1).to_string());
+                Ok(Some((
+                    page,
+                    if done {
+                        None
+                    } else {
+                        Some((params, bound, Some(oldest)))
+                    },
+                )))
+            }
+        })
+        .map_ok(|page| stream::iter(page).map(Ok))
+        .try_flatten()
+    }
+
+    ///Helper function to turn the search terms into the parameters to send.
+    fn into_params(self) -> ParamList {
+        ParamList::new()
             .extended_tweets()
             .add_param("q", self.query)
             .add_opt_param("lang", self.lang)
@@ -195,16 +370,186 @@ impl SearchBuilder {
                     Distance::Miles(r) => format!("{:.6},{:.6},{}mi", lat, lon, r),
                     Distance::Kilometers(r) => format!("{:.6},{:.6},{}km", lat, lon, r),
                 }),
-            );
+            )
+    }
+}
 
-        let req = get(links::statuses::SEARCH, token, Some(&params));
-        let mut resp = request_with_json_response::<SearchResult>(req).await?;
+///Loads a page of search results with the given parameters, waiting for the rate limit to reset
+///and trying again if it's been reached (as far as the current `RetryPolicy` allows).
+async fn load_search_page(
+    params: &ParamList,
+    token: &auth::Token,
+) -> Result<Response<SearchResult>, error::Error> {
+    wait_for_rate_limit(|| {
+        let req = get(links::statuses::SEARCH, token, Some(params));
+        request_with_json_response::<SearchResult>(req)
+    })
+    .await
+}
 
-        resp.response.params = Some(params);
-        Ok(resp)
+///Begin setting up a search of the premium 30-day or full-archive search APIs with the given
+///query, using the dev environment with the given label.
+///
+///The query uses the premium search operators, which are described in [Twitter's
+///documentation][premium-doc].
+///
+///[premium-doc]: https://developer.twitter.com/en/docs/twitter-api/premium/search-api/guides/operators
+pub fn archive_search<S: Into<CowStr>, L: Into<CowStr>>(
+    query: S,
+    product: ArchiveProduct,
+    label: L,
+) -> ArchiveSearchBuilder {
+    ArchiveSearchBuilder {
+        query: query.into(),
+        product,
+        label: label.into(),
+        from_date: None,
+        to_date: None,
+        max_results: None,
+        next: None,
     }
 }
 
+///Represents which premium search API to search with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArchiveProduct {
+    ///Search the tweets from the last 30 days.
+    ThirtyDay,
+    ///Search every tweet since 2006.
+    FullArchive,
+}
+
+///Display impl that turns the variants into the names used in the search URLs.
+impl fmt::Display for ArchiveProduct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArchiveProduct::ThirtyDay => write!(f, "30day"),
+            ArchiveProduct::FullArchive => write!(f, "fullarchive"),
+        }
+    }
+}
+
+///Represents a premium historical search query before being sent.
+#[derive(Debug, Clone)]
+#[must_use = "ArchiveSearchBuilder is lazy and won't do anything unless `call`ed"]
+pub struct ArchiveSearchBuilder {
+    ///The text to search for.
+    query: CowStr,
+    product: ArchiveProduct,
+    label: CowStr,
+    from_date: Option<chrono::DateTime<chrono::Utc>>,
+    to_date: Option<chrono::DateTime<chrono::Utc>>,
+    max_results: Option<u32>,
+    next: Option<String>,
+}
+
+impl ArchiveSearchBuilder {
+    ///Restricts results to those posted at or after the given time. Twitter only looks at the
+    ///minute, so seconds are dropped. The default is 30 days before `to_date`.
+    pub fn from_date(self, from_date: chrono::DateTime<chrono::Utc>) -> Self {
+        ArchiveSearchBuilder {
+            from_date: Some(from_date),
+            ..self
+        }
+    }
+
+    ///Restricts results to those posted before the given time. Twitter only looks at the minute,
+    ///so seconds are dropped. The default is the current time.
+    pub fn to_date(self, to_date: chrono::DateTime<chrono::Utc>) -> Self {
+        ArchiveSearchBuilder {
+            to_date: Some(to_date),
+            ..self
+        }
+    }
+
+    ///Set the number of tweets to return per-page, from 10 up to a maximum of 500 (or 100 for the
+    ///sandbox tier). The default is 100.
+    pub fn max_results(self, max_results: u32) -> Self {
+        ArchiveSearchBuilder {
+            max_results: Some(max_results),
+            ..self
+        }
+    }
+
+    ///Sets the `next` token of the page to load, as given in an earlier `ArchiveSearchResult`.
+    ///
+    ///This can be used to page through results manually with `call`, or to resume a search that
+    ///was interrupted. The rest of the search terms need to be the same as the ones that gave the
+    ///token.
+    pub fn next(self, next: String) -> Self {
+        ArchiveSearchBuilder {
+            next: Some(next),
+            ..self
+        }
+    }
+
+    ///Loads the page of results given by the `next` token, or the first page if no token was set.
+    pub async fn call(
+        &self,
+        token: &auth::Token,
+    ) -> Result<Response<ArchiveSearchResult>, error::Error> {
+        let params = ParamList::new()
+            .add_param("query", self.query.clone())
+            .add_opt_param(
+                "fromDate",
+                self.from_date
+                    .map(|d| d.format(ARCHIVE_DATE_FORMAT).to_string()),
+            )
+            .add_opt_param(
+                "toDate",
+                self.to_date
+                    .map(|d| d.format(ARCHIVE_DATE_FORMAT).to_string()),
+            )
+            .add_opt_param("maxResults", self.max_results.map_string())
+            .add_opt_param("next", self.next.clone());
+
+        let link = format!(
+            "{}/{}/{}.json",
+            links::statuses::PREMIUM_SEARCH_STEM,
+            self.product,
+            percent_encode(&self.label)
+        );
+        let req = get(&link, token, Some(&params));
+        request_with_json_response(req).await
+    }
+
+    ///Converts this search into a `Stream` of tweets, which automatically loads the next page as
+    ///needed until there are no more results.
+    pub fn into_stream(
+        self,
+        token: &auth::Token,
+    ) -> impl Stream<Item = Result<Response<Tweet>, error::Error>> {
+        let token = token.clone();
+        stream::try_unfold(Some(self), move |search| {
+            let token = token.clone();
+            async move {
+                let search = match search {
+                    Some(search) => search,
+                    None => return Ok::<_, error::Error>(None),
+                };
+                let page = search.call(&token).await?;
+                let next = page.next.clone().map(|next| search.next(next));
+                Ok(Some((Response::map(page, |page| page.results), next)))
+            }
+        })
+        .map_ok(|page| stream::iter(page).map(Ok))
+        .try_flatten()
+    }
+}
+
+///The date format used by the premium search APIs.
+const ARCHIVE_DATE_FORMAT: &str = "%Y%m%d%H%M";
+
+///Represents a page of premium historical search results.
+#[derive(Debug, Deserialize)]
+pub struct ArchiveSearchResult {
+    ///The tweets in this page of results.
+    pub results: Vec<Tweet>,
+    ///The token to load the next page of results with `ArchiveSearchBuilder::next`, if there is
+    ///one.
+    pub next: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct RawSearch {
     search_metadata: RawSearchMetaData,
@@ -295,3 +640,123 @@ impl SearchResult {
         Ok(resp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::testing::MockTwitter;
+
+    #[tokio::test]
+    async fn archive_search_pages() {
+        let twitter = MockTwitter::new();
+        let token = crate::Token::Bearer("archive".to_string());
+        let search = archive_search("rustlang", ArchiveProduct::FullArchive, "dev")
+            .from_date(
+                chrono::Utc
+                    .with_ymd_and_hms(2015, 5, 1, 12, 30, 15)
+                    .unwrap(),
+            )
+            .max_results(10);
+
+        let tweets = twitter
+            .run(search.into_stream(&token).try_collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(tweets.len(), 20);
+
+        let requests = twitter.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/1.1/tweets/search/fullarchive/dev.json");
+        assert_eq!(
+            requests[0].params.get("fromDate").map(|d| d.as_str()),
+            Some("201505011230")
+        );
+        assert_eq!(
+            requests[0].params.get("maxResults").map(|m| m.as_str()),
+            Some("10")
+        );
+        assert_eq!(requests[0].params.get("next"), None);
+        assert!(requests[1].params.contains_key("next"));
+
+        // the label is encoded so it stays within its path segment
+        let search = archive_search("rustlang", ArchiveProduct::ThirtyDay, "dev/../env");
+        twitter.run(search.call(&token)).await.unwrap();
+        assert_eq!(
+            twitter.requests()[2].path,
+            "/1.1/tweets/search/30day/dev%2F..%2Fenv.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn search_stream() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let all = search("rustlang")
+                    .count(6)
+                    .into_stream(&token)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(all.len(), 20);
+                assert!(all.windows(2).all(|w| w[0].id > w[1].id));
+
+                // four pages of tweets, then the empty page that ends the stream
+                let requests = twitter.requests();
+                assert_eq!(requests.len(), 5);
+                assert_eq!(requests[0].params.get("max_id"), None);
+                assert_eq!(
+                    requests[1].params.get("max_id"),
+                    Some(&(all[5].id - 1).to_string())
+                );
+
+                let first = search("rustlang")
+                    .count(6)
+                    .into_stream_until(&token, StopAt::Count(8))
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(first.len(), 8);
+                assert_eq!(first[7].id, all[7].id);
+                assert_eq!(twitter.requests().len(), 7);
+
+                let since = all[10].created_at;
+                let recent = search("rustlang")
+                    .count(6)
+                    .into_stream_until(&token, StopAt::Date(since))
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert!(recent.len() >= 11);
+                assert!(recent.iter().all(|tweet| tweet.created_at >= since));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn search_stream_waits_for_rate_limit() {
+        tokio::time::pause();
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+
+        let tweets = twitter
+            .run(async {
+                let mut stream = Box::pin(search("rustlang").count(10).into_stream(&token));
+                let mut tweets = vec![stream.try_next().await.unwrap().unwrap()];
+                // rate-limit the search for older tweets
+                twitter.inject_rate_limit("/1.1/search/tweets.json", 1);
+                while let Some(tweet) = stream.try_next().await.unwrap() {
+                    tweets.push(tweet);
+                }
+                tweets
+            })
+            .await;
+
+        assert_eq!(tweets.len(), 20);
+        // three pages, plus the rate-limited request that was retried
+        assert_eq!(twitter.requests().len(), 4);
+    }
+}
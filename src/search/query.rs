@@ -0,0 +1,521 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A typed builder for search queries, which checks them before they're sent.
+
+use std::fmt;
+use std::ops::Not;
+
+use super::Distance;
+
+/// The longest query the standard search API accepts, in characters.
+pub const MAX_QUERY_LENGTH: usize = 500;
+
+/// A search query, built from the operators the standard search API understands.
+///
+/// Building a query by pasting strings together means a typo in an operator, or a screen name
+/// with a stray space in it, is only discovered when Twitter returns the wrong results (or none at
+/// all). A `Query` is built from its parts instead, and `build` checks each part before rendering
+/// it to the string Twitter expects, along with checking that the whole query fits in Twitter's
+/// length limit.
+///
+/// Start with one of the constructor functions, then combine queries with `and` and `or`, or
+/// negate them with the `!` operator:
+///
+/// ```rust
+/// use egg_mode::search::{Filter, Query};
+///
+/// let query = Query::from_user("rustlang")
+///     .and(!Query::filter(Filter::Retweets))
+///     .and(Query::keyword("release").or(Query::hashtag("rustlang")))
+///     .and(Query::lang("en"));
+///
+/// assert_eq!(
+///     query.build().unwrap(),
+///     "from:rustlang -filter:retweets (release OR #rustlang) lang:en"
+/// );
+/// ```
+///
+/// A finished `Query` can be given to [`search_query`] to start a search with it.
+///
+/// [`search_query`]: fn.search_query.html
+///
+/// The operators are described in [Twitter's documentation][search-doc]. Note that the premium
+/// and API v2 search APIs use a different set of operators, so queries built here are only meant
+/// for the standard search API.
+///
+/// [search-doc]: https://developer.twitter.com/en/docs/twitter-api/v1/rules-and-filtering/search-operators
+#[derive(Debug, Clone)]
+pub enum Query {
+    /// A single word, which needs to appear in the tweet.
+    Keyword(String),
+    /// An exact phrase, which needs to appear in the tweet as given.
+    Phrase(String),
+    /// Tweets posted by the given user (`from:`).
+    From(String),
+    /// Tweets in reply to the given user (`to:`).
+    To(String),
+    /// Tweets mentioning the given user (`@`).
+    Mention(String),
+    /// Tweets with the given hashtag (`#`).
+    Hashtag(String),
+    /// Tweets with a link containing the given text (`url:`).
+    Url(String),
+    /// Tweets matching the given filter (`filter:`).
+    Filter(Filter),
+    /// Tweets in the given language, as a two-letter language code (`lang:`).
+    Lang(String),
+    /// Tweets posted within the given radius of the given latitude and longitude (`geocode:`).
+    Geocode(f32, f32, Distance),
+    /// Tweets posted on or after the given date (`since:`).
+    Since(chrono::NaiveDate),
+    /// Tweets posted before the given date (`until:`).
+    Until(chrono::NaiveDate),
+    /// Tweets matching every one of the given queries.
+    All(Vec<Query>),
+    /// Tweets matching any of the given queries.
+    Any(Vec<Query>),
+    /// Tweets not matching the given query.
+    Not(Box<Query>),
+}
+
+/// The kinds of tweets that can be picked out with the `filter:` operator.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// Tweets with links.
+    Links,
+    /// Tweets with images.
+    Images,
+    /// Tweets with images or videos.
+    Media,
+    /// Tweets with videos uploaded to Twitter.
+    NativeVideo,
+    /// Tweets with Periscope broadcasts.
+    Periscope,
+    /// Tweets with Vine videos.
+    Vine,
+    /// Tweets with links to images hosted by Twitter.
+    Twimg,
+    /// Retweets, including quote tweets.
+    Retweets,
+    /// Retweets made with the retweet button.
+    NativeRetweets,
+    /// Replies to other tweets.
+    Replies,
+    /// Tweets posted by verified users.
+    Verified,
+    /// Tweets that haven't been marked as potentially sensitive.
+    Safe,
+    /// Tweets linking to news articles.
+    News,
+}
+
+impl Filter {
+    /// Returns the name of this filter, as used in a query.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Filter::Links => "links",
+            Filter::Images => "images",
+            Filter::Media => "media",
+            Filter::NativeVideo => "native_video",
+            Filter::Periscope => "periscope",
+            Filter::Vine => "vine",
+            Filter::Twimg => "twimg",
+            Filter::Retweets => "retweets",
+            Filter::NativeRetweets => "nativeretweets",
+            Filter::Replies => "replies",
+            Filter::Verified => "verified",
+            Filter::Safe => "safe",
+            Filter::News => "news",
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Represents a problem with a `Query` that keeps it from being sent to Twitter.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum QueryError {
+    /// A part of the query was empty, like a keyword with no text or a group with no queries in
+    /// it. The enclosed value names the part of the query that was empty.
+    #[error("Empty {} in search query", _0)]
+    Empty(&'static str),
+    /// A part of the query had a value Twitter wouldn't accept, like a screen name with a space in
+    /// it. The enclosed values name the part of the query and give the value that was rejected.
+    #[error("Invalid {} in search query: {:?}", _0, _1)]
+    Invalid(&'static str, String),
+    /// The rendered query was longer than the limit. The enclosed values are the length of the
+    /// query and the limit, in characters.
+    #[error("Search query is {} characters long, over the limit of {}", _0, _1)]
+    TooLong(usize, usize),
+}
+
+impl Query {
+    /// Creates a query for tweets containing the given word.
+    pub fn keyword(word: impl Into<String>) -> Query {
+        Query::Keyword(word.into())
+    }
+
+    /// Creates a query for tweets containing the given exact phrase.
+    pub fn phrase(phrase: impl Into<String>) -> Query {
+        Query::Phrase(phrase.into())
+    }
+
+    /// Creates a query for tweets posted by the given user.
+    ///
+    /// The screen name can be given with or without a leading `@`.
+    pub fn from_user(screen_name: impl Into<String>) -> Query {
+        Query::From(screen_name.into())
+    }
+
+    /// Creates a query for tweets in reply to the given user.
+    ///
+    /// The screen name can be given with or without a leading `@`.
+    pub fn to_user(screen_name: impl Into<String>) -> Query {
+        Query::To(screen_name.into())
+    }
+
+    /// Creates a query for tweets mentioning the given user.
+    ///
+    /// The screen name can be given with or without a leading `@`.
+    pub fn mention(screen_name: impl Into<String>) -> Query {
+        Query::Mention(screen_name.into())
+    }
+
+    /// Creates a query for tweets with the given hashtag.
+    ///
+    /// The hashtag can be given with or without a leading `#`.
+    pub fn hashtag(hashtag: impl Into<String>) -> Query {
+        Query::Hashtag(hashtag.into())
+    }
+
+    /// Creates a query for tweets with a link containing the given text.
+    pub fn url(text: impl Into<String>) -> Query {
+        Query::Url(text.into())
+    }
+
+    /// Creates a query for tweets matching the given filter.
+    pub fn filter(filter: Filter) -> Query {
+        Query::Filter(filter)
+    }
+
+    /// Creates a query for tweets in the given language.
+    pub fn lang(lang: impl Into<String>) -> Query {
+        Query::Lang(lang.into())
+    }
+
+    /// Creates a query for tweets posted within the given radius of the given coordinate.
+    pub fn geocode(latitude: f32, longitude: f32, radius: Distance) -> Query {
+        Query::Geocode(latitude, longitude, radius)
+    }
+
+    /// Creates a query for tweets posted on or after the given date.
+    pub fn since(date: chrono::NaiveDate) -> Query {
+        Query::Since(date)
+    }
+
+    /// Creates a query for tweets posted before the given date.
+    pub fn until(date: chrono::NaiveDate) -> Query {
+        Query::Until(date)
+    }
+
+    /// Creates a query for tweets matching every one of the given queries.
+    pub fn all<I: IntoIterator<Item = Query>>(queries: I) -> Query {
+        Query::All(queries.into_iter().collect())
+    }
+
+    /// Creates a query for tweets matching any of the given queries.
+    pub fn any<I: IntoIterator<Item = Query>>(queries: I) -> Query {
+        Query::Any(queries.into_iter().collect())
+    }
+
+    /// Combines this query with the given one, so that tweets need to match both.
+    pub fn and(self, other: Query) -> Query {
+        match self {
+            Query::All(mut queries) => {
+                queries.push(other);
+                Query::All(queries)
+            }
+            query => Query::All(vec![query, other]),
+        }
+    }
+
+    /// Combines this query with the given one, so that tweets need to match either.
+    pub fn or(self, other: Query) -> Query {
+        match self {
+            Query::Any(mut queries) => {
+                queries.push(other);
+                Query::Any(queries)
+            }
+            query => Query::Any(vec![query, other]),
+        }
+    }
+
+    /// Checks this query and renders it to a query string, which needs to fit in the standard
+    /// search API's limit of 500 characters.
+    pub fn build(&self) -> Result<String, QueryError> {
+        self.build_with_limit(MAX_QUERY_LENGTH)
+    }
+
+    /// Checks this query and renders it to a query string, which needs to fit in the given number
+    /// of characters.
+    pub fn build_with_limit(&self, limit: usize) -> Result<String, QueryError> {
+        let mut out = String::new();
+        self.render(&mut out)?;
+        let length = out.chars().count();
+        if length > limit {
+            return Err(QueryError::TooLong(length, limit));
+        }
+        Ok(out)
+    }
+
+    /// Renders this query onto the end of the given string.
+    fn render(&self, out: &mut String) -> Result<(), QueryError> {
+        match self.simplified() {
+            Query::Keyword(word) => {
+                if word.is_empty() {
+                    return Err(QueryError::Empty("keyword"));
+                }
+                // operators, phrases, and negation each have their own variant
+                if word.contains(|c: char| c.is_whitespace() || "\":()".contains(c))
+                    || word.starts_with('-')
+                    || word == "OR"
+                {
+                    return Err(QueryError::Invalid("keyword", word.clone()));
+                }
+                out.push_str(word);
+            }
+            Query::Phrase(phrase) => {
+                if phrase.trim().is_empty() {
+                    return Err(QueryError::Empty("phrase"));
+                }
+                if phrase.contains('"') {
+                    return Err(QueryError::Invalid("phrase", phrase.clone()));
+                }
+                out.push('"');
+                out.push_str(phrase);
+                out.push('"');
+            }
+            Query::From(name) => push_operator(out, "from:", check_screen_name(name)?),
+            Query::To(name) => push_operator(out, "to:", check_screen_name(name)?),
+            Query::Mention(name) => push_operator(out, "@", check_screen_name(name)?),
+            Query::Hashtag(tag) => {
+                let tag = tag.strip_prefix('#').unwrap_or(tag);
+                if tag.is_empty() {
+                    return Err(QueryError::Empty("hashtag"));
+                }
+                if !tag.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(QueryError::Invalid("hashtag", tag.to_string()));
+                }
+                push_operator(out, "#", tag);
+            }
+            Query::Url(text) => {
+                if text.is_empty() {
+                    return Err(QueryError::Empty("url"));
+                }
+                if text.contains(|c: char| c.is_whitespace() || c == '"') {
+                    return Err(QueryError::Invalid("url", text.clone()));
+                }
+                push_operator(out, "url:", text);
+            }
+            Query::Filter(filter) => push_operator(out, "filter:", filter.as_str()),
+            Query::Lang(lang) => {
+                if lang.is_empty() {
+                    return Err(QueryError::Empty("lang"));
+                }
+                if lang.len() > 8 || !lang.chars().all(|c| c.is_ascii_alphabetic() || c == '-') {
+                    return Err(QueryError::Invalid("lang", lang.clone()));
+                }
+                push_operator(out, "lang:", lang);
+            }
+            Query::Geocode(lat, lon, radius) => {
+                let (r, unit) = match *radius {
+                    Distance::Miles(r) => (r, "mi"),
+                    Distance::Kilometers(r) => (r, "km"),
+                };
+                let geocode = format!("{:.6},{:.6},{}{}", lat, lon, r, unit);
+                let valid =
+                    (-90.0..=90.0).contains(lat) && (-180.0..=180.0).contains(lon) && r > 0.0;
+                if !valid {
+                    return Err(QueryError::Invalid("geocode", geocode));
+                }
+                push_operator(out, "geocode:", &geocode);
+            }
+            Query::Since(date) => {
+                push_operator(out, "since:", &date.format("%Y-%m-%d").to_string())
+            }
+            Query::Until(date) => {
+                push_operator(out, "until:", &date.format("%Y-%m-%d").to_string())
+            }
+            Query::All(queries) => {
+                if queries.is_empty() {
+                    return Err(QueryError::Empty("group"));
+                }
+                for (idx, query) in queries.iter().enumerate() {
+                    if idx > 0 {
+                        out.push(' ');
+                    }
+                    // terms next to each other are already ANDed together, so only OR groups
+                    // need parentheses
+                    match query.simplified() {
+                        Query::Any(_) => query.render_grouped(out)?,
+                        _ => query.render(out)?,
+                    }
+                }
+            }
+            Query::Any(queries) => {
+                if queries.is_empty() {
+                    return Err(QueryError::Empty("group"));
+                }
+                for (idx, query) in queries.iter().enumerate() {
+                    if idx > 0 {
+                        out.push_str(" OR ");
+                    }
+                    match query.simplified() {
+                        Query::All(_) => query.render_grouped(out)?,
+                        _ => query.render(out)?,
+                    }
+                }
+            }
+            Query::Not(query) => {
+                out.push('-');
+                match query.simplified() {
+                    Query::All(_) | Query::Any(_) => query.render_grouped(out)?,
+                    _ => query.render(out)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the query this one renders the same as, without double negations or groups of a
+    /// single query, so that the query around it can tell whether it needs parentheses.
+    fn simplified(&self) -> &Query {
+        match self {
+            Query::All(group) | Query::Any(group) if group.len() == 1 => group[0].simplified(),
+            Query::Not(query) => match query.simplified() {
+                // two negations cancel each other out
+                Query::Not(inner) => inner.simplified(),
+                _ => self,
+            },
+            _ => self,
+        }
+    }
+
+    /// Renders this query onto the end of the given string, wrapped in parentheses.
+    fn render_grouped(&self, out: &mut String) -> Result<(), QueryError> {
+        out.push('(');
+        self.render(out)?;
+        out.push(')');
+        Ok(())
+    }
+}
+
+impl Not for Query {
+    type Output = Query;
+
+    /// Negates this query, so that it matches the tweets it wouldn't match before.
+    fn not(self) -> Query {
+        Query::Not(Box::new(self))
+    }
+}
+
+/// Pushes the given operator and value onto the end of the given string.
+fn push_operator(out: &mut String, operator: &str, value: &str) {
+    out.push_str(operator);
+    out.push_str(value);
+}
+
+/// Checks that the given screen name (with or without a leading `@`) is one Twitter would allow,
+/// and returns it without the `@`.
+fn check_screen_name(name: &str) -> Result<&str, QueryError> {
+    let name = name.strip_prefix('@').unwrap_or(name);
+    if name.is_empty() {
+        return Err(QueryError::Empty("screen name"));
+    }
+    if name.len() > 15 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(QueryError::Invalid("screen name", name.to_string()));
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_groups() {
+        let query = Query::any(vec![
+            Query::keyword("a"),
+            Query::keyword("b").and(Query::phrase("c d")),
+        ])
+        .and(!Query::all(vec![
+            Query::mention("@x"),
+            Query::hashtag("#y"),
+        ]))
+        .and(!!Query::url("example.com"))
+        .and(Query::since(
+            chrono::NaiveDate::from_ymd_opt(2020, 6, 1).unwrap(),
+        ))
+        .and(Query::geocode(37.75, -122.5, Distance::Miles(1.0)));
+
+        assert_eq!(
+            query.build().unwrap(),
+            "(a OR (b \"c d\")) -(@x #y) url:example.com since:2020-06-01 \
+             geocode:37.750000,-122.500000,1mi"
+        );
+
+        // double negations and single-query groups don't hide the groups inside them
+        let either = || Query::keyword("a").or(Query::keyword("b"));
+        assert_eq!(
+            (!!either()).and(Query::keyword("c")).build().unwrap(),
+            "(a OR b) c"
+        );
+        assert_eq!(
+            Query::any(vec![either()])
+                .and(Query::keyword("c"))
+                .build()
+                .unwrap(),
+            "(a OR b) c"
+        );
+        assert_eq!((!!!either()).build().unwrap(), "-(a OR b)");
+        assert_eq!(
+            (!Query::all(vec![!!Query::keyword("a")])).build().unwrap(),
+            "-a"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_parts() {
+        assert_eq!(
+            Query::from_user("rust lang").build(),
+            Err(QueryError::Invalid("screen name", "rust lang".to_string()))
+        );
+        assert_eq!(
+            Query::keyword("").build(),
+            Err(QueryError::Empty("keyword"))
+        );
+        assert_eq!(
+            Query::keyword("from:x").build(),
+            Err(QueryError::Invalid("keyword", "from:x".to_string()))
+        );
+        assert_eq!(
+            Query::keyword("(a").build(),
+            Err(QueryError::Invalid("keyword", "(a".to_string()))
+        );
+        assert_eq!(Query::any(vec![]).build(), Err(QueryError::Empty("group")));
+        assert!(Query::geocode(91.0, 0.0, Distance::Kilometers(5.0))
+            .build()
+            .is_err());
+
+        let long = Query::all((0..100).map(|_| Query::keyword("rustlang")));
+        assert_eq!(long.build(), Err(QueryError::TooLong(899, 500)));
+        assert!(long.build_with_limit(1024).is_ok());
+    }
+}
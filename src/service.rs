@@ -15,15 +15,34 @@
 //! [privacy]: fn.privacy.html
 //! [rate-limit status]: fn.rate_limit_status.html
 //! [config]: fn.config.html
+//!
+//! This module also contains the [`RateLimitTracker`], which keeps the latest rate-limit
+//! information egg-mode has received for each method, so you can check whether you can call a
+//! method before you try.
+//!
+//! [`RateLimitTracker`]: struct.RateLimitTracker.html
+//!
+//! Using that same information, egg-mode can also hold requests until their rate limit has room
+//! for them, instead of sending them only to receive an error. This is disabled by default; see
+//! [`set_throttle`] for details.
+//!
+//! [`set_throttle`]: fn.set_throttle.html
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::result::Result as StdResult;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use serde::de::Error;
 use serde::{Deserialize, Deserializer};
 use serde_json;
 
+use crate::auth::raw::RateLimitKey;
 use crate::common::*;
 use crate::error::{
     Error::{InvalidResponse, MissingValue},
@@ -257,8 +276,13 @@ impl<'de> Deserialize<'de> for RateLimitStatus {
     }
 }
 
-///Method identifiers, used by `rate_limit_status` to return rate-limit information.
-enum Method {
+///Method identifiers, used by `rate_limit_status` and `RateLimitTracker` to organize rate-limit
+///information.
+///
+///Each of the `*Method` enums in this module can be converted into a `Method`, so functions that
+///take an `impl Into<Method>` can be given a `TweetMethod`, `UserMethod`, etc directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
     ///A method from the `direct` module.
     Direct(DirectMethod),
     ///A method from the `place` module.
@@ -275,6 +299,72 @@ enum Method {
     List(ListMethod),
 }
 
+impl Method {
+    ///Attempts to determine which method a request was sent to, from the path of its URL.
+    pub(crate) fn from_path(path: &str) -> Option<Method> {
+        let path = &path[path.find("/1.1/")? + 4..];
+        let path = path.strip_suffix(".json").unwrap_or(path);
+
+        if let Ok(method) = path.parse() {
+            return Some(method);
+        }
+
+        // endpoints that take an ID as part of their path are listed with a placeholder, and
+        // the ones that also take it as a parameter are listed without the ".json"
+        match path {
+            "/statuses/show" => Some(Method::Tweet(TweetMethod::Show)),
+            "/users/show" => Some(Method::User(UserMethod::Show)),
+            _ if path.starts_with("/statuses/retweets/") => {
+                Some(Method::Tweet(TweetMethod::RetweetsOf))
+            }
+            _ if path.starts_with("/geo/id/") => Some(Method::Place(PlaceMethod::Show)),
+            _ => None,
+        }
+    }
+}
+
+impl From<DirectMethod> for Method {
+    fn from(method: DirectMethod) -> Method {
+        Method::Direct(method)
+    }
+}
+
+impl From<PlaceMethod> for Method {
+    fn from(method: PlaceMethod) -> Method {
+        Method::Place(method)
+    }
+}
+
+impl From<SearchMethod> for Method {
+    fn from(method: SearchMethod) -> Method {
+        Method::Search(method)
+    }
+}
+
+impl From<ServiceMethod> for Method {
+    fn from(method: ServiceMethod) -> Method {
+        Method::Service(method)
+    }
+}
+
+impl From<TweetMethod> for Method {
+    fn from(method: TweetMethod) -> Method {
+        Method::Tweet(method)
+    }
+}
+
+impl From<UserMethod> for Method {
+    fn from(method: UserMethod) -> Method {
+        Method::User(method)
+    }
+}
+
+impl From<ListMethod> for Method {
+    fn from(method: ListMethod) -> Method {
+        Method::List(method)
+    }
+}
+
 impl FromStr for Method {
     type Err = ();
 
@@ -340,8 +430,322 @@ impl FromStr for Method {
     }
 }
 
+///A shared record of the latest rate-limit information for each method, for each `Token`.
+///
+///Every `Response` egg-mode returns carries the rate-limit information for the method that was
+///called, but it's up to you to keep track of it. The `RateLimitTracker` returned by
+///[`rate_limit_tracker`] does this for you: every response egg-mode receives for a method listed
+///in this module's `*Method` enums is recorded in it, keyed by the method and by the `Token` the
+///request was signed with. (Rate limits are tracked per-user for Access tokens, and per-app for
+///Bearer tokens, just like Twitter does.)
+///
+///[`rate_limit_tracker`]: fn.rate_limit_tracker.html
+///
+///Before making a call, you can ask the tracker whether you have calls remaining for a method, and
+///if not, when the rate-limit window resets. The tracker only knows about methods that have been
+///called (or that were loaded with `seed`), and assumes that any method it hasn't seen can be
+///called freely. To fill in every method at once, you can `seed` it with the results of
+///[`rate_limit_status`].
+///
+///[`rate_limit_status`]: fn.rate_limit_status.html
+///
+///`RateLimitTracker` is a handle to shared state, so clones of it refer to the same records.
+///
+///# Example
+///
+///```rust,no_run
+///# use egg_mode::Token;
+///use egg_mode::service::{self, UserMethod};
+///# #[tokio::main]
+///# async fn main() {
+///# let token: Token = unimplemented!();
+///let tracker = service::rate_limit_tracker();
+///tracker.seed(&service::rate_limit_status(&token).await.unwrap(), &token);
+///
+///match tracker.available_at(UserMethod::Lookup, &token) {
+///    None => println!("can look up users now"),
+///    Some(reset) => println!("can look up users again at {}", reset),
+///}
+///# }
+///```
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitTracker {
+    limits: Arc<Mutex<Limits>>,
+}
+
+///The records kept by a `RateLimitTracker`.
+#[derive(Debug, Default)]
+struct Limits {
+    ///The latest rate-limit information Twitter gave for each method and key.
+    reported: HashMap<(RateLimitKey, Method), RateLimit>,
+    ///The number of calls reserved for each method and key whose requests haven't been answered
+    ///yet. These are kept apart from what Twitter reports, so a request that fails without a
+    ///response doesn't use up a call for the rest of the window.
+    reserved: HashMap<(RateLimitKey, Method), i32>,
+}
+
+impl Limits {
+    ///Returns the number of calls left for the given method and key once the reserved calls are
+    ///made, along with when the current window resets, if the window is still current.
+    fn available(&self, key: &(RateLimitKey, Method)) -> Option<(i32, i32)> {
+        let limit = self.reported.get(key)?;
+        if i64::from(limit.reset) <= chrono::Utc::now().timestamp() {
+            return None;
+        }
+        let reserved = self.reserved.get(key).copied().unwrap_or(0);
+        Some((limit.remaining - reserved, limit.reset))
+    }
+}
+
+///One of the calls remaining for a method, reserved by `RateLimitTracker::acquire` for a request
+///that's about to be sent.
+///
+///The reservation is given back when this is dropped. Drop it after recording the rate-limit
+///information from the response, if there was one, so that the call isn't counted twice.
+#[must_use]
+pub(crate) struct Reservation {
+    tracker: RateLimitTracker,
+    key: (RateLimitKey, Method),
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut limits = self.tracker.lock();
+        if let Some(reserved) = limits.reserved.get_mut(&self.key) {
+            *reserved -= 1;
+            if *reserved <= 0 {
+                limits.reserved.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl RateLimitTracker {
+    ///Creates a new, empty `RateLimitTracker`.
+    ///
+    ///Note that only the tracker returned by [`rate_limit_tracker`] is updated automatically; a
+    ///tracker created with this function only knows what is given to `record` and `seed`.
+    ///
+    ///[`rate_limit_tracker`]: fn.rate_limit_tracker.html
+    pub fn new() -> RateLimitTracker {
+        RateLimitTracker::default()
+    }
+
+    ///Records the given rate-limit information for the given method and `Token`.
+    ///
+    ///If the tracker already has newer information for this method - from a later rate-limit
+    ///window, or with fewer calls remaining in the same window - the given information is ignored.
+    ///Calls that egg-mode has reserved for requests in flight aren't counted here, so this only
+    ///compares what Twitter has reported.
+    pub fn record(&self, method: impl Into<Method>, token: &auth::Token, rate_limit: RateLimit) {
+        self.record_for_key(RateLimitKey::new(token), method.into(), rate_limit);
+    }
+
+    ///Records the rate-limit information for every method in the given `RateLimitStatus`, as
+    ///loaded with the given `Token`.
+    pub fn seed(&self, status: &RateLimitStatus, token: &auth::Token) {
+        let key = RateLimitKey::new(token);
+        let methods = status
+            .direct
+            .iter()
+            .map(|(m, r)| (Method::from(*m), r))
+            .chain(status.place.iter().map(|(m, r)| (Method::from(*m), r)))
+            .chain(status.search.iter().map(|(m, r)| (Method::from(*m), r)))
+            .chain(status.service.iter().map(|(m, r)| (Method::from(*m), r)))
+            .chain(status.tweet.iter().map(|(m, r)| (Method::from(*m), r)))
+            .chain(status.user.iter().map(|(m, r)| (Method::from(*m), r)))
+            .chain(status.list.iter().map(|(m, r)| (Method::from(*m), r)));
+        for (method, resp) in methods {
+            self.record_for_key(key.clone(), method, resp.rate_limit_status);
+        }
+    }
+
+    ///Returns the latest rate-limit information recorded for the given method and `Token`, if
+    ///any.
+    pub fn get(&self, method: impl Into<Method>, token: &auth::Token) -> Option<RateLimit> {
+        self.lock()
+            .reported
+            .get(&(RateLimitKey::new(token), method.into()))
+            .copied()
+    }
+
+    ///Returns whether the given method can be called with the given `Token` right now, as far as
+    ///this tracker knows.
+    pub fn can_call(&self, method: impl Into<Method>, token: &auth::Token) -> bool {
+        self.available_at(method, token).is_none()
+    }
+
+    ///Returns when the given method can next be called with the given `Token`, as a UTC Unix
+    ///timestamp, or `None` if it can be called right now.
+    ///
+    ///Calls that egg-mode has reserved for requests it's currently sending count against the
+    ///remaining calls.
+    pub fn available_at(&self, method: impl Into<Method>, token: &auth::Token) -> Option<i32> {
+        let key = (RateLimitKey::new(token), method.into());
+        match self.lock().available(&key) {
+            Some((remaining, reset)) if remaining <= 0 => Some(reset),
+            _ => None,
+        }
+    }
+
+    ///Waits until a call to the given method can be made with the given key, reserving one of
+    ///its remaining calls until the returned `Reservation` is dropped. If that would take longer
+    ///than `max_wait`, `Error::RateLimit` is returned instead.
+    pub(crate) async fn acquire(
+        &self,
+        key: &RateLimitKey,
+        method: Method,
+        max_wait: Duration,
+    ) -> Result<Reservation> {
+        let key = (key.clone(), method);
+        loop {
+            let reset = match self.try_acquire(&key) {
+                Some(reset) => reset,
+                None => {
+                    return Ok(Reservation {
+                        tracker: self.clone(),
+                        key,
+                    })
+                }
+            };
+            // wait an extra second, in case our clock is slightly behind Twitter's
+            let wait = i64::from(reset) - chrono::Utc::now().timestamp() + 1;
+            let wait = Duration::from_secs(wait.max(1) as u64);
+            if wait > max_wait {
+                return Err(crate::error::Error::RateLimit(reset));
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    ///Reserves one of the remaining calls for the given method and key, or returns when the
+    ///current rate-limit window resets if there are none left.
+    fn try_acquire(&self, key: &(RateLimitKey, Method)) -> Option<i32> {
+        let mut limits = self.lock();
+        match limits.available(key) {
+            Some((remaining, reset)) if remaining <= 0 => Some(reset),
+            _ => {
+                *limits.reserved.entry(key.clone()).or_insert(0) += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn record_for_key(&self, key: RateLimitKey, method: Method, rate_limit: RateLimit) {
+        if rate_limit.remaining < 0 || rate_limit.reset < 0 {
+            // the rate-limit headers were missing
+            return;
+        }
+
+        let mut limits = self.lock();
+        let newer = match limits.reported.get(&(key.clone(), method)) {
+            Some(old) => {
+                old.reset < rate_limit.reset
+                    || (old.reset == rate_limit.reset && old.remaining >= rate_limit.remaining)
+            }
+            None => true,
+        };
+        if newer {
+            limits.reported.insert((key, method), rate_limit);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Limits> {
+        self.limits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TRACKER: RateLimitTracker = RateLimitTracker::new();
+}
+
+///Returns the `RateLimitTracker` that egg-mode updates with the rate-limit information from every
+///response.
+///
+///See the documentation for [`RateLimitTracker`] for more information.
+///
+///[`RateLimitTracker`]: struct.RateLimitTracker.html
+pub fn rate_limit_tracker() -> RateLimitTracker {
+    TRACKER.clone()
+}
+
+thread_local! {
+    static SCOPED_THROTTLE: RefCell<Option<Option<Duration>>> = const { RefCell::new(None) };
+}
+
+lazy_static::lazy_static! {
+    static ref THROTTLE: ScopedSetting<Option<Duration>> =
+        ScopedSetting::new(None, &SCOPED_THROTTLE);
+}
+
+///Sets whether egg-mode should hold requests until the rate limit for their method has room for
+///them, and for how long.
+///
+///By default, egg-mode sends every request as soon as it's made, even if the last response for
+///that method said that there were no calls left, and you'll receive `Error::RateLimit` from
+///Twitter. Once throttling is enabled, egg-mode checks the [`rate_limit_tracker`] before sending
+///each request for one of the methods in this module's `*Method` enums. If the tracker knows that
+///there are no calls left for that method and `Token`, the request is held until the rate-limit
+///window resets. If the window resets more than `max_wait` from now, `Error::RateLimit` is
+///returned right away, without sending the request.
+///
+///[`rate_limit_tracker`]: fn.rate_limit_tracker.html
+///
+///Every request reserves one of the calls the tracker knows are remaining before it's sent, so
+///concurrent tasks that share a `Token` won't send more requests between them than the rate limit
+///allows.
+///
+///Giving `None` disables throttling. Giving `Some(Duration::from_secs(0))` makes requests that are
+///known to be rate-limited fail immediately, without waiting and without calling Twitter.
+///
+///To enable or disable throttling for only some of your requests, use [`with_throttle`].
+///
+///[`with_throttle`]: fn.with_throttle.html
+pub fn set_throttle(max_wait: Option<Duration>) {
+    THROTTLE.set(max_wait);
+}
+
+///Returns how long egg-mode will hold a request for its rate limit, or `None` if throttling is
+///disabled.
+///
+///If this is called from within a future wrapped by [`with_throttle`], the setting given there is
+///returned. Otherwise, this returns the setting given to [`set_throttle`].
+///
+///[`with_throttle`]: fn.with_throttle.html
+///[`set_throttle`]: fn.set_throttle.html
+pub fn throttle() -> Option<Duration> {
+    THROTTLE.get()
+}
+
+///Runs the given future, throttling any requests it makes with the given setting.
+///
+///Unlike [`set_throttle`], this only affects requests sent while the given future is being
+///polled. Giving `None` disables throttling for the given future, so latency-sensitive work can
+///still fail fast even if throttling has been enabled for the whole process.
+///
+///[`set_throttle`]: fn.set_throttle.html
+pub fn with_throttle<F: Future>(max_wait: Option<Duration>, fut: F) -> WithThrottle<F> {
+    WithThrottle(THROTTLE.scope(max_wait, fut))
+}
+
+///A future that throttles its requests with a specific setting.
+///
+///This type is returned by [`with_throttle`]. See that function's documentation for details.
+///
+///[`with_throttle`]: fn.with_throttle.html
+#[must_use = "futures do nothing unless polled"]
+pub struct WithThrottle<F>(Scoped<Option<Duration>, F>);
+
+impl<F: Future> Future for WithThrottle<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
 ///Method identifiers from the `direct` module, for use by `rate_limit_status`.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DirectMethod {
     ///`direct::show`
     Show,
@@ -352,7 +756,7 @@ pub enum DirectMethod {
 }
 
 ///Method identifiers from the `place` module, for use by `rate_limit_status`.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PlaceMethod {
     ///`place::show`
     Show,
@@ -363,7 +767,7 @@ pub enum PlaceMethod {
 }
 
 ///Method identifiers from the `search` module, for use by `rate_limit_status`.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SearchMethod {
     ///`search::search`
     Search,
@@ -371,7 +775,7 @@ pub enum SearchMethod {
 
 ///Method identifiers from the `service` module, for use by `rate_limit_status`. Also includes
 ///`verify_tokens` from the egg-mode top-level methods.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ServiceMethod {
     ///`service::terms`
     Terms,
@@ -386,7 +790,7 @@ pub enum ServiceMethod {
 }
 
 ///Method identifiers from the `tweet` module, for use by `rate_limit_status`.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TweetMethod {
     ///`tweet::show`
     Show,
@@ -409,7 +813,7 @@ pub enum TweetMethod {
 }
 
 ///Method identifiers from the `user` module, for use by `rate_limit_status`.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UserMethod {
     ///`user::show`
     Show,
@@ -448,7 +852,7 @@ pub enum UserMethod {
 }
 
 ///Method identifiers from the `list` module, for use by `rate_limit_status`.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ListMethod {
     ///`list::show`
     Show,
@@ -482,4 +886,132 @@ mod tests {
         let sample = load_file("sample_payloads/rate_limit_sample.json");
         ::serde_json::from_str::<RateLimitStatus>(&sample).unwrap();
     }
+
+    #[test]
+    fn method_from_path() {
+        assert_eq!(
+            Method::from_path("/1.1/statuses/show.json"),
+            Some(Method::Tweet(TweetMethod::Show))
+        );
+        assert_eq!(
+            Method::from_path("/1.1/statuses/retweets/1234.json"),
+            Some(Method::Tweet(TweetMethod::RetweetsOf))
+        );
+        assert_eq!(
+            Method::from_path("/mock/1.1/users/lookup.json"),
+            Some(Method::User(UserMethod::Lookup))
+        );
+        assert_eq!(Method::from_path("/1.1/statuses/update.json"), None);
+    }
+
+    #[tokio::test]
+    async fn tracker_records_responses() {
+        use crate::testing::MockTwitter;
+
+        // use a token of our own, so other tests don't touch the same records
+        let token = auth::Token::Access {
+            consumer: auth::KeyPair::new("tracker-consumer", "secret"),
+            access: auth::KeyPair::new("tracker-access", "secret"),
+        };
+        let twitter = MockTwitter::new();
+        twitter.set_remaining("/1.1/users/lookup.json", 1);
+        let tracker = rate_limit_tracker();
+
+        twitter
+            .run(async {
+                let status = rate_limit_status(&token).await.unwrap();
+                tracker.seed(&status, &token);
+                assert!(tracker.get(ListMethod::Statuses, &token).is_some());
+
+                assert!(tracker.can_call(UserMethod::Lookup, &token));
+                crate::user::lookup(vec![2244994945u64], &token)
+                    .await
+                    .unwrap();
+            })
+            .await;
+
+        let limit = tracker.get(UserMethod::Lookup, &token).unwrap();
+        assert_eq!(limit.remaining, 0);
+        assert_eq!(
+            tracker.available_at(UserMethod::Lookup, &token),
+            Some(limit.reset)
+        );
+        assert!(tracker.can_call(UserMethod::Show, &token));
+    }
+
+    #[tokio::test]
+    async fn throttle_holds_back_requests() {
+        use crate::error::Error;
+        use crate::testing::MockTwitter;
+
+        let token = auth::Token::Access {
+            consumer: auth::KeyPair::new("throttle-consumer", "secret"),
+            access: auth::KeyPair::new("throttle-access", "secret"),
+        };
+        let twitter = MockTwitter::new();
+        twitter.set_remaining("/1.1/users/show.json", 3);
+
+        let results = twitter
+            .run(with_throttle(Some(Duration::from_secs(0)), async {
+                // the first call lets the tracker know there are two calls left
+                crate::user::show("rustlang", &token).await.unwrap();
+                futures::future::join3(
+                    crate::user::show("rustlang", &token),
+                    crate::user::show("rustlang", &token),
+                    crate::user::show("rustlang", &token),
+                )
+                .await
+            }))
+            .await;
+
+        let results = [results.0, results.1, results.2];
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 2);
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(Error::RateLimit(_)))));
+        // the last call never made it to Twitter
+        assert_eq!(twitter.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn failed_requests_give_back_their_calls() {
+        use std::sync::Arc;
+
+        use crate::common::{with_transport, Transport, TransportFuture};
+        use crate::testing::MockTwitter;
+
+        /// A transport whose requests never reach Twitter.
+        struct Unreachable;
+
+        impl Transport for Unreachable {
+            fn send(&self, _request: hyper::Request<hyper::Body>) -> TransportFuture {
+                Box::pin(async { Err(InvalidResponse("connection refused", None)) })
+            }
+        }
+
+        let token = auth::Token::Access {
+            consumer: auth::KeyPair::new("giveback-consumer", "secret"),
+            access: auth::KeyPair::new("giveback-access", "secret"),
+        };
+        let twitter = MockTwitter::new();
+        twitter.set_remaining("/1.1/users/show.json", 2);
+
+        twitter
+            .run(with_throttle(Some(Duration::from_secs(0)), async {
+                // the first call lets the tracker know there's one call left
+                crate::user::show("rustlang", &token).await.unwrap();
+
+                let failed =
+                    with_transport(Arc::new(Unreachable), crate::user::show("rustlang", &token))
+                        .await;
+                assert!(matches!(failed, Err(InvalidResponse(..))));
+
+                // so the failed request didn't use it up
+                assert!(rate_limit_tracker().can_call(UserMethod::Show, &token));
+                crate::user::show("rustlang", &token).await.unwrap();
+            }))
+            .await;
+
+        assert_eq!(twitter.requests().len(), 2);
+    }
 }
@@ -43,25 +43,37 @@
 //! * In the case of an unreliable connection (e.g. mobile network), fall back to the polling API
 //!
 //! The [official guide](https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/connecting) has more information.
+//!
+//! If you'd rather not handle this yourself, [`ReconnectingStream`] wraps a stream to follow these
+//! guidelines for you, and can be started from a [`StreamBuilder`] with `start_reconnecting`.
+//!
+//! [`ReconnectingStream`]: struct.ReconnectingStream.html
+//! [`StreamBuilder`]: struct.StreamBuilder.html
 use std::future::Future;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::task::{Context, Poll};
 use std::{self, io};
 
+use bytes::{Bytes, BytesMut};
 use futures::Stream;
-use hyper::client::ResponseFuture;
 use hyper::{Body, Request};
 use serde::de::Error;
 use serde::{Serialize, Deserialize, Deserializer};
 use serde_json;
+use serde_json::value::RawValue;
 
-use crate::auth::Token;
+use crate::auth::{SharedToken, Token};
 use crate::common::*;
 use crate::tweet::Tweet;
+use crate::user::TwitterUser;
 use crate::{error, links};
 
-// TODO rewrite this
+mod raw;
+mod reconnect;
+
+pub use self::reconnect::{DisconnectReason, ReconnectingStream, StreamEvent};
+
 // https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/streaming-message-types
 /// Represents the kinds of messages that can be sent over Twitter's Streaming API.
 #[derive(Debug)]
@@ -131,12 +143,67 @@ pub enum StreamMessage {
     ///
     /// [stream-doc]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/streaming-message-types
     Disconnect(u64, String),
+    /// Notice given when a filtered stream matched more tweets than Twitter is allowed to deliver.
+    ///
+    /// The enclosed value is the total number of matching tweets that weren't delivered since the
+    /// connection was opened.
+    Limit(u64),
+    /// A warning that the client is reading messages too slowly, and will be disconnected if it
+    /// doesn't catch up.
+    ///
+    /// These are only sent when the stream was opened with `stall_warnings` enabled, at most once
+    /// every 5 minutes.
+    StallWarning {
+        /// The warning code, currently always `FALLING_BEHIND`.
+        code: String,
+        /// A human-readable description of the warning.
+        message: String,
+        /// How full Twitter's queue of messages waiting to be sent to this stream is, as a
+        /// percentage.
+        percent_full: u32,
+    },
+    /// A warning that the user being streamed follows more accounts than the stream can deliver
+    /// tweets for.
+    FollowsOverLimit {
+        /// The user that follows too many accounts.
+        user_id: u64,
+        /// A human-readable description of the warning.
+        message: String,
+    },
+    /// An event involving the authenticated user, like a follow or a like, sent on user and site
+    /// streams.
+    Event(Box<UserEvent>),
+    /// A message sent on a site stream, wrapping the message that was sent to the given user.
+    ForUser(u64, Box<StreamMessage>),
+    /// A control message sent at the beginning of a site stream. The enclosed value is the URI
+    /// used to add and remove users from the stream.
+    Control(String),
     /// An unhandled message payload.
     ///
     /// Twitter can add new streaming messages to the API, and egg-mode includes them here so that
     /// they can be used before egg-mode has a chance to handle them.
     Unknown(serde_json::Value),
-    //TODO: stall warnings? "follows over limit" warnings? (other warnings?)
+}
+
+/// Represents an event involving the authenticated user, sent on user and site streams.
+///
+/// The kinds of events, and what the `source`, `target`, and `target_object` refer to for each,
+/// are listed on [Twitter's stream documentation][stream-doc], under "Events (event)".
+///
+/// [stream-doc]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/streaming-message-types
+#[derive(Debug, Deserialize)]
+pub struct UserEvent {
+    /// The kind of event, like `"follow"` or `"favorite"`.
+    pub event: String,
+    /// When the event occurred.
+    #[serde(with = "serde_datetime")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// The user who performed the action.
+    pub source: TwitterUser,
+    /// The user the action was performed on.
+    pub target: TwitterUser,
+    /// The object the action was performed on, like a tweet or a list, if any.
+    pub target_object: Option<serde_json::Value>,
 }
 
 impl<'de> Deserialize<'de> for StreamMessage {
@@ -144,51 +211,8 @@ impl<'de> Deserialize<'de> for StreamMessage {
     where
         D: Deserializer<'de>,
     {
-        macro_rules! fetch {
-            ($input: ident, $key: expr) => {
-                $input
-                    .get($key)
-                    .and_then(|val| serde_json::from_value(val.clone()).ok())
-                    .ok_or_else(|| D::Error::custom("Failed"))
-            };
-        }
-
-        let input = serde_json::Value::deserialize(deser)?;
-        let msg = if let Some(del) = input.get("delete").and_then(|d| d.get("status")) {
-            StreamMessage::Delete {
-                status_id: fetch!(del, "id")?,
-                user_id: fetch!(del, "user_id")?,
-            }
-        } else if let Some(scrub) = input.get("scrub_geo") {
-            StreamMessage::ScrubGeo {
-                user_id: fetch!(scrub, "user_id")?,
-                up_to_status_id: fetch!(scrub, "up_to_status_id")?,
-            }
-        } else if let Some(tweet) = input.get("status_withheld") {
-            StreamMessage::StatusWithheld {
-                status_id: fetch!(tweet, "id")?,
-                user_id: fetch!(tweet, "user_id")?,
-                withheld_in_countries: fetch!(tweet, "withheld_in_countries")?,
-            }
-        } else if let Some(user) = input.get("user_withheld") {
-            StreamMessage::UserWithheld {
-                user_id: fetch!(user, "id")?,
-                withheld_in_countries: fetch!(user, "withheld_in_countries")?,
-            }
-        } else if let Some(err) = input.get("disconnect") {
-            StreamMessage::Disconnect(fetch!(err, "code")?, fetch!(err, "reason")?)
-        } else if let Some(friends) = input.get("friends") {
-            StreamMessage::FriendList(
-                serde_json::from_value(friends.clone())
-                    .map_err(|e| D::Error::custom(format!("{}", e)))?,
-            )
-        // TODO remove clone?
-        } else if let Ok(tweet) = serde_json::from_value::<Tweet>(input.clone()) {
-            StreamMessage::Tweet(tweet)
-        } else {
-            StreamMessage::Unknown(input.clone())
-        };
-        Ok(msg)
+        let input = Box::<RawValue>::deserialize(deser)?;
+        raw::parse(input.get()).map_err(|e| D::Error::custom(format!("{}", e)))
     }
 }
 
@@ -199,37 +223,144 @@ impl FromStr for StreamMessage {
         if input.is_empty() {
             Ok(StreamMessage::Ping)
         } else {
-            Ok(serde_json::from_str(input)?)
+            Ok(raw::parse(input)?)
         }
     }
 }
 
+/// A message from the Streaming API that hasn't been parsed yet.
+///
+/// This is yielded by a [`RawTwitterStream`], for when parsing every message as it arrives would
+/// be wasteful - for example, if most messages are passed along elsewhere as-is, or only a few
+/// need to be looked at closely. Holding onto a `RawStreamMessage` doesn't copy the message; it
+/// shares the buffer it was read into.
+///
+/// [`RawTwitterStream`]: struct.RawTwitterStream.html
+#[derive(Debug, Clone)]
+pub struct RawStreamMessage {
+    bytes: Bytes,
+}
+
+impl RawStreamMessage {
+    /// Returns the raw bytes of the message, without the line break that ended it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the message as a string, if it's valid UTF-8.
+    pub fn as_str(&self) -> Result<&str, error::Error> {
+        std::str::from_utf8(&self.bytes).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            )
+            .into()
+        })
+    }
+
+    /// Returns whether this message is a keep-alive ping, which is an empty line.
+    pub fn is_ping(&self) -> bool {
+        self.bytes.iter().all(|b| b.is_ascii_whitespace())
+    }
+
+    /// Parses this message into a `StreamMessage`.
+    pub fn parse(&self) -> Result<StreamMessage, error::Error> {
+        StreamMessage::from_str(self.as_str()?)
+    }
+}
+
 /// A `Stream` that represents a connection to the Twitter Streaming API.
+///
+/// Messages from Twitter are normally separated by line breaks, but if the stream was opened with
+/// `delimited=length` (as with [`StreamBuilder::length_delimited`]), each message is preceded by
+/// its length in bytes. `TwitterStream` recognizes these lengths as they come in, and uses them to
+/// pick out each message without searching for its end.
+///
+/// Every message is parsed into a `StreamMessage` as it arrives. To defer that until you need it,
+/// convert this into a [`RawTwitterStream`] with `raw`.
+///
+/// [`StreamBuilder::length_delimited`]: struct.StreamBuilder.html#method.length_delimited
+/// [`RawTwitterStream`]: struct.RawTwitterStream.html
 #[must_use = "Streams are lazy and do nothing unless polled"]
 pub struct TwitterStream {
-    buf: Vec<u8>,
+    buf: BytesMut,
+    /// How much of `buf` has already been searched for a line break.
+    scanned: usize,
+    /// The length of the next message, if it was given by a length prefix.
+    next_len: Option<usize>,
     request: Option<Request<Body>>,
-    response: Option<ResponseFuture>,
+    response: Option<TransportFuture>,
     body: Option<Body>,
 }
 
 impl TwitterStream {
     pub(crate) fn new(request: Request<Body>) -> TwitterStream {
         TwitterStream {
-            buf: vec![],
+            buf: BytesMut::new(),
+            scanned: 0,
+            next_len: None,
             request: Some(request),
             response: None,
             body: None,
         }
     }
-}
 
-impl Stream for TwitterStream {
-    type Item = Result<StreamMessage, error::Error>;
+    /// Converts this stream into one that yields messages without parsing them.
+    pub fn raw(self) -> RawTwitterStream {
+        RawTwitterStream { stream: self }
+    }
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+    /// Attempts to split a complete message off the front of the buffer.
+    fn next_frame(&mut self) -> Option<Result<Bytes, error::Error>> {
+        loop {
+            if let Some(len) = self.next_len {
+                if self.buf.len() < len {
+                    return None;
+                }
+                self.next_len = None;
+                self.scanned = 0;
+                let mut frame = self.buf.split_to(len);
+                trim_line_break(&mut frame);
+                return Some(Ok(frame.freeze()));
+            }
+
+            let pos = match self.buf[self.scanned..].iter().position(|&b| b == b'\n') {
+                Some(pos) => self.scanned + pos,
+                None => {
+                    self.scanned = self.buf.len();
+                    return None;
+                }
+            };
+            self.scanned = 0;
+            let mut line = self.buf.split_to(pos + 1);
+            trim_line_break(&mut line);
+
+            // messages are JSON objects, so a line of digits can only be a length prefix
+            if !line.is_empty() && line.iter().all(|b| b.is_ascii_digit()) {
+                match std::str::from_utf8(&line).ok().and_then(|l| l.parse().ok()) {
+                    Some(len) => self.next_len = Some(len),
+                    None => {
+                        return Some(Err(error::Error::InvalidResponse(
+                            "stream message length was too large",
+                            None,
+                        )))
+                    }
+                }
+                continue;
+            }
+
+            return Some(Ok(line.freeze()));
+        }
+    }
+
+    /// Polls for the next complete message, without parsing it.
+    fn poll_frame(&mut self, cx: &mut Context) -> Poll<Option<Result<Bytes, error::Error>>> {
         if let Some(req) = self.request.take() {
-            self.response = Some(get_response(req));
+            self.response = Some(match req.extensions().get::<SharedToken>().cloned() {
+                // refresh the token if it has expired, like `raw_request` does
+                Some(shared) => Box::pin(async move { shared.connect(req).await }),
+                None => get_response(req),
+            });
         }
 
         if let Some(mut resp) = self.response.take() {
@@ -238,7 +369,7 @@ impl Stream for TwitterStream {
                     self.response = Some(resp);
                     return Poll::Pending;
                 }
-                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
                 Poll::Ready(Ok(resp)) => {
                     let status = resp.status();
                     if !status.is_success() {
@@ -253,6 +384,13 @@ impl Stream for TwitterStream {
 
         if let Some(mut body) = self.body.take() {
             loop {
+                // a single chunk can carry several messages, so check the buffer before waiting
+                // for more data
+                if let Some(frame) = self.next_frame() {
+                    self.body = Some(body);
+                    return Poll::Ready(Some(frame));
+                }
+
                 match Pin::new(&mut body).poll_next(cx) {
                     Poll::Pending => {
                         self.body = Some(body);
@@ -266,24 +404,7 @@ impl Stream for TwitterStream {
                         return Poll::Ready(Some(Err(e.into())));
                     }
                     Poll::Ready(Some(Ok(chunk))) => {
-                        self.buf.extend(&*chunk);
-
-                        if let Some(pos) = self.buf.windows(2).position(|w| w == b"\r\n") {
-                            self.body = Some(body);
-                            let pos = pos + 2;
-                            let resp = if let Ok(msg_str) = std::str::from_utf8(&self.buf[..pos]) {
-                                StreamMessage::from_str(msg_str)
-                            } else {
-                                Err(io::Error::new(
-                                    io::ErrorKind::InvalidData,
-                                    "stream did not contain valid UTF-8",
-                                )
-                                .into())
-                            };
-
-                            self.buf.drain(..pos);
-                            return Poll::Ready(Some(Ok(resp?)));
-                        }
+                        self.buf.extend_from_slice(&chunk);
                     }
                 }
             }
@@ -293,6 +414,50 @@ impl Stream for TwitterStream {
     }
 }
 
+/// Removes the trailing `\r\n` (or `\n`) from the given line.
+fn trim_line_break(line: &mut BytesMut) {
+    if line.ends_with(b"\n") {
+        line.truncate(line.len() - 1);
+    }
+    if line.ends_with(b"\r") {
+        line.truncate(line.len() - 1);
+    }
+}
+
+impl Stream for TwitterStream {
+    type Item = Result<StreamMessage, error::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.poll_frame(cx).map(|frame| {
+            frame.map(|frame| {
+                frame.and_then(|frame| RawStreamMessage { bytes: frame }.parse())
+            })
+        })
+    }
+}
+
+/// A `Stream` that represents a connection to the Twitter Streaming API, yielding messages without
+/// parsing them.
+///
+/// This is created by calling `raw` on a `TwitterStream`. Each message is yielded as a
+/// [`RawStreamMessage`], which can be parsed into a `StreamMessage` later, if at all.
+///
+/// [`RawStreamMessage`]: struct.RawStreamMessage.html
+#[must_use = "Streams are lazy and do nothing unless polled"]
+pub struct RawTwitterStream {
+    stream: TwitterStream,
+}
+
+impl Stream for RawTwitterStream {
+    type Item = Result<RawStreamMessage, error::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.stream
+            .poll_frame(cx)
+            .map(|frame| frame.map(|frame| frame.map(|bytes| RawStreamMessage { bytes })))
+    }
+}
+
 /// Represents the amount of filtering that can be done to streams on Twitter's side.
 ///
 /// According to Twitter's documentation, "When displaying a stream of Tweets to end users
@@ -334,6 +499,7 @@ impl ::std::fmt::Display for FilterLevel {
 ///
 /// __Note__: The user __must__ specify at least one `track`, `follow` or `locations` filter or else
 /// the stream will __fail__ at point of connection.
+#[derive(Clone)]
 pub struct StreamBuilder {
     url: &'static str,
     follow: Vec<u64>,
@@ -341,6 +507,8 @@ pub struct StreamBuilder {
     language: Vec<String>,
     locations: Vec<BoundingBox>,
     filter_level: Option<FilterLevel>,
+    stall_warnings: bool,
+    length_delimited: bool,
 }
 
 impl StreamBuilder {
@@ -352,6 +520,8 @@ impl StreamBuilder {
             language: Vec::new(),
             locations: Vec::new(),
             filter_level: None,
+            stall_warnings: false,
+            length_delimited: false,
         }
     }
 
@@ -428,6 +598,31 @@ impl StreamBuilder {
         }
     }
 
+    /// Sets whether Twitter should send warnings when the stream is falling behind.
+    ///
+    /// If messages are being read more slowly than Twitter is sending them, they begin to queue
+    /// up on Twitter's end, and the stream is disconnected once the queue is full. With stall
+    /// warnings enabled, Twitter sends a `StreamMessage::StallWarning` when this happens, which
+    /// says how full the queue is.
+    pub fn stall_warnings(self, stall_warnings: bool) -> StreamBuilder {
+        StreamBuilder {
+            stall_warnings,
+            ..self
+        }
+    }
+
+    /// Sets whether Twitter should precede each message with its length in bytes.
+    ///
+    /// This is the `delimited=length` parameter. With it, `TwitterStream` can read each message
+    /// by its length instead of searching for the line break at its end, which saves a good deal
+    /// of work on high-volume streams. The messages yielded by the stream are the same either way.
+    pub fn length_delimited(self, length_delimited: bool) -> StreamBuilder {
+        StreamBuilder {
+            length_delimited,
+            ..self
+        }
+    }
+
     /// Finalizes the stream parameters and returns the resulting `TwitterStream`.
     pub fn start(self, token: &Token) -> TwitterStream {
         // Re connection failure, arguably this library should check that either 'track' or
@@ -468,10 +663,29 @@ impl StreamBuilder {
             params.add_param_ref("locations", locs);
         }
 
+        if self.stall_warnings {
+            params.add_param_ref("stall_warnings", "true");
+        }
+
+        if self.length_delimited {
+            params.add_param_ref("delimited", "length");
+        }
+
         let req = post(self.url, token, Some(&params));
 
         TwitterStream::new(req)
     }
+
+    /// Finalizes the stream parameters and returns a `ReconnectingStream` that keeps the resulting
+    /// stream connected.
+    ///
+    /// See the documentation for [`ReconnectingStream`] for details on when and how it reconnects.
+    ///
+    /// [`ReconnectingStream`]: struct.ReconnectingStream.html
+    pub fn start_reconnecting(self, token: &Token) -> ReconnectingStream {
+        let token = token.clone();
+        ReconnectingStream::new(move || self.clone().start(&token))
+    }
 }
 
 /// Begins building a request to a filtered public stream.
@@ -545,6 +759,187 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_message_types() {
+        match load_stream("sample_payloads/sample-stream-delete.json") {
+            StreamMessage::Delete { status_id, user_id } => {
+                assert_eq!(status_id, 1234);
+                assert_eq!(user_id, 3);
+            }
+            msg => panic!("Not a delete: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-scrub-geo.json") {
+            StreamMessage::ScrubGeo {
+                user_id,
+                up_to_status_id,
+            } => {
+                assert_eq!(user_id, 14090452);
+                assert_eq!(up_to_status_id, 23260136625);
+            }
+            msg => panic!("Not a scrub_geo: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-status-withheld.json") {
+            StreamMessage::StatusWithheld {
+                status_id,
+                withheld_in_countries,
+                ..
+            } => {
+                assert_eq!(status_id, 1234567890);
+                assert_eq!(withheld_in_countries, ["DE", "AR"]);
+            }
+            msg => panic!("Not a status_withheld: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-user-withheld.json") {
+            StreamMessage::UserWithheld { user_id, .. } => assert_eq!(user_id, 123456),
+            msg => panic!("Not a user_withheld: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-disconnect.json") {
+            StreamMessage::Disconnect(code, _) => assert_eq!(code, 4),
+            msg => panic!("Not a disconnect: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-limit.json") {
+            StreamMessage::Limit(track) => assert_eq!(track, 1234),
+            msg => panic!("Not a limit: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-stall-warning.json") {
+            StreamMessage::StallWarning {
+                code, percent_full, ..
+            } => {
+                assert_eq!(code, "FALLING_BEHIND");
+                assert_eq!(percent_full, 60);
+            }
+            msg => panic!("Not a stall warning: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-follows-over-limit.json") {
+            StreamMessage::FollowsOverLimit { user_id, .. } => assert_eq!(user_id, 13),
+            msg => panic!("Not a follows-over-limit warning: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-event.json") {
+            StreamMessage::Event(event) => {
+                assert_eq!(event.event, "follow");
+                assert!(event.target_object.is_none());
+            }
+            msg => panic!("Not an event: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-for-user.json") {
+            StreamMessage::ForUser(1888, msg) => match *msg {
+                StreamMessage::FriendList(friends) => assert_eq!(friends.len(), 3),
+                msg => panic!("Not a friend list: {:?}", msg),
+            },
+            msg => panic!("Not a for_user envelope: {:?}", msg),
+        }
+
+        match load_stream("sample_payloads/sample-stream-control.json") {
+            StreamMessage::Control(uri) => assert!(uri.starts_with("/1.1/site/c/")),
+            msg => panic!("Not a control message: {:?}", msg),
+        }
+    }
+
+    /// Creates a `TwitterStream` that reads the given chunks.
+    fn stream_of(chunks: Vec<String>) -> TwitterStream {
+        let chunks = chunks.into_iter().map(Ok::<_, io::Error>);
+        TwitterStream {
+            body: Some(Body::wrap_stream(futures::stream::iter(chunks))),
+            request: None,
+            ..TwitterStream::new(Request::new(Body::empty()))
+        }
+    }
+
+    #[tokio::test]
+    async fn frame_stream_messages() {
+        use futures::TryStreamExt;
+
+        let limit = r#"{"limit":{"track":1}}"#;
+        let scrub = r#"{"scrub_geo":{"user_id":2,"up_to_status_id":3}}"#;
+
+        // messages split across chunks, and several messages in one chunk
+        let text = format!("{}\r\n\r\n{}\r\n", limit, scrub);
+        let chunks = vec![text[..10].to_string(), text[10..22].to_string(), text[22..].to_string()];
+        let msgs = stream_of(chunks).try_collect::<Vec<_>>().await.unwrap();
+        assert!(matches!(
+            msgs[..],
+            [
+                StreamMessage::Limit(1),
+                StreamMessage::Ping,
+                StreamMessage::ScrubGeo { user_id: 2, .. }
+            ]
+        ));
+
+        // the same, but with length prefixes, which may be split from their messages
+        let text = format!(
+            "{}\r\n{}\r\n\r\n{}\r\n{}\r\n",
+            limit.len() + 2,
+            limit,
+            scrub.len() + 2,
+            scrub
+        );
+        let chunks = vec![text[..1].to_string(), text[1..30].to_string(), text[30..].to_string()];
+        let msgs = stream_of(chunks).try_collect::<Vec<_>>().await.unwrap();
+        assert!(matches!(
+            msgs[..],
+            [
+                StreamMessage::Limit(1),
+                StreamMessage::Ping,
+                StreamMessage::ScrubGeo { user_id: 2, .. }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn raw_stream_messages() {
+        use futures::TryStreamExt;
+
+        let limit = r#"{"limit":{"track":1}}"#;
+        let text = format!("{}\r\n\r\n{}\n", limit, limit);
+        let msgs = stream_of(vec![text])
+            .raw()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0].as_bytes(), limit.as_bytes());
+        assert!(msgs[1].is_ping());
+        assert!(!msgs[2].is_ping());
+        assert!(matches!(msgs[0].parse().unwrap(), StreamMessage::Limit(1)));
+        assert!(matches!(msgs[1].parse().unwrap(), StreamMessage::Ping));
+        assert!(matches!(msgs[2].parse().unwrap(), StreamMessage::Limit(1)));
+    }
+
+    #[tokio::test]
+    async fn length_delimited_stream() {
+        use crate::testing::MockTwitter;
+        use futures::TryStreamExt;
+
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        let msgs = twitter
+            .run(
+                filter()
+                    .track(["rustlang"])
+                    .stall_warnings(true)
+                    .length_delimited(true)
+                    .start(&token)
+                    .try_collect::<Vec<_>>(),
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(msgs[..], [StreamMessage::Tweet(_), StreamMessage::Ping]));
+        let params = &twitter.requests()[0].params;
+        assert_eq!(params["delimited"], "length");
+        assert_eq!(params["stall_warnings"], "true");
+    }
+
     #[test]
     fn parse_empty_stream() {
         let msg = StreamMessage::from_str("").unwrap();
@@ -554,4 +949,10 @@ mod tests {
             panic!("Not a ping")
         }
     }
+
+    #[test]
+    fn parse_escaped_keys() {
+        let msg = StreamMessage::from_str(r#"{"\u006cimit":{"track":7}}"#).unwrap();
+        assert!(matches!(msg, StreamMessage::Limit(7)));
+    }
 }
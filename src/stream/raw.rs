@@ -0,0 +1,173 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Internal types used to decode stream messages in a single pass, without building an intermediate
+//! `serde_json::Value`.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde::de::value::MapDeserializer;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::tweet::Tweet;
+
+use super::StreamMessage;
+
+/// The name of a top-level key in a message. This borrows from the message, unless the key
+/// contains escapes.
+#[derive(Deserialize, PartialEq, Eq, Hash)]
+struct Key<'a>(#[serde(borrow)] Cow<'a, str>);
+
+/// The top-level keys of a message, each with the raw JSON of its value.
+///
+/// The message is only scanned once, into this map. The keys that identify each kind of message
+/// other than a tweet are looked up here, and once the kind of message is known, only the values it
+/// needs are parsed. Tweets and user events are deserialized from these same values, rather than
+/// from the original text.
+struct Fields<'a>(HashMap<Key<'a>, &'a RawValue>);
+
+impl<'a> Fields<'a> {
+    /// Returns the raw JSON of the given key's value, if the message has that key.
+    fn get(&self, key: &str) -> Option<&'a RawValue> {
+        self.0.get(&Key(Cow::Borrowed(key))).copied()
+    }
+
+    /// Deserializes the whole message from its fields.
+    fn parse<T: Deserialize<'a>>(&self) -> serde_json::Result<T> {
+        let fields = self.0.iter().map(|(key, value)| (key.0.as_ref(), *value));
+        T::deserialize(MapDeserializer::new(fields))
+    }
+}
+
+#[derive(Deserialize)]
+struct RawDelete {
+    status: Option<RawDeletedStatus>,
+}
+
+#[derive(Deserialize)]
+struct RawDeletedStatus {
+    id: u64,
+    user_id: u64,
+}
+
+#[derive(Deserialize)]
+struct RawScrubGeo {
+    user_id: u64,
+    up_to_status_id: u64,
+}
+
+#[derive(Deserialize)]
+struct RawStatusWithheld {
+    id: u64,
+    user_id: u64,
+    withheld_in_countries: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RawUserWithheld {
+    id: u64,
+    withheld_in_countries: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RawDisconnect {
+    code: u64,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct RawLimit {
+    track: u64,
+}
+
+#[derive(Deserialize)]
+struct RawWarning {
+    code: String,
+    message: String,
+    percent_full: Option<u32>,
+    user_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct RawControl {
+    control_uri: String,
+}
+
+/// Parses the given JSON text as a `StreamMessage`.
+pub(crate) fn parse(input: &str) -> serde_json::Result<StreamMessage> {
+    use serde_json::from_str;
+
+    let env = Fields(from_str(input)?);
+    let for_user = match env.get("for_user") {
+        Some(user_id) => Some(from_str::<u64>(user_id.get())?),
+        None => None,
+    };
+
+    if let Some(del) = env.get("delete") {
+        if let Some(status) = from_str::<RawDelete>(del.get())?.status {
+            return Ok(StreamMessage::Delete {
+                status_id: status.id,
+                user_id: status.user_id,
+            });
+        }
+    } else if let Some(scrub) = env.get("scrub_geo") {
+        let scrub: RawScrubGeo = from_str(scrub.get())?;
+        return Ok(StreamMessage::ScrubGeo {
+            user_id: scrub.user_id,
+            up_to_status_id: scrub.up_to_status_id,
+        });
+    } else if let Some(tweet) = env.get("status_withheld") {
+        let tweet: RawStatusWithheld = from_str(tweet.get())?;
+        return Ok(StreamMessage::StatusWithheld {
+            status_id: tweet.id,
+            user_id: tweet.user_id,
+            withheld_in_countries: tweet.withheld_in_countries,
+        });
+    } else if let Some(user) = env.get("user_withheld") {
+        let user: RawUserWithheld = from_str(user.get())?;
+        return Ok(StreamMessage::UserWithheld {
+            user_id: user.id,
+            withheld_in_countries: user.withheld_in_countries,
+        });
+    } else if let Some(err) = env.get("disconnect") {
+        let err: RawDisconnect = from_str(err.get())?;
+        return Ok(StreamMessage::Disconnect(err.code, err.reason));
+    } else if let Some(friends) = env.get("friends") {
+        return Ok(StreamMessage::FriendList(from_str(friends.get())?));
+    } else if let Some(limit) = env.get("limit") {
+        return Ok(StreamMessage::Limit(from_str::<RawLimit>(limit.get())?.track));
+    } else if let Some(warning) = env.get("warning") {
+        let warning: RawWarning = from_str(warning.get())?;
+        match (warning.code.as_str(), warning.percent_full, warning.user_id) {
+            ("FALLING_BEHIND", Some(percent_full), _) => {
+                return Ok(StreamMessage::StallWarning {
+                    code: warning.code,
+                    message: warning.message,
+                    percent_full,
+                });
+            }
+            ("FOLLOWS_OVER_LIMIT", _, Some(user_id)) => {
+                return Ok(StreamMessage::FollowsOverLimit {
+                    user_id,
+                    message: warning.message,
+                });
+            }
+            _ => (),
+        }
+    } else if env.get("event").is_some() {
+        return Ok(StreamMessage::Event(env.parse()?));
+    } else if let (Some(user_id), Some(msg)) = (for_user, env.get("message")) {
+        return Ok(StreamMessage::ForUser(user_id, Box::new(parse(msg.get())?)));
+    } else if let Some(control) = env.get("control") {
+        let control: RawControl = from_str(control.get())?;
+        return Ok(StreamMessage::Control(control.control_uri));
+    } else if let Ok(tweet) = env.parse::<Tweet>() {
+        return Ok(StreamMessage::Tweet(tweet));
+    }
+
+    // only build a `Value` for messages egg-mode doesn't recognize
+    Ok(StreamMessage::Unknown(env.parse()?))
+}
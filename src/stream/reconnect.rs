@@ -0,0 +1,389 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A wrapper around `TwitterStream` that reconnects according to Twitter's connection guidelines.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::Stream;
+use hyper::StatusCode;
+use tokio::time::{sleep, Instant, Sleep};
+
+use crate::error::Error;
+
+use super::{StreamMessage, TwitterStream};
+
+/// How long to wait for data before deciding the connection has stalled. Twitter sends a ping
+/// every 30 seconds, and recommends waiting 90 seconds before reconnecting.
+const STALL_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// An event yielded by a `ReconnectingStream`.
+#[derive(Debug)]
+// messages are by far the most common event, so there's no use boxing them
+#[allow(clippy::large_enum_variant)]
+pub enum StreamEvent {
+    /// A message received from Twitter.
+    Message(StreamMessage),
+    /// The connection to Twitter was lost, and will be opened again after the given delay.
+    Reconnecting {
+        /// Why the connection was lost.
+        reason: DisconnectReason,
+        /// How long the stream will wait before connecting again.
+        delay: Duration,
+    },
+}
+
+/// The reason a `ReconnectingStream` had to reconnect.
+#[derive(Debug)]
+pub enum DisconnectReason {
+    /// No data, not even a keep-alive ping, was received for the stall timeout.
+    Stalled,
+    /// Twitter closed the connection.
+    Ended,
+    /// The connection failed with the given error.
+    Error(Error),
+}
+
+/// The kinds of failure that Twitter's connection guidelines give different backoff strategies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backoff {
+    /// Network errors, stalls, and closed connections: back off linearly by 250ms, up to 16s.
+    Network,
+    /// HTTP errors: back off exponentially from 5s, up to 320s.
+    Http,
+    /// HTTP 420 and 429: back off exponentially from one minute, up to 960s.
+    RateLimited,
+}
+
+impl Backoff {
+    fn next(self, last: Option<(Backoff, Duration)>) -> Duration {
+        let last = match last {
+            Some((kind, delay)) if kind == self => Some(delay),
+            _ => None,
+        };
+        match self {
+            Backoff::Network => last
+                .map_or(Duration::from_millis(250), |d| {
+                    d + Duration::from_millis(250)
+                })
+                .min(Duration::from_secs(16)),
+            Backoff::Http => last
+                .map_or(Duration::from_secs(5), |d| d * 2)
+                .min(Duration::from_secs(320)),
+            Backoff::RateLimited => last
+                .map_or(Duration::from_secs(60), |d| d * 2)
+                .min(Duration::from_secs(960)),
+        }
+    }
+}
+
+/// A `Stream` that keeps a connection to the Twitter Streaming API open, reconnecting as
+/// necessary.
+///
+/// A `TwitterStream` represents a single connection to Twitter, which ends as soon as the
+/// connection does. Twitter [recommends][connecting] that long-running clients reconnect when this
+/// happens, backing off between attempts depending on what went wrong. `ReconnectingStream`
+/// follows these guidelines:
+///
+/// * If no data (including the keep-alive pings Twitter sends every 30 seconds) has arrived for 90
+///   seconds, the connection is considered stalled and is reopened.
+/// * After network errors, stalls, and connections closed by Twitter, the delay before
+///   reconnecting starts at 250 milliseconds and grows by 250 milliseconds on each attempt (250ms,
+///   500ms, 750ms, ...), staying at 16 seconds once it gets there.
+/// * After HTTP 5xx errors, the delay starts at 5 seconds and doubles on each attempt (5s, 10s,
+///   20s, ...), staying at 320 seconds once it gets there.
+/// * After HTTP 420 ("Enhance Your Calm") or 429 errors, the delay starts at one minute and
+///   doubles on each attempt (60s, 120s, 240s, 480s), staying at 960 seconds once it gets there.
+/// * Once a message is received, or the stream fails for a different kind of reason than the last
+///   attempt, the delays start over.
+///
+/// [connecting]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/connecting
+///
+/// Each time the stream reconnects, it yields a `StreamEvent::Reconnecting` describing why it
+/// disconnected and how long it will wait, and then keeps yielding `StreamEvent::Message`s from the
+/// new connection.
+///
+/// Errors that reconnecting won't fix, like an HTTP 401 (Unauthorized) response, are returned as an
+/// `Err`, after which the stream ends. Errors parsing an individual message are also returned as an
+/// `Err`, but the stream carries on afterward.
+///
+/// A `ReconnectingStream` can be created from a `StreamBuilder` with `start_reconnecting`, or from
+/// any function that opens a `TwitterStream` with `ReconnectingStream::new`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// # let token: egg_mode::Token = unimplemented!();
+/// use egg_mode::stream::{self, StreamEvent, StreamMessage};
+/// use futures::TryStreamExt;
+///
+/// let mut stream = stream::filter().track(&["rustlang"]).start_reconnecting(&token);
+///
+/// while let Some(event) = stream.try_next().await.unwrap() {
+///     match event {
+///         StreamEvent::Message(StreamMessage::Tweet(tweet)) => println!("{}", tweet.text),
+///         StreamEvent::Reconnecting { reason, delay } => {
+///             println!("reconnecting in {:?}: {:?}", delay, reason)
+///         }
+///         _ => (),
+///     }
+/// }
+/// # }
+/// ```
+#[must_use = "Streams are lazy and do nothing unless polled"]
+pub struct ReconnectingStream {
+    connect: Box<dyn FnMut() -> TwitterStream + Send>,
+    stream: Option<TwitterStream>,
+    delay: Option<Pin<Box<Sleep>>>,
+    stall: Pin<Box<Sleep>>,
+    stall_timeout: Duration,
+    last_backoff: Option<(Backoff, Duration)>,
+    done: bool,
+}
+
+impl ReconnectingStream {
+    /// Creates a new `ReconnectingStream` that calls the given function each time it needs to open
+    /// a connection.
+    ///
+    /// ```rust,no_run
+    /// # let token: egg_mode::Token = unimplemented!();
+    /// use egg_mode::stream::{self, ReconnectingStream};
+    ///
+    /// let stream = ReconnectingStream::new(move || stream::sample(&token));
+    /// ```
+    pub fn new(connect: impl FnMut() -> TwitterStream + Send + 'static) -> ReconnectingStream {
+        ReconnectingStream {
+            connect: Box::new(connect),
+            stream: None,
+            delay: None,
+            stall: Box::pin(sleep(STALL_TIMEOUT)),
+            stall_timeout: STALL_TIMEOUT,
+            last_backoff: None,
+            done: false,
+        }
+    }
+
+    /// Sets how long to wait for data before reconnecting. The default is 90 seconds.
+    pub fn with_stall_timeout(self, stall_timeout: Duration) -> ReconnectingStream {
+        ReconnectingStream {
+            stall_timeout,
+            ..self
+        }
+    }
+
+    /// Drops the current connection and schedules a new one, returning the event to yield.
+    fn reconnect(&mut self, reason: DisconnectReason, backoff: Backoff) -> StreamEvent {
+        let delay = backoff.next(self.last_backoff);
+        self.last_backoff = Some((backoff, delay));
+        self.stream = None;
+        self.delay = Some(Box::pin(sleep(delay)));
+        StreamEvent::Reconnecting { reason, delay }
+    }
+}
+
+impl Stream for ReconnectingStream {
+    type Item = Result<StreamEvent, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        if let Some(delay) = this.delay.as_mut() {
+            match delay.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(()) => this.delay = None,
+            }
+        }
+
+        if this.stream.is_none() {
+            this.stream = Some((this.connect)());
+            this.stall
+                .as_mut()
+                .reset(Instant::now() + this.stall_timeout);
+        }
+        let stream = this.stream.as_mut().unwrap();
+
+        match Pin::new(stream).poll_next(cx) {
+            Poll::Ready(Some(Ok(msg))) => {
+                this.last_backoff = None;
+                this.stall
+                    .as_mut()
+                    .reset(Instant::now() + this.stall_timeout);
+                Poll::Ready(Some(Ok(StreamEvent::Message(msg))))
+            }
+            Poll::Ready(Some(Err(err))) => {
+                let backoff = match &err {
+                    Error::BadStatus(StatusCode::TOO_MANY_REQUESTS) => Backoff::RateLimited,
+                    Error::BadStatus(status) if status.as_u16() == 420 => Backoff::RateLimited,
+                    Error::BadStatus(status) if status.is_server_error() => Backoff::Http,
+                    Error::BadStatus(_) => {
+                        // the request itself was rejected, which reconnecting won't fix
+                        this.done = true;
+                        this.stream = None;
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                    Error::NetError(_) | Error::IOError(_) => Backoff::Network,
+                    #[cfg(feature = "native_tls")]
+                    Error::TlsError(_) => Backoff::Network,
+                    _ => return Poll::Ready(Some(Err(err))),
+                };
+                let event = this.reconnect(DisconnectReason::Error(err), backoff);
+                Poll::Ready(Some(Ok(event)))
+            }
+            Poll::Ready(None) => {
+                let event = this.reconnect(DisconnectReason::Ended, Backoff::Network);
+                Poll::Ready(Some(Ok(event)))
+            }
+            Poll::Pending => match this.stall.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    let event = this.reconnect(DisconnectReason::Stalled, Backoff::Network);
+                    Poll::Ready(Some(Ok(event)))
+                }
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+    use hyper::{Body, Method, Request};
+
+    use super::*;
+    use crate::common::{with_transport, Transport, TransportFuture};
+    use crate::stream;
+    use crate::testing::MockTwitter;
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnects_when_the_stream_ends() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        let events = twitter
+            .run(
+                stream::filter()
+                    .track(["rustlang"])
+                    .start_reconnecting(&token)
+                    .take(7)
+                    .collect::<Vec<_>>(),
+            )
+            .await;
+
+        let events = events.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert!(matches!(
+            events[0],
+            StreamEvent::Message(StreamMessage::Tweet(_))
+        ));
+        assert!(matches!(
+            events[1],
+            StreamEvent::Message(StreamMessage::Ping)
+        ));
+        match events[2] {
+            StreamEvent::Reconnecting {
+                reason: DisconnectReason::Ended,
+                delay,
+            } => assert_eq!(delay, Duration::from_millis(250)),
+            ref other => panic!("expected a reconnect, got {:?}", other),
+        }
+        // receiving a message resets the backoff
+        assert!(matches!(
+            events[3],
+            StreamEvent::Message(StreamMessage::Tweet(_))
+        ));
+        match events[5] {
+            StreamEvent::Reconnecting { delay, .. } => {
+                assert_eq!(delay, Duration::from_millis(250))
+            }
+            ref other => panic!("expected a reconnect, got {:?}", other),
+        }
+        assert_eq!(twitter.requests().len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backs_off_after_http_errors() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        let path = "/1.1/statuses/filter.json";
+
+        twitter.respond(Method::POST, path, StatusCode::from_u16(420).unwrap(), "");
+        let events = twitter
+            .run(
+                stream::filter()
+                    .track(["rustlang"])
+                    .start_reconnecting(&token)
+                    .take(2)
+                    .collect::<Vec<_>>(),
+            )
+            .await;
+        let delays = events
+            .into_iter()
+            .map(|e| match e {
+                Ok(StreamEvent::Reconnecting { delay, .. }) => delay,
+                other => panic!("expected a reconnect, got {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [Duration::from_secs(60), Duration::from_secs(120)]);
+
+        twitter.respond(Method::POST, path, StatusCode::UNAUTHORIZED, "");
+        let events = twitter
+            .run(
+                stream::filter()
+                    .track(["rustlang"])
+                    .start_reconnecting(&token)
+                    .collect::<Vec<_>>(),
+            )
+            .await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            Err(Error::BadStatus(StatusCode::UNAUTHORIZED))
+        ));
+    }
+
+    /// A transport whose streams never send anything.
+    #[derive(Default)]
+    struct StallingTransport(Mutex<Vec<hyper::body::Sender>>);
+
+    impl Transport for StallingTransport {
+        fn send(&self, _request: Request<Body>) -> TransportFuture {
+            let (sender, body) = Body::channel();
+            self.0.lock().unwrap().push(sender);
+            Box::pin(async move { Ok(hyper::Response::new(body)) })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnects_stalled_streams() {
+        let transport = Arc::new(StallingTransport::default());
+        let token = MockTwitter::token();
+        let start = Instant::now();
+        let events = with_transport(
+            transport.clone(),
+            ReconnectingStream::new(move || stream::sample(&token))
+                .take(2)
+                .collect::<Vec<_>>(),
+        )
+        .await;
+
+        assert!(events.iter().all(|e| matches!(
+            e,
+            Ok(StreamEvent::Reconnecting {
+                reason: DisconnectReason::Stalled,
+                ..
+            })
+        )));
+        assert!(start.elapsed() >= STALL_TIMEOUT * 2);
+        assert_eq!(transport.0.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,1250 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! An in-process stand-in for the Twitter API, to test code that uses egg-mode without network
+//! access.
+//!
+//! This module is only available with the `testing` crate feature enabled.
+//!
+//! The centerpiece of this module is [`MockTwitter`], a fake Twitter server that lives entirely
+//! inside your process. It implements the [`Transport`] trait, so every request egg-mode makes can
+//! be answered by it instead of going out over the network. It's seeded with the same sample
+//! payloads egg-mode uses for its own tests, and it keeps enough state for a handful of common
+//! workflows to behave realistically:
+//!
+//! * Timelines (`tweet::home_timeline`, `tweet::user_timeline`, `list::statuses`, and so on) are
+//!   served from a set of 20 tweets, respecting the `count`, `max_id`, and `since_id` parameters so
+//!   that `older` and `newer` page correctly. Tweets posted with `DraftTweet::send` are added to
+//!   these timelines, and `tweet::delete` removes them. Like Twitter, `exclude_replies` removes
+//!   replies after a page has been counted out, so such pages can come back short.
+//! * User lookups, searches, and follower/friend listings are served from a set of four users. The
+//!   authenticated user is always `@rustlang`.
+//! * Lists return a single sample list, whose members are the sample users.
+//! * Direct Messages can be listed, shown, sent, and deleted.
+//! * Media uploads go through the full INIT/APPEND/FINALIZE/STATUS sequence. Videos and GIFs
+//!   report that they need processing after being finalized, and report success when their status
+//!   is checked.
+//! * The sample and filter streams send a sample tweet followed by a keep-alive ping, then end
+//!   the connection. More messages can be added with `push_stream_message`. If the stream was
+//!   opened with `delimited=length`, each message is preceded by its length.
+//! * Cursored endpoints (like `user::followers_ids`) are paged according to their `count`
+//!   parameter. The items they serve can be replaced with `set_cursor_items`.
+//! * The premium search APIs (`search::archive_search`) serve the same tweets as the standard
+//!   search, paged with `next` tokens according to their `maxResults` parameter.
+//! * OAuth 2.0 tokens can be refreshed, and the tokens given with `expire_token` are rejected as
+//!   invalid, to test code that refreshes them.
+//!
+//! Every non-streaming endpoint also sends rate-limit headers, and reports error 88 once its
+//! (per-endpoint) rate limit has been used up. You can adjust the limit for an endpoint with
+//! `set_remaining` (or `set_remaining_for`, to give a single token its own limit), make the next
+//! few calls to it fail with `inject_rate_limit`, or override the response for any endpoint with
+//! `respond`.
+//!
+//! [`MockTwitter`]: struct.MockTwitter.html
+//! [`Transport`]: ../raw/trait.Transport.html
+//!
+//! # Example
+//!
+//! ```rust
+//! use egg_mode::testing::MockTwitter;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let twitter = MockTwitter::new();
+//! let token = MockTwitter::token();
+//!
+//! twitter.run(async {
+//!     let user = egg_mode::user::show("rustlang", &token).await.unwrap();
+//!     assert_eq!(user.screen_name, "rustlang");
+//!
+//!     let timeline = egg_mode::tweet::user_timeline("rustlang", false, true, &token);
+//!     let (_timeline, tweets) = timeline.start().await.unwrap();
+//!     assert_eq!(tweets.len(), 20);
+//! }).await;
+//!
+//! assert_eq!(twitter.requests().len(), 2);
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Method, Request, StatusCode};
+use serde_json::{json, Value};
+
+use crate::auth::{KeyPair, Token};
+use crate::common::{
+    set_shared_transport, with_transport, Transport, TransportFuture, WithTransport,
+};
+
+const TWEETS: &str = include_str!("../sample_payloads/tweet_array.json");
+const USERS: &str = include_str!("../sample_payloads/user_array.json");
+const LIST: &str = include_str!("../sample_payloads/sample-list.json");
+const STREAM_TWEET: &str = include_str!("../sample_payloads/sample-stream.json");
+const RATE_LIMIT_STATUS: &str = include_str!("../sample_payloads/rate_limit_sample.json");
+
+/// The rate limit given to every endpoint, per 15-minute window.
+const RATE_LIMIT: i32 = 900;
+/// The length of a rate-limit window, in seconds.
+const RATE_LIMIT_WINDOW: i64 = 15 * 60;
+/// How long after an error from `inject_rate_limit` its rate limit resets, in seconds.
+const INJECTED_RESET: i64 = 60;
+/// The screen name of the user that `MockTwitter` treats as the authenticated user.
+const ME: &str = "rustlang";
+
+/// A request received by a `MockTwitter`.
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    /// The HTTP method of the request.
+    pub method: Method,
+    /// The path of the request, like `/1.1/statuses/show.json`.
+    pub path: String,
+    /// The headers of the request, including its `Authorization` header.
+    pub headers: HeaderMap,
+    /// The parameters given with the request, both from the query string and from a
+    /// form-encoded request body.
+    pub params: HashMap<String, String>,
+    /// The raw body of the request.
+    pub body: Vec<u8>,
+}
+
+/// An in-process fake of the Twitter API.
+///
+/// For an overview of what this type can do, see the [module documentation](index.html).
+///
+/// `MockTwitter` is a handle to shared state, so clones of it refer to the same fake server. To
+/// send requests to it, either wrap the code under test with `run`, or call `install` to send
+/// every request in the process to it.
+#[derive(Clone)]
+pub struct MockTwitter {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTwitter {
+    /// Creates a new `MockTwitter`, seeded with egg-mode's sample payloads.
+    pub fn new() -> MockTwitter {
+        MockTwitter {
+            state: Arc::new(Mutex::new(MockState::new())),
+        }
+    }
+
+    /// Returns a `Token` that can be used with a `MockTwitter`.
+    ///
+    /// `MockTwitter` doesn't check the signatures of the requests it receives, so any `Token` will
+    /// do, but this saves you from making one up.
+    pub fn token() -> Token {
+        Token::Access {
+            consumer: KeyPair::new("mock-consumer-key", "mock-consumer-secret"),
+            access: KeyPair::new("mock-access-key", "mock-access-secret"),
+        }
+    }
+
+    /// Runs the given future, sending all the requests it makes to this `MockTwitter`.
+    ///
+    /// This uses [`raw::with_transport`], so other code in the process is unaffected.
+    ///
+    /// [`raw::with_transport`]: ../raw/fn.with_transport.html
+    pub fn run<F: Future>(&self, fut: F) -> WithTransport<F> {
+        with_transport(Arc::new(self.clone()), fut)
+    }
+
+    /// Sends every further request in the process to this `MockTwitter`.
+    ///
+    /// This uses [`raw::set_shared_transport`]. To go back to sending requests to Twitter, call
+    /// `raw::set_shared_transport(raw::default_transport())`.
+    ///
+    /// [`raw::set_shared_transport`]: ../raw/fn.set_shared_transport.html
+    pub fn install(&self) {
+        set_shared_transport(Arc::new(self.clone()));
+    }
+
+    /// Overrides the response for the given method and path, like `GET /1.1/users/show.json`.
+    ///
+    /// All further requests to this endpoint will receive the given status code and body. No
+    /// rate-limit headers are sent with these responses.
+    pub fn respond(&self, method: Method, path: &str, status: StatusCode, body: impl Into<String>) {
+        self.lock()
+            .overrides
+            .insert((method, path.to_string()), (status, body.into()));
+    }
+
+    /// Sets the number of calls remaining in the current rate-limit window for the given path,
+    /// like `/1.1/statuses/home_timeline.json`.
+    ///
+    /// Once the remaining calls reach zero, the endpoint will respond with error 88 until the
+    /// window resets, fifteen minutes after the `MockTwitter` was created.
+    pub fn set_remaining(&self, path: &str, remaining: i32) {
+        self.lock().remaining.insert(path.to_string(), remaining);
+    }
+
+    /// Like `set_remaining`, but only for calls made with the given token, which are counted
+    /// separately from calls made with other tokens.
+    ///
+    /// For a `Token::Pool`, this gives each token in the pool its own limit.
+    pub fn set_remaining_for(&self, token: &Token, path: &str, remaining: i32) {
+        let mut state = self.lock();
+        for caller in callers(token) {
+            state
+                .remaining_for
+                .insert((caller, path.to_string()), remaining);
+        }
+    }
+
+    /// Makes the next `times` calls to the given path fail with error 88, with a rate limit that
+    /// resets a minute later.
+    ///
+    /// These calls don't count against the endpoint's rate limit.
+    pub fn inject_rate_limit(&self, path: &str, times: usize) {
+        *self.lock().injected.entry(path.to_string()).or_insert(0) += times;
+    }
+
+    /// Serves the given items from the cursored endpoint at the given path, like
+    /// `/1.1/followers/ids.json`, under the given key of each page, like `ids`.
+    pub fn set_cursor_items(&self, path: &str, key: &str, items: impl IntoIterator<Item = Value>) {
+        self.lock().cursors.insert(
+            path.to_string(),
+            (key.to_string(), items.into_iter().collect()),
+        );
+    }
+
+    /// Rejects all further requests made with the given OAuth 2.0 access token or Bearer token as
+    /// invalid, responding with a 401 status and error 89.
+    ///
+    /// OAuth 2.0 tokens can still be refreshed, which gives them a new access token that's
+    /// accepted again.
+    pub fn expire_token(&self, access_token: &str) {
+        self.lock().expired.insert(access_token.to_string());
+    }
+
+    /// Adds the given JSON message to those sent to each stream connection.
+    ///
+    /// Each message is sent on its own line, after the messages that were already present and
+    /// before the final keep-alive ping. Make sure the message doesn't contain any line breaks.
+    pub fn push_stream_message(&self, message: impl Into<String>) {
+        self.lock().stream.push(message.into());
+    }
+
+    /// Returns every request this `MockTwitter` has received so far, in order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.lock().requests.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for MockTwitter {
+    fn default() -> MockTwitter {
+        MockTwitter::new()
+    }
+}
+
+impl Transport for MockTwitter {
+    fn send(&self, request: Request<Body>) -> TransportFuture {
+        let mock = self.clone();
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await?.to_vec();
+
+            let mut params = HashMap::new();
+            if let Some(query) = parts.uri.query() {
+                params.extend(url::form_urlencoded::parse(query.as_bytes()).into_owned());
+            }
+            let form = matches!(
+                parts.headers.get(CONTENT_TYPE).and_then(|c| c.to_str().ok()),
+                Some(c) if c.starts_with("application/x-www-form-urlencoded")
+            );
+            if form {
+                params.extend(url::form_urlencoded::parse(&body).into_owned());
+            }
+
+            let request = MockRequest {
+                method: parts.method,
+                path: parts.uri.path().to_string(),
+                headers: parts.headers,
+                params,
+                body,
+            };
+
+            let mut state = mock.lock();
+            state.requests.push(request.clone());
+            Ok(state.handle(&request))
+        })
+    }
+}
+
+/// A media upload in progress.
+struct MediaUpload {
+    total_bytes: usize,
+    received_bytes: usize,
+    category: String,
+}
+
+/// The state behind a `MockTwitter`.
+struct MockState {
+    /// Every tweet, sorted from newest to oldest.
+    tweets: Vec<Value>,
+    users: Vec<Value>,
+    list: Value,
+    /// Every DM event, sorted from newest to oldest.
+    messages: Vec<Value>,
+    stream: Vec<String>,
+    media: HashMap<u64, MediaUpload>,
+    next_id: u64,
+    overrides: HashMap<(Method, String), (StatusCode, String)>,
+    remaining: HashMap<String, i32>,
+    /// The calls remaining for a path with a single token, keyed by the token's access key.
+    remaining_for: HashMap<(String, String), i32>,
+    reset: i64,
+    /// The number of calls to each path that still need to fail with error 88.
+    injected: HashMap<String, usize>,
+    /// The items and page key of each cursored endpoint whose items were replaced.
+    cursors: HashMap<String, (String, Vec<Value>)>,
+    /// The access tokens given to `expire_token`.
+    expired: HashSet<String>,
+    requests: Vec<MockRequest>,
+}
+
+/// A response from the mock server, prior to being turned into a `hyper::Response`.
+struct Reply {
+    status: StatusCode,
+    body: String,
+}
+
+impl Reply {
+    fn json(value: Value) -> Reply {
+        Reply {
+            status: StatusCode::OK,
+            body: value.to_string(),
+        }
+    }
+
+    fn empty() -> Reply {
+        Reply {
+            status: StatusCode::NO_CONTENT,
+            body: String::new(),
+        }
+    }
+
+    fn error(status: StatusCode, code: i32, message: &str) -> Reply {
+        Reply {
+            status,
+            body: json!({ "errors": [{ "code": code, "message": message }] }).to_string(),
+        }
+    }
+
+    fn not_found() -> Reply {
+        Reply::error(
+            StatusCode::NOT_FOUND,
+            34,
+            "Sorry, that page does not exist.",
+        )
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn id_of(value: &Value) -> u64 {
+    value["id"]
+        .as_u64()
+        .or_else(|| value["id"].as_str().and_then(|id| id.parse().ok()))
+        .unwrap_or(0)
+}
+
+fn has_screen_name(user: &Value, name: &str) -> bool {
+    matches!(user["screen_name"].as_str(), Some(n) if n.eq_ignore_ascii_case(name))
+}
+
+fn param<T: std::str::FromStr>(params: &HashMap<String, String>, name: &str) -> Option<T> {
+    params.get(name).and_then(|p| p.parse().ok())
+}
+
+fn id_list(params: &HashMap<String, String>, name: &str) -> Vec<u64> {
+    params
+        .get(name)
+        .map(|ids| {
+            ids.split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the page of the given items asked for by the `cursor` and `count` parameters, using
+/// the offset of each page as its cursor.
+fn cursor_page(key: &str, items: Vec<Value>, params: &HashMap<String, String>) -> Value {
+    let start = param::<usize>(params, "cursor")
+        .unwrap_or(0)
+        .min(items.len());
+    let count = param(params, "count").unwrap_or(items.len()).max(1);
+    let end = items.len().min(start + count);
+    let next = if end < items.len() { end } else { 0 };
+    json!({
+        key: &items[start..end],
+        "next_cursor": next,
+        "next_cursor_str": next.to_string(),
+        "previous_cursor": 0,
+        "previous_cursor_str": "0",
+    })
+}
+
+/// Returns the key that the rate limits of requests with the given headers are counted under,
+/// which is the access token or Bearer token they were signed with.
+fn caller(headers: &HeaderMap) -> String {
+    let auth = headers
+        .get(AUTHORIZATION)
+        .and_then(|a| a.to_str().ok())
+        .unwrap_or("");
+    if let Some(bearer) = auth.strip_prefix("Bearer ") {
+        return bearer.to_string();
+    }
+    auth.trim_start_matches("OAuth ")
+        .split(',')
+        .filter_map(|p| p.trim().strip_prefix("oauth_token="))
+        .map(|t| percent_encoding::percent_decode_str(t.trim_matches('"')).decode_utf8_lossy())
+        .map(|t| t.into_owned())
+        .next()
+        .unwrap_or_default()
+}
+
+/// Returns the keys that the rate limits of requests signed with the given token are counted
+/// under. See `caller`.
+fn callers(token: &Token) -> Vec<String> {
+    match token {
+        Token::Access { access, .. } => vec![access.key.to_string()],
+        Token::Bearer(bearer) => vec![bearer.clone()],
+        Token::OAuth2(token) => vec![token.access_token.clone()],
+        Token::Shared(shared) => vec![shared.current().access_token],
+        Token::Pool(pool) => pool.tokens().iter().flat_map(callers).collect(),
+    }
+}
+
+/// Returns the lowercase terms of the given search query that `MockTwitter` looks for, skipping
+/// operators.
+fn search_terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .filter(|t| !t.contains(':') && !t.starts_with('-'))
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// Returns whether the given tweet contains all of the given search terms, either in its text or
+/// as its author.
+fn matches_terms(tweet: &Value, terms: &[String]) -> bool {
+    let text = tweet["full_text"]
+        .as_str()
+        .or_else(|| tweet["text"].as_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let author = tweet["user"]["screen_name"]
+        .as_str()
+        .unwrap_or("")
+        .to_lowercase();
+    terms.iter().all(|term| {
+        let term = term.trim_start_matches(&['#', '@'][..]);
+        text.contains(term) || author == term
+    })
+}
+
+fn is_stream(path: &str) -> bool {
+    path == "/1.1/statuses/sample.json" || path == "/1.1/statuses/filter.json"
+}
+
+impl MockState {
+    fn new() -> MockState {
+        let mut tweets: Vec<Value> = serde_json::from_str(TWEETS).unwrap();
+        tweets.sort_by_key(|t| std::cmp::Reverse(id_of(t)));
+        let users: Vec<Value> = serde_json::from_str(USERS).unwrap();
+        let list: Value = serde_json::from_str(LIST).unwrap();
+        let stream_tweet: Value = serde_json::from_str(STREAM_TWEET).unwrap();
+        let next_id = tweets.first().map_or(1, |t| id_of(t) + 1);
+
+        let mut state = MockState {
+            tweets,
+            users,
+            list,
+            messages: Vec::new(),
+            stream: vec![stream_tweet.to_string()],
+            media: HashMap::new(),
+            next_id,
+            overrides: HashMap::new(),
+            remaining: HashMap::new(),
+            remaining_for: HashMap::new(),
+            reset: now() + RATE_LIMIT_WINDOW,
+            injected: HashMap::new(),
+            cursors: HashMap::new(),
+            expired: HashSet::new(),
+            requests: Vec::new(),
+        };
+
+        let me = state.me_id();
+        let dev = state
+            .find_user(None, Some("TwitterDev"))
+            .map_or(0, |u| id_of(&u));
+        let first = state.dm_event(dev, me, "Hey, have you tried egg-mode?");
+        let second = state.dm_event(me, dev, "I have! It's great.");
+        state.messages = vec![second, first];
+
+        state
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn me_id(&self) -> u64 {
+        self.find_user(None, Some(ME)).map_or(0, |u| id_of(&u))
+    }
+
+    fn find_user(&self, id: Option<u64>, screen_name: Option<&str>) -> Option<Value> {
+        self.users
+            .iter()
+            .find(|u| {
+                id == Some(id_of(u))
+                    || matches!(screen_name, Some(name) if has_screen_name(u, name))
+            })
+            .cloned()
+    }
+
+    fn requested_user(&self, params: &HashMap<String, String>) -> Option<Value> {
+        let id = param(params, "user_id");
+        let screen_name = params.get("screen_name").map(|s| s.as_str());
+        if id.is_none() && screen_name.is_none() {
+            self.find_user(None, Some(ME))
+        } else {
+            self.find_user(id, screen_name)
+        }
+    }
+
+    fn find_tweet(&self, id: u64) -> Option<Value> {
+        self.tweets.iter().find(|t| id_of(t) == id).cloned()
+    }
+
+    fn dm_event(&mut self, sender: u64, recipient: u64, text: &str) -> Value {
+        json!({
+            "type": "message_create",
+            "id": self.next_id().to_string(),
+            "created_timestamp": (now() * 1000).to_string(),
+            "message_create": {
+                "target": { "recipient_id": recipient.to_string() },
+                "sender_id": sender.to_string(),
+                "message_data": {
+                    "text": text,
+                    "entities": { "hashtags": [], "symbols": [], "urls": [], "user_mentions": [] },
+                },
+            },
+        })
+    }
+
+    fn timeline(&self, params: &HashMap<String, String>, user: Option<u64>) -> Value {
+        let count = param(params, "count").unwrap_or(20);
+        let max_id = param(params, "max_id").unwrap_or(u64::MAX);
+        let since_id = param(params, "since_id").unwrap_or(0);
+        let exclude_replies = param(params, "exclude_replies").unwrap_or(false);
+        let tweets = self
+            .tweets
+            .iter()
+            .filter(|t| {
+                let id = id_of(t);
+                id <= max_id && id > since_id
+            })
+            .filter(|t| user.is_none() || user == Some(id_of(&t["user"])))
+            .take(count)
+            .filter(|t| !exclude_replies || t["in_reply_to_status_id"].is_null())
+            .cloned()
+            .collect::<Vec<_>>();
+        Value::Array(tweets)
+    }
+
+    fn search(&self, params: &HashMap<String, String>) -> Value {
+        let query = params.get("q").cloned().unwrap_or_default();
+        let terms = search_terms(&query);
+        let mut matches = self.timeline(params, None);
+        if let Value::Array(ref mut tweets) = matches {
+            tweets.retain(|t| matches_terms(t, &terms));
+        }
+        let ids = matches
+            .as_array()
+            .map(|tweets| tweets.iter().map(id_of).collect::<Vec<_>>())
+            .unwrap_or_default();
+        json!({
+            "statuses": matches,
+            "search_metadata": {
+                "completed_in": 0.01,
+                "max_id": ids.iter().max().cloned().unwrap_or(0),
+                "query": query,
+                "count": ids.len(),
+                "since_id": param(params, "since_id").unwrap_or(0u64),
+            },
+        })
+    }
+
+    /// Serves a page of premium search results, using the offset of each page as its `next`
+    /// token.
+    fn archive_search(&self, params: &HashMap<String, String>) -> Value {
+        let terms = search_terms(params.get("query").map_or("", |q| q.as_str()));
+        let matches = self
+            .tweets
+            .iter()
+            .filter(|t| matches_terms(t, &terms))
+            .collect::<Vec<_>>();
+        let start = param::<usize>(params, "next")
+            .unwrap_or(0)
+            .min(matches.len());
+        let end = matches
+            .len()
+            .min(start + param(params, "maxResults").unwrap_or(100));
+        let mut page = json!({ "results": &matches[start..end] });
+        if end < matches.len() {
+            page["next"] = json!(end.to_string());
+        }
+        page
+    }
+
+    /// Gives out a new OAuth 2.0 token, for either the authorization code or a refresh token.
+    fn oauth2_token(&mut self) -> Reply {
+        let id = self.next_id();
+        Reply::json(json!({
+            "token_type": "bearer",
+            "expires_in": 7200,
+            "access_token": format!("mock-oauth2-token-{}", id),
+            "refresh_token": format!("mock-refresh-token-{}", id),
+            "scope": "tweet.read users.read offline.access",
+        }))
+    }
+
+    fn post_tweet(&mut self, params: &HashMap<String, String>) -> Reply {
+        let text = match params.get("status") {
+            Some(text) => text.clone(),
+            None => {
+                return Reply::error(
+                    StatusCode::FORBIDDEN,
+                    170,
+                    "Missing required parameter: status.",
+                )
+            }
+        };
+        let me = match self.find_user(None, Some(ME)) {
+            Some(me) => me,
+            None => return Reply::not_found(),
+        };
+        let id = self.next_id();
+        let reply_to =
+            param::<u64>(params, "in_reply_to_status_id").and_then(|id| self.find_tweet(id));
+        let created_at = chrono::Utc::now().format("%a %b %d %T %z %Y").to_string();
+        let tweet = json!({
+            "created_at": created_at,
+            "id": id,
+            "id_str": id.to_string(),
+            "full_text": text,
+            "truncated": false,
+            "display_text_range": [0, text.chars().count()],
+            "entities": { "hashtags": [], "symbols": [], "urls": [], "user_mentions": [] },
+            "source": "<a href=\"https://github.com/egg-mode-rs/egg-mode\" rel=\"nofollow\">egg-mode</a>",
+            "in_reply_to_status_id": reply_to.as_ref().map(id_of),
+            "in_reply_to_status_id_str": reply_to.as_ref().map(|t| id_of(t).to_string()),
+            "in_reply_to_user_id": reply_to.as_ref().map(|t| id_of(&t["user"])),
+            "in_reply_to_user_id_str": reply_to.as_ref().map(|t| id_of(&t["user"]).to_string()),
+            "in_reply_to_screen_name": reply_to.as_ref().map(|t| t["user"]["screen_name"].clone()),
+            "user": me,
+            "geo": null,
+            "coordinates": null,
+            "place": null,
+            "contributors": null,
+            "is_quote_status": false,
+            "retweet_count": 0,
+            "favorite_count": 0,
+            "favorited": false,
+            "retweeted": false,
+            "lang": "en",
+        });
+        self.tweets.insert(0, tweet.clone());
+        Reply::json(tweet)
+    }
+
+    fn media(&mut self, method: &Method, params: &HashMap<String, String>) -> Reply {
+        let command = params.get("command").map(|c| c.as_str()).unwrap_or("");
+        let media_id = param::<u64>(params, "media_id");
+        match (method, command) {
+            (&Method::POST, "INIT") => {
+                let id = self.next_id();
+                self.media.insert(
+                    id,
+                    MediaUpload {
+                        total_bytes: param(params, "total_bytes").unwrap_or(0),
+                        received_bytes: 0,
+                        category: params.get("media_category").cloned().unwrap_or_default(),
+                    },
+                );
+                Reply::json(json!({
+                    "media_id": id,
+                    "media_id_string": id.to_string(),
+                    "expires_after_secs": 86400,
+                }))
+            }
+            (&Method::POST, "APPEND") => {
+                let upload = match media_id.and_then(|id| self.media.get_mut(&id)) {
+                    Some(upload) => upload,
+                    None => return Reply::error(StatusCode::BAD_REQUEST, 324, "Invalid media id."),
+                };
+                let data = params.get("media_data").map(|d| d.as_str()).unwrap_or("");
+                match base64::decode(data) {
+                    Ok(data) => {
+                        upload.received_bytes += data.len();
+                        Reply::empty()
+                    }
+                    Err(_) => Reply::error(StatusCode::BAD_REQUEST, 324, "Invalid media data."),
+                }
+            }
+            (&Method::POST, "FINALIZE") => {
+                let id = media_id.unwrap_or(0);
+                let upload = match self.media.get(&id) {
+                    Some(upload) => upload,
+                    None => return Reply::error(StatusCode::BAD_REQUEST, 324, "Invalid media id."),
+                };
+                if upload.received_bytes != upload.total_bytes {
+                    return Reply::error(
+                        StatusCode::BAD_REQUEST,
+                        324,
+                        "File size does not match the size given in INIT.",
+                    );
+                }
+                let mut media = json!({
+                    "media_id": id,
+                    "media_id_string": id.to_string(),
+                    "size": upload.total_bytes,
+                    "expires_after_secs": 86400,
+                });
+                if upload.category.ends_with("_video") || upload.category.ends_with("_gif") {
+                    media["processing_info"] = json!({
+                        "state": "pending",
+                        "check_after_secs": 0,
+                    });
+                }
+                Reply::json(media)
+            }
+            (&Method::GET, "STATUS") => {
+                let id = media_id.unwrap_or(0);
+                if !self.media.contains_key(&id) {
+                    return Reply::error(StatusCode::BAD_REQUEST, 324, "Invalid media id.");
+                }
+                Reply::json(json!({
+                    "media_id": id,
+                    "media_id_string": id.to_string(),
+                    "expires_after_secs": 86400,
+                    "processing_info": { "state": "succeeded", "progress_percent": 100 },
+                }))
+            }
+            _ => Reply::error(StatusCode::BAD_REQUEST, 38, "command parameter is missing."),
+        }
+    }
+
+    fn send_dm(&mut self, body: &[u8]) -> Reply {
+        let message: Value = match serde_json::from_slice(body) {
+            Ok(message) => message,
+            Err(_) => return Reply::error(StatusCode::BAD_REQUEST, 214, "Invalid event."),
+        };
+        let create = &message["event"]["message_create"];
+        let recipient = create["target"]["recipient_id"].as_u64().or_else(|| {
+            create["target"]["recipient_id"]
+                .as_str()
+                .and_then(|r| r.parse().ok())
+        });
+        let recipient = match recipient {
+            Some(recipient) => recipient,
+            None => return Reply::error(StatusCode::BAD_REQUEST, 214, "Invalid event."),
+        };
+        let text = create["message_data"]["text"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        let me = self.me_id();
+        let event = self.dm_event(me, recipient, &text);
+        self.messages.insert(0, event.clone());
+        Reply::json(json!({ "event": event }))
+    }
+
+    /// Consumes a call from the rate limit of the given path for the given caller, returning the
+    /// number of calls remaining and whether this call is allowed to go through.
+    fn rate_limit(&mut self, caller: String, path: &str) -> (i32, bool) {
+        if now() >= self.reset {
+            self.reset = now() + RATE_LIMIT_WINDOW;
+            self.remaining.clear();
+            self.remaining_for.clear();
+        }
+        let key = (caller, path.to_string());
+        let remaining = match self.remaining_for.get_mut(&key) {
+            Some(remaining) => remaining,
+            None => self.remaining.entry(key.1).or_insert(RATE_LIMIT),
+        };
+        if *remaining <= 0 {
+            (0, false)
+        } else {
+            *remaining -= 1;
+            (*remaining, true)
+        }
+    }
+
+    fn handle(&mut self, request: &MockRequest) -> hyper::Response<Body> {
+        let caller = caller(&request.headers);
+        if self.expired.contains(&caller) && request.path != "/2/oauth2/token" {
+            let reply = Reply::error(StatusCode::UNAUTHORIZED, 89, "Invalid or expired token.");
+            let mut resp = hyper::Response::new(Body::from(reply.body));
+            *resp.status_mut() = reply.status;
+            return resp;
+        }
+
+        let key = (request.method.clone(), request.path.clone());
+        if let Some((status, body)) = self.overrides.get(&key) {
+            let mut resp = hyper::Response::new(Body::from(body.clone()));
+            *resp.status_mut() = *status;
+            return resp;
+        }
+
+        if is_stream(&request.path) {
+            let length_delimited =
+                matches!(param::<String>(&request.params, "delimited"), Some(d) if d == "length");
+            // send each message in its own chunk, like they would arrive over the network
+            let chunks = self
+                .stream
+                .iter()
+                .map(|m| {
+                    if length_delimited {
+                        format!("{}\r\n{}\r\n", m.len() + 2, m)
+                    } else {
+                        format!("{}\r\n", m)
+                    }
+                })
+                .chain(Some("\r\n".to_string()))
+                .map(Ok::<_, std::io::Error>)
+                .collect::<Vec<_>>();
+            return hyper::Response::new(Body::wrap_stream(futures::stream::iter(chunks)));
+        }
+
+        let injected = match self.injected.get_mut(&request.path) {
+            Some(times) if *times > 0 => {
+                *times -= 1;
+                true
+            }
+            _ => false,
+        };
+        let (remaining, reset, reply) = if injected {
+            let reply = Reply::error(StatusCode::TOO_MANY_REQUESTS, 88, "Rate limit exceeded");
+            (0, now() + INJECTED_RESET, reply)
+        } else {
+            match self.rate_limit(caller, &request.path) {
+                (remaining, true) => (remaining, self.reset, self.route(request)),
+                (remaining, false) => {
+                    let reply =
+                        Reply::error(StatusCode::TOO_MANY_REQUESTS, 88, "Rate limit exceeded");
+                    (remaining, self.reset, reply)
+                }
+            }
+        };
+
+        let mut resp = hyper::Response::new(Body::from(reply.body));
+        *resp.status_mut() = reply.status;
+        let headers = resp.headers_mut();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/json;charset=utf-8"),
+        );
+        headers.insert("x-rate-limit-limit", RATE_LIMIT.into());
+        headers.insert("x-rate-limit-remaining", remaining.into());
+        headers.insert("x-rate-limit-reset", reset.into());
+        resp
+    }
+
+    fn route(&mut self, request: &MockRequest) -> Reply {
+        let params = &request.params;
+        let path = request.path.as_str();
+
+        if let Some((key, items)) = self.cursors.get(path) {
+            return Reply::json(cursor_page(key, items.clone(), params));
+        }
+        if path.starts_with("/1.1/tweets/search/") {
+            return Reply::json(self.archive_search(params));
+        }
+
+        if let Some(rest) = path.strip_prefix("/1.1/statuses/") {
+            let stem_id = |stem: &str| -> Option<u64> {
+                rest.strip_prefix(stem)
+                    .and_then(|r| r.strip_suffix(".json"))
+                    .and_then(|id| id.parse().ok())
+            };
+            if let Some(id) = stem_id("retweets/") {
+                return match self.find_tweet(id) {
+                    Some(_) => Reply::json(json!([])),
+                    None => {
+                        Reply::error(StatusCode::NOT_FOUND, 144, "No status found with that ID.")
+                    }
+                };
+            }
+            if let Some(id) = stem_id("retweet/").or_else(|| stem_id("unretweet/")) {
+                return match self.find_tweet(id) {
+                    Some(tweet) => Reply::json(tweet),
+                    None => {
+                        Reply::error(StatusCode::NOT_FOUND, 144, "No status found with that ID.")
+                    }
+                };
+            }
+            if let Some(id) = stem_id("destroy/") {
+                return match self.tweets.iter().position(|t| id_of(t) == id) {
+                    Some(idx) => Reply::json(self.tweets.remove(idx)),
+                    None => {
+                        Reply::error(StatusCode::NOT_FOUND, 144, "No status found with that ID.")
+                    }
+                };
+            }
+        }
+
+        match (&request.method, path) {
+            (&Method::GET, "/1.1/statuses/home_timeline.json")
+            | (&Method::GET, "/1.1/statuses/mentions_timeline.json")
+            | (&Method::GET, "/1.1/statuses/retweets_of_me.json")
+            | (&Method::GET, "/1.1/favorites/list.json")
+            | (&Method::GET, "/1.1/lists/statuses.json") => {
+                Reply::json(self.timeline(params, None))
+            }
+            (&Method::GET, "/1.1/statuses/user_timeline.json") => match self.requested_user(params)
+            {
+                Some(user) => Reply::json(self.timeline(params, Some(id_of(&user)))),
+                None => Reply::error(
+                    StatusCode::NOT_FOUND,
+                    34,
+                    "Sorry, that page does not exist.",
+                ),
+            },
+            (&Method::GET, "/1.1/statuses/show.json") => {
+                match param(params, "id").and_then(|id| self.find_tweet(id)) {
+                    Some(tweet) => Reply::json(tweet),
+                    None => {
+                        Reply::error(StatusCode::NOT_FOUND, 144, "No status found with that ID.")
+                    }
+                }
+            }
+            (_, "/1.1/statuses/lookup.json") => {
+                let ids = id_list(params, "id");
+                if params.get("map").map(|m| m.as_str()) == Some("true") {
+                    let map = ids
+                        .iter()
+                        .map(|id| (id.to_string(), self.find_tweet(*id).unwrap_or(Value::Null)))
+                        .collect::<serde_json::Map<_, _>>();
+                    Reply::json(json!({ "id": map }))
+                } else {
+                    let tweets = ids
+                        .iter()
+                        .filter_map(|id| self.find_tweet(*id))
+                        .collect::<Vec<_>>();
+                    Reply::json(Value::Array(tweets))
+                }
+            }
+            (&Method::GET, "/1.1/statuses/retweeters/ids.json") => {
+                Reply::json(cursor_page("ids", Vec::new(), params))
+            }
+            (&Method::POST, "/1.1/statuses/update.json") => self.post_tweet(params),
+            (&Method::POST, "/1.1/favorites/create.json")
+            | (&Method::POST, "/1.1/favorites/destroy.json") => {
+                match param(params, "id").and_then(|id| self.find_tweet(id)) {
+                    Some(tweet) => Reply::json(tweet),
+                    None => {
+                        Reply::error(StatusCode::NOT_FOUND, 144, "No status found with that ID.")
+                    }
+                }
+            }
+            (&Method::GET, "/1.1/search/tweets.json") => Reply::json(self.search(params)),
+
+            (&Method::GET, "/1.1/account/verify_credentials.json") => {
+                match self.find_user(None, Some(ME)) {
+                    Some(user) => Reply::json(user),
+                    None => Reply::not_found(),
+                }
+            }
+            (&Method::GET, "/1.1/users/show.json")
+            | (&Method::GET, "/1.1/lists/members/show.json")
+            | (&Method::GET, "/1.1/lists/subscribers/show.json")
+            | (&Method::POST, "/1.1/friendships/create.json")
+            | (&Method::POST, "/1.1/friendships/destroy.json")
+            | (&Method::POST, "/1.1/blocks/create.json")
+            | (&Method::POST, "/1.1/blocks/destroy.json")
+            | (&Method::POST, "/1.1/mutes/users/create.json")
+            | (&Method::POST, "/1.1/mutes/users/destroy.json")
+            | (&Method::POST, "/1.1/users/report_spam.json") => match self.requested_user(params) {
+                Some(user) => Reply::json(user),
+                None => Reply::error(StatusCode::NOT_FOUND, 50, "User not found."),
+            },
+            (_, "/1.1/users/lookup.json") => {
+                let ids = id_list(params, "user_id");
+                let names = params
+                    .get("screen_name")
+                    .map(|n| {
+                        n.split(',')
+                            .map(|s| s.trim().to_string())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                let users = self
+                    .users
+                    .iter()
+                    .filter(|u| {
+                        ids.contains(&id_of(u)) || names.iter().any(|n| has_screen_name(u, n))
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if users.is_empty() {
+                    Reply::error(
+                        StatusCode::NOT_FOUND,
+                        17,
+                        "No user matches for specified terms.",
+                    )
+                } else {
+                    Reply::json(Value::Array(users))
+                }
+            }
+            (&Method::GET, "/1.1/users/search.json") => {
+                let query = params
+                    .get("q")
+                    .map(|q| q.to_lowercase())
+                    .unwrap_or_default();
+                let users = self
+                    .users
+                    .iter()
+                    .filter(|u| {
+                        ["name", "screen_name"].iter().any(|field| {
+                            matches!(u[*field].as_str(), Some(s) if s.to_lowercase().contains(&query))
+                        })
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Reply::json(Value::Array(users))
+            }
+            (&Method::GET, "/1.1/friends/list.json")
+            | (&Method::GET, "/1.1/followers/list.json")
+            | (&Method::GET, "/1.1/blocks/list.json")
+            | (&Method::GET, "/1.1/mutes/users/list.json")
+            | (&Method::GET, "/1.1/lists/members.json")
+            | (&Method::GET, "/1.1/lists/subscribers.json") => {
+                Reply::json(cursor_page("users", self.users.clone(), params))
+            }
+            (&Method::GET, "/1.1/friends/ids.json")
+            | (&Method::GET, "/1.1/followers/ids.json")
+            | (&Method::GET, "/1.1/blocks/ids.json")
+            | (&Method::GET, "/1.1/mutes/users/ids.json") => {
+                let ids = self.users.iter().map(|u| json!(id_of(u))).collect();
+                Reply::json(cursor_page("ids", ids, params))
+            }
+            (&Method::GET, "/1.1/friendships/incoming.json")
+            | (&Method::GET, "/1.1/friendships/outgoing.json") => {
+                Reply::json(cursor_page("ids", Vec::new(), params))
+            }
+            (&Method::GET, "/1.1/friendships/no_retweets/ids.json") => Reply::json(json!([])),
+
+            (&Method::GET, "/1.1/lists/show.json")
+            | (&Method::POST, "/1.1/lists/create.json")
+            | (&Method::POST, "/1.1/lists/update.json")
+            | (&Method::POST, "/1.1/lists/destroy.json")
+            | (&Method::POST, "/1.1/lists/members/create.json")
+            | (&Method::POST, "/1.1/lists/members/create_all.json")
+            | (&Method::POST, "/1.1/lists/members/destroy.json")
+            | (&Method::POST, "/1.1/lists/members/destroy_all.json")
+            | (&Method::POST, "/1.1/lists/subscribers/create.json")
+            | (&Method::POST, "/1.1/lists/subscribers/destroy.json") => {
+                Reply::json(self.list.clone())
+            }
+            (&Method::GET, "/1.1/lists/list.json") => Reply::json(json!([self.list.clone()])),
+            (&Method::GET, "/1.1/lists/ownerships.json")
+            | (&Method::GET, "/1.1/lists/subscriptions.json")
+            | (&Method::GET, "/1.1/lists/memberships.json") => {
+                Reply::json(cursor_page("lists", vec![self.list.clone()], params))
+            }
+
+            (&Method::GET, "/1.1/direct_messages/events/list.json") => {
+                let count = param(params, "count").unwrap_or(20);
+                let events = self
+                    .messages
+                    .iter()
+                    .take(count)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Reply::json(json!({ "events": events }))
+            }
+            (&Method::GET, "/1.1/direct_messages/events/show.json") => {
+                let id = param(params, "id").unwrap_or(0);
+                match self.messages.iter().find(|m| id_of(m) == id) {
+                    Some(event) => Reply::json(json!({ "event": event })),
+                    None => Reply::not_found(),
+                }
+            }
+            (&Method::POST, "/1.1/direct_messages/events/new.json") => self.send_dm(&request.body),
+            (&Method::DELETE, "/1.1/direct_messages/events/destroy.json") => {
+                let id = param(params, "id").unwrap_or(0);
+                match self.messages.iter().position(|m| id_of(m) == id) {
+                    Some(idx) => {
+                        self.messages.remove(idx);
+                        Reply::empty()
+                    }
+                    None => Reply::not_found(),
+                }
+            }
+            (&Method::POST, "/1.1/direct_messages/mark_read.json")
+            | (&Method::POST, "/1.1/direct_messages/indicate_typing.json") => Reply::empty(),
+
+            (method, "/1.1/media/upload.json") => self.media(method, params),
+            (&Method::POST, "/1.1/media/metadata/create.json") => Reply {
+                status: StatusCode::OK,
+                body: String::new(),
+            },
+
+            (&Method::POST, "/2/oauth2/token") => self.oauth2_token(),
+
+            (&Method::GET, "/1.1/application/rate_limit_status.json") => Reply {
+                status: StatusCode::OK,
+                body: RATE_LIMIT_STATUS.to_string(),
+            },
+
+            _ => Reply::not_found(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::{direct, list, media, stream, tweet, user};
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn timelines_page_through_tweets() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let timeline = tweet::home_timeline(&token).with_page_size(15);
+                let (timeline, first) = timeline.start().await.unwrap();
+                assert_eq!(first.len(), 15);
+                let (_, second) = timeline.older(None).await.unwrap();
+                assert_eq!(second.len(), 5);
+                assert!(second.iter().all(|t| t.id < first.last().unwrap().id));
+
+                let draft = tweet::DraftTweet::new("hello from the mock");
+                let posted = draft.send(&token).await.unwrap();
+                let shown = tweet::show(posted.id, &token).await.unwrap();
+                assert_eq!(shown.text, "hello from the mock");
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn users_and_lists() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let users = user::lookup(vec!["TwitterDev", "rustlang"], &token)
+                    .await
+                    .unwrap();
+                assert_eq!(users.len(), 2);
+
+                let followers = user::followers_ids("rustlang", &token)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(followers.len(), 4);
+
+                let list = list::show(list::ListID::from_id(1122308540973010944), &token)
+                    .await
+                    .unwrap();
+                assert_eq!(list.slug, "all-people-in-spatial-2");
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn direct_messages() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let sent = direct::DraftMessage::new("hi there", 2244994945u64)
+                    .send(&token)
+                    .await
+                    .unwrap();
+                let messages = direct::list(&token)
+                    .into_stream()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(messages.len(), 3);
+                assert_eq!(messages[0].id, sent.id);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn media_upload() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let video = vec![0u8; 1536 * 1024];
+                let handle = media::upload_media(&video, &media::media_types::video_mp4(), &token)
+                    .await
+                    .unwrap();
+                assert_eq!(handle.progress, Some(media::ProgressInfo::Pending(0)));
+                let status = media::get_status(handle.id, &token).await.unwrap();
+                assert_eq!(status.progress, Some(media::ProgressInfo::Success));
+            })
+            .await;
+
+        let appends = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.params.get("command").map(|c| c.as_str()) == Some("APPEND"))
+            .count();
+        assert_eq!(appends, 2);
+    }
+
+    #[tokio::test]
+    async fn streams() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        let messages = twitter
+            .run(stream::sample(&token).try_collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], stream::StreamMessage::Tweet(_)));
+        assert!(matches!(messages[1], stream::StreamMessage::Ping));
+    }
+
+    #[tokio::test]
+    async fn rate_limits() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter.set_remaining("/1.1/users/show.json", 1);
+        twitter
+            .run(async {
+                let user = user::show("rustlang", &token).await.unwrap();
+                assert_eq!(user.rate_limit_status.remaining, 0);
+                match user::show("rustlang", &token).await {
+                    Err(Error::RateLimit(_)) => (),
+                    other => panic!("expected a rate-limit error, got {:?}", other.map(|_| ())),
+                }
+            })
+            .await;
+    }
+}
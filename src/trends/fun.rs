@@ -0,0 +1,57 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::common::*;
+use crate::error::Result;
+use crate::{auth, links};
+
+use super::*;
+
+/// Begins building a request for the top trends at the location with the given WOEID.
+///
+/// To load worldwide trends, use [`WORLDWIDE`] as the WOEID.
+///
+/// [`WORLDWIDE`]: constant.WORLDWIDE.html
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # use egg_mode::Token;
+/// # #[tokio::main]
+/// # async fn main() {
+/// # let token: Token = unimplemented!();
+/// use egg_mode::trends;
+///
+/// let result = trends::place(trends::WORLDWIDE).call(&token).await.unwrap();
+///
+/// for trend in &result.trends {
+///     println!("{}", trend.name);
+/// }
+/// # }
+/// ```
+pub fn place(woeid: u32) -> TrendsBuilder {
+    TrendsBuilder::new(woeid)
+}
+
+/// Loads every location that Twitter has trends for.
+pub async fn available(token: &auth::Token) -> Result<Response<Vec<TrendLocation>>> {
+    let req = get(links::trends::AVAILABLE, token, None);
+    request_with_json_response(req).await
+}
+
+/// Loads the locations Twitter has trends for that are closest to the given coordinate.
+///
+/// The latitude and longitude are given the same way as with `place::search_point`. This is
+/// useful for finding the WOEID to give to `place`.
+pub async fn closest(
+    latitude: f64,
+    longitude: f64,
+    token: &auth::Token,
+) -> Result<Response<Vec<TrendLocation>>> {
+    let params = ParamList::new()
+        .add_param("lat", latitude.to_string())
+        .add_param("long", longitude.to_string());
+    let req = get(links::trends::CLOSEST, token, Some(&params));
+    request_with_json_response(req).await
+}
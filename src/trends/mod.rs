@@ -0,0 +1,184 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Types and methods for loading trending topics.
+//!
+//! Twitter organizes its trends by location, using Yahoo! "Where On Earth" IDs, or WOEIDs, to
+//! identify each one. Trends are available worldwide (with the WOEID given by [`WORLDWIDE`]) and
+//! for a selection of countries and cities. To find the WOEIDs Twitter has trends for, call
+//! `available` to load every location, or `closest` to load the ones nearest to a given
+//! latitude/longitude coordinate, like the one you'd give to `place::search_point`.
+//!
+//! [`WORLDWIDE`]: constant.WORLDWIDE.html
+//!
+//! Once you have a WOEID, hand it to `place` to load the top trends there. This returns a
+//! builder, in case you'd like to leave hashtags out of the results with `exclude_hashtags`:
+//!
+//! ```rust,no_run
+//! # use egg_mode::Token;
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let token: Token = unimplemented!();
+//! use egg_mode::trends;
+//!
+//! let locations = trends::closest(51.507222, -0.1275, &token).await.unwrap();
+//! let london = trends::place(locations[0].woeid)
+//!     .exclude_hashtags()
+//!     .call(&token)
+//!     .await
+//!     .unwrap();
+//!
+//! for trend in &london.trends {
+//!     println!("{} ({:?} tweets)", trend.name, trend.tweet_volume);
+//! }
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+use crate::{auth, error, links};
+
+mod fun;
+
+pub use self::fun::*;
+
+///The WOEID that represents the whole world, for loading worldwide trends.
+pub const WORLDWIDE: u32 = 1;
+
+///Represents a trending topic.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Trend {
+    ///The name of the trend, as shown in Twitter's clients.
+    pub name: String,
+    ///A link to a Twitter search for this trend.
+    pub url: String,
+    ///The search query for this trend, URL-encoded. This can be handed to `search::search` after
+    ///decoding it.
+    pub query: String,
+    ///If this trend is promoted, this is set to a value indicating so.
+    pub promoted_content: Option<String>,
+    ///The number of tweets about this trend over the last 24 hours, if Twitter has this
+    ///information.
+    pub tweet_volume: Option<u64>,
+}
+
+///Represents a location that Twitter has trends for.
+///
+///When returned from `place`, only `name` and `woeid` are filled in; the remaining fields are
+///given by `available` and `closest`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrendLocation {
+    ///Human-readable name of this location.
+    pub name: String,
+    ///The WOEID of this location, for loading its trends with `place`.
+    pub woeid: u32,
+    ///Name of the country containing this location. This is empty for the worldwide location.
+    pub country: Option<String>,
+    ///Two-letter country code of the country containing this location.
+    #[serde(rename = "countryCode")]
+    pub country_code: Option<String>,
+    ///The WOEID of the location containing this one.
+    #[serde(rename = "parentid")]
+    pub parent_id: Option<u32>,
+    ///The kind of location this is, like a town or a country.
+    #[serde(rename = "placeType")]
+    pub place_type: Option<TrendPlaceType>,
+    ///A link to information about this location.
+    pub url: Option<String>,
+}
+
+///Represents the kind of location that a `TrendLocation` is.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrendPlaceType {
+    ///Numeric code for this kind of location, like 7 for a town or 12 for a country.
+    pub code: u32,
+    ///Human-readable name for this kind of location, like "Town" or "Country".
+    pub name: String,
+}
+
+///Represents the trends loaded for a location by `place`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrendResult {
+    ///The top trends at this location, with the most popular first.
+    pub trends: Vec<Trend>,
+    ///UTC timestamp of when this list of trends was loaded.
+    pub as_of: chrono::DateTime<chrono::Utc>,
+    ///UTC timestamp of when Twitter created this list of trends.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    ///The locations these trends were loaded for.
+    pub locations: Vec<TrendLocation>,
+}
+
+///Represents an in-progress request for the trends at a location.
+///
+///Use the `place` function to start building a request.
+#[derive(Debug, Clone)]
+pub struct TrendsBuilder {
+    woeid: u32,
+    exclude_hashtags: bool,
+}
+
+impl TrendsBuilder {
+    ///Begins building a request for the trends at the given WOEID.
+    fn new(woeid: u32) -> Self {
+        TrendsBuilder {
+            woeid,
+            exclude_hashtags: false,
+        }
+    }
+
+    ///Leaves any trends that are hashtags out of the results.
+    pub fn exclude_hashtags(self) -> Self {
+        TrendsBuilder {
+            exclude_hashtags: true,
+            ..self
+        }
+    }
+
+    ///Finalize the request and load the trends.
+    pub async fn call(&self, token: &auth::Token) -> Result<Response<TrendResult>, error::Error> {
+        let mut params = ParamList::new().add_param("id", self.woeid.to_string());
+        if self.exclude_hashtags {
+            params.add_param_ref("exclude", "hashtags");
+        }
+
+        let req = get(links::trends::PLACE, token, Some(&params));
+        let resp = request_with_json_response::<Vec<TrendResult>>(req).await?;
+
+        // Twitter wraps the result in a single-element array
+        match resp.response.into_iter().next() {
+            Some(response) => Ok(Response {
+                rate_limit_status: resp.rate_limit_status,
+                response,
+            }),
+            None => Err(error::Error::MissingValue("trends")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrendLocation, TrendResult};
+    use crate::common::tests::load_file;
+
+    #[test]
+    fn parse_trends_samples() {
+        let content = load_file("sample_payloads/trends_place.json");
+        let result = ::serde_json::from_str::<Vec<TrendResult>>(&content).unwrap();
+        assert_eq!(result[0].trends.len(), 3);
+        assert_eq!(result[0].trends[0].name, "#GiftAGamer");
+        assert_eq!(result[0].trends[0].tweet_volume, None);
+        assert_eq!(result[0].trends[1].tweet_volume, Some(20212));
+        assert_eq!(result[0].locations[0].woeid, super::WORLDWIDE);
+        assert_eq!(result[0].as_of.timestamp(), 1486570698);
+
+        let content = load_file("sample_payloads/trends_available.json");
+        let locations = ::serde_json::from_str::<Vec<TrendLocation>>(&content).unwrap();
+        assert_eq!(locations.len(), 2);
+        assert_eq!(locations[1].country_code.as_deref(), Some("SE"));
+        assert_eq!(locations[1].parent_id, Some(1));
+        assert_eq!(locations[1].place_type.as_ref().unwrap().name, "Country");
+    }
+}
@@ -21,6 +21,8 @@
 //!   coordinate are available.
 //! - `Timeline`: Returned by several functions in this module, this is how you cursor through a
 //!   collection of tweets. See the struct-level documentation for details.
+//! - `StopAt`: Given to `Timeline::into_stream_until` to say how far back a stream of tweets should
+//!   go, or how many tweets it should yield.
 //!
 //! ## Functions
 //!
@@ -59,8 +61,10 @@ use std::future::Future;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use chrono;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use hyper::{Body, Request};
 use regex::Regex;
 use serde::{Serialize, Deserialize, Deserializer};
@@ -484,6 +488,56 @@ pub struct ExtendedTweetEntities {
 /// If you want to manually pull tweets between certain IDs, the baseline `call` function can do
 /// that for you. Keep in mind, though, that `call` doesn't update the `min_id` or `max_id` fields,
 /// so you'll have to set those yourself if you want to follow up with `older` or `newer`.
+///
+/// An adapter is provided which converts a `Timeline` into a `futures::stream::Stream` which
+/// yields one tweet at a time, calling `older` to load each page as needed until Twitter runs out
+/// of tweets to give. As the stream's `Item` is a `Result` which can express the error caused by
+/// loading the next page, it also implements `futures::stream::TryStream` as well. To only load
+/// tweets back to a certain point, use `into_stream_until` with a [`StopAt`] bound:
+///
+/// [`StopAt`]: enum.StopAt.html
+///
+/// ```rust,no_run
+/// # use egg_mode::Token;
+/// use egg_mode::tweet::StopAt;
+/// use futures::stream::TryStreamExt;
+/// # #[tokio::main]
+/// # async fn main() {
+/// # let token: Token = unimplemented!();
+/// let yesterday = chrono::Utc::now() - chrono::Duration::days(1);
+/// let timeline = egg_mode::tweet::user_timeline("rustlang", true, true, &token);
+/// let tweets = timeline.with_page_size(200)
+///                      .into_stream_until(StopAt::Date(yesterday))
+///                      .try_collect::<Vec<_>>()
+///                      .await
+///                      .unwrap();
+/// # }
+/// ```
+///
+/// To follow a timeline as new tweets are posted to it, `watch` converts it into a `Stream` that
+/// polls for newer tweets on an interval:
+///
+/// ```rust,no_run
+/// # use egg_mode::Token;
+/// use futures::stream::TryStreamExt;
+/// use std::time::Duration;
+/// # #[tokio::main]
+/// # async fn main() {
+/// # let token: Token = unimplemented!();
+/// let timeline = egg_mode::tweet::mentions_timeline(&token).with_page_size(200);
+/// let mut mentions = Box::pin(timeline.watch(Duration::from_secs(60)));
+///
+/// while let Some(tweet) = mentions.try_next().await.unwrap() {
+///     println!("<@{}> {}", tweet.user.as_ref().unwrap().screen_name, tweet.text);
+/// }
+/// # }
+/// ```
+///
+/// To pick up where a long-running job left off after a restart, save a [`TimelineCheckpoint`]
+/// from `checkpoint` after each page, and give it to `with_checkpoint` on a new `Timeline` from the
+/// same call to restore the tracked IDs.
+///
+/// [`TimelineCheckpoint`]: struct.TimelineCheckpoint.html
 pub struct Timeline {
     ///The URL to request tweets from.
     link: &'static str,
@@ -580,6 +634,130 @@ impl Timeline {
         self.min_id = resp.last().map(|status| status.id);
     }
 
+    ///Returns a checkpoint of the IDs and page size this `Timeline` is tracking, which can be
+    ///given to `with_checkpoint` to resume from this point later.
+    pub fn checkpoint(&self) -> TimelineCheckpoint {
+        TimelineCheckpoint {
+            max_id: self.max_id,
+            min_id: self.min_id,
+            count: self.count,
+        }
+    }
+
+    ///Restores the IDs and page size saved in the given checkpoint, so that `older` and `newer`
+    ///continue from where the `Timeline` the checkpoint came from left off.
+    ///
+    ///The checkpoint should come from a `Timeline` for the same call, with the same parameters.
+    pub fn with_checkpoint(self, checkpoint: TimelineCheckpoint) -> Self {
+        Timeline {
+            max_id: checkpoint.max_id,
+            min_id: checkpoint.min_id,
+            count: checkpoint.count,
+            ..self
+        }
+    }
+
+    ///Converts this `Timeline` into a `Stream` of tweets, which automatically loads older tweets
+    ///as needed until there are no more to load.
+    ///
+    ///The stream picks up from the tweet IDs this `Timeline` is tracking, so a `Timeline` that
+    ///hasn't loaded anything yet starts from the newest tweets, and one that has already loaded a
+    ///page continues with the tweets older than it.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Response<Tweet>>> {
+        self.stream_until(None)
+    }
+
+    ///Converts this `Timeline` into a `Stream` of tweets like `into_stream`, which stops once it
+    ///reaches the given bound.
+    pub fn into_stream_until(self, bound: StopAt) -> impl Stream<Item = Result<Response<Tweet>>> {
+        self.stream_until(Some(bound))
+    }
+
+    ///Helper function to build the `Stream` for `into_stream` and `into_stream_until`.
+    fn stream_until(self, bound: Option<StopAt>) -> impl Stream<Item = Result<Response<Tweet>>> {
+        let bound = StreamBound::new(bound);
+
+        stream::try_unfold(Some((self, bound)), |state| async move {
+            let (timeline, mut bound) = match state {
+                Some(state) => state,
+                None => return Ok::<_, error::Error>(None),
+            };
+            let (timeline, page) = timeline.older(bound.since_id()).await?;
+            if page.is_empty() {
+                return Ok(None);
+            }
+
+            let mut page = page.into_iter().collect::<Vec<_>>();
+            let done = bound.trim(&mut page);
+            Ok(Some((page, if done { None } else { Some((timeline, bound)) })))
+        })
+        .map_ok(|page| stream::iter(page).map(Ok))
+        .try_flatten()
+    }
+
+    ///Converts this `Timeline` into a `Stream` that polls for new tweets on the given interval,
+    ///yielding each one once, oldest first.
+    ///
+    ///The stream only yields tweets newer than this `Timeline`'s `max_id`. If that isn't set
+    ///(because the `Timeline` hasn't loaded anything yet), the first poll only notes the newest
+    ///tweet available, and the stream starts with the tweets posted after it (or with every tweet,
+    ///if the timeline was empty). If more tweets arrive between polls than fit in one page, the
+    ///stream loads as many pages as it takes to catch up, so none are skipped - even when Twitter
+    ///hands back a short page because it left out deleted or filtered tweets.
+    ///
+    ///To stay within the rate limit, the stream waits longer than `interval` between polls if
+    ///polling that often would use up the remaining calls before the rate-limit window resets. If
+    ///Twitter reports that the rate limit has run out anyway, the stream waits for it to reset and
+    ///tries again, as long as the current `RetryPolicy` allows (or the default one, if none is
+    ///set). The stream ends after yielding any other error from loading a page.
+    pub fn watch(self, interval: Duration) -> impl Stream<Item = Result<Response<Tweet>>> {
+        // the newest tweet seen so far, once the first poll has gone out (or if this `Timeline`
+        // has already loaded something)
+        let seen = self.max_id.map(Some);
+        stream::try_unfold((self, seen, None), move |state| async move {
+            let (mut timeline, seen, wait) = state;
+            if let Some(wait) = wait {
+                tokio::time::sleep(wait).await;
+            }
+
+            let since_id = seen.flatten();
+            let mut tweets = Vec::new();
+            let mut max_id = None;
+            let mut calls = 0;
+            let rate_limit = loop {
+                let page = wait_for_rate_limit(|| {
+                    calls += 1;
+                    timeline.call(since_id, max_id)
+                })
+                .await?;
+                let rate_limit = page.rate_limit_status;
+                let oldest = page.last().map(|tweet| tweet.id);
+                tweets.extend(page);
+                // Twitter counts out a page before removing deleted or filtered tweets, so
+                // even a short page can have older tweets behind it; keep going until a page
+                // comes back empty or reaches the last tweet seen
+                match oldest {
+                    // before the first poll, only the newest tweet is needed
+                    Some(_) if seen.is_none() => break rate_limit,
+                    Some(id) if id > since_id.unwrap_or(0) + 1 => max_id = Some(id - 1),
+                    _ => break rate_limit,
+                }
+            };
+
+            let newest = tweets.first().map(|tweet| tweet.id).or(since_id);
+            timeline.max_id = newest;
+            if seen.is_none() {
+                tweets.clear();
+            }
+            tweets.reverse();
+
+            let wait = watch_interval(interval, &rate_limit, calls);
+            Ok::<_, error::Error>(Some((tweets, (timeline, Some(newest), Some(wait)))))
+        })
+        .map_ok(|page| stream::iter(page).map(Ok))
+        .try_flatten()
+    }
+
     ///Create an instance of `Timeline` with the given link and tokens.
     pub(crate) fn new(
         link: &'static str,
@@ -597,6 +775,26 @@ impl Timeline {
     }
 }
 
+///Returns how long `Timeline::watch` should wait before polling again, given that its last poll
+///made the given number of calls.
+fn watch_interval(interval: Duration, rate_limit: &RateLimit, calls: u32) -> Duration {
+    if rate_limit.remaining < 0 || rate_limit.reset < 0 {
+        // no rate-limit headers to go by
+        return interval;
+    }
+
+    let window = i64::from(rate_limit.reset) - chrono::Utc::now().timestamp();
+    let window = Duration::from_secs(window.max(0) as u64);
+    // spread the remaining calls out over the rest of the window, assuming each poll makes as
+    // many calls as the last one
+    let polls = rate_limit.remaining as u32 / calls;
+    if polls == 0 {
+        interval.max(window)
+    } else {
+        interval.max(window / polls)
+    }
+}
+
 /// `Future` which represents loading from a `Timeline`.
 ///
 /// When this future completes, it will either return the tweets given by Twitter (after having
@@ -627,6 +825,83 @@ impl Future for TimelineFuture {
     }
 }
 
+/// A saved position in a `Timeline`, which can be used to resume it later.
+///
+/// A `TimelineCheckpoint` is given by `Timeline::checkpoint`, and can be given back to
+/// `Timeline::with_checkpoint` on a `Timeline` for the same call. It can be serialized, so it can
+/// be saved to disk and used to resume in a later run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineCheckpoint {
+    ///The largest/most recent tweet ID the `Timeline` had loaded.
+    pub max_id: Option<u64>,
+    ///The smallest/oldest tweet ID the `Timeline` had loaded.
+    pub min_id: Option<u64>,
+    ///The page size the `Timeline` was using.
+    pub count: i32,
+}
+
+/// A bound on how far back the stream from `Timeline::into_stream_until` (or
+/// `SearchBuilder::into_stream_until`) loads tweets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopAt {
+    /// Only load tweets newer than the tweet with the given ID.
+    ///
+    /// This is passed to Twitter as `since_id`, so no tweets past the bound are loaded at all.
+    Id(u64),
+    /// Only load tweets posted at or after the given time.
+    ///
+    /// Twitter can't filter timelines by date, so the stream stops once it loads a tweet older
+    /// than this.
+    Date(chrono::DateTime<chrono::Utc>),
+    /// Stop after yielding the given number of tweets.
+    Count(usize),
+}
+
+/// Tracks a stream of tweets' progress toward a `StopAt` bound.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct StreamBound {
+    bound: Option<StopAt>,
+    /// The number of tweets let through so far.
+    seen: usize,
+}
+
+impl StreamBound {
+    pub(crate) fn new(bound: Option<StopAt>) -> StreamBound {
+        StreamBound { bound, seen: 0 }
+    }
+
+    /// Returns the `since_id` to load pages with, if the bound is a tweet ID.
+    pub(crate) fn since_id(&self) -> Option<u64> {
+        match self.bound {
+            Some(StopAt::Id(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Drops the tweets in the given page (which is ordered newest-first) that are past the
+    /// bound, and returns whether the bound was reached.
+    pub(crate) fn trim(&mut self, page: &mut Vec<Response<Tweet>>) -> bool {
+        let done = match self.bound {
+            // tweets come newest-first, so once one is too old, so are the rest
+            Some(StopAt::Date(until)) => match page.iter().position(|t| t.created_at < until) {
+                Some(idx) => {
+                    page.truncate(idx);
+                    true
+                }
+                None => false,
+            },
+            Some(StopAt::Count(count)) => {
+                let left = count.saturating_sub(self.seen);
+                page.truncate(left);
+                page.len() == left
+            }
+            _ => false,
+        };
+        self.seen += page.len();
+        done
+    }
+}
+
 /// Represents an in-progress tweet before it is sent.
 ///
 /// This is your entry point to posting new tweets to Twitter. To begin, make a new `DraftTweet` by
@@ -887,10 +1162,15 @@ impl DraftTweet {
 
 #[cfg(test)]
 mod tests {
-    use super::Tweet;
+    use std::time::Duration;
+
+    use super::{watch_interval, StopAt, TimelineCheckpoint, Tweet};
     use crate::common::tests::load_file;
+    use crate::common::RateLimit;
+    use crate::testing::MockTwitter;
 
     use chrono::{Datelike, Timelike, Weekday};
+    use futures::{StreamExt, TryStreamExt};
 
     fn load_tweet(path: &str) -> Tweet {
         let sample = load_file(path);
@@ -1007,4 +1287,210 @@ mod tests {
 
         assert_eq!(json1, json2);
     }
+
+    #[tokio::test]
+    async fn watch_yields_new_tweets() {
+        const HOME_TIMELINE: &str = "/1.1/statuses/home_timeline.json";
+        tokio::time::pause();
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let timeline = super::home_timeline(&token).with_page_size(5);
+                let (timeline, _) = timeline.start().await.unwrap();
+
+                let mut posted = Vec::new();
+                for i in 0..7 {
+                    let draft = super::DraftTweet::new(format!("new tweet {}", i));
+                    posted.push(draft.send(&token).await.unwrap().id);
+                }
+
+                // the first poll runs into the rate limit, and tries again once it resets
+                twitter.inject_rate_limit(HOME_TIMELINE, 1);
+                let mut watch = Box::pin(timeline.watch(Duration::from_secs(30)));
+                let mut seen = Vec::new();
+                for _ in 0..7 {
+                    seen.push(watch.try_next().await.unwrap().unwrap().id);
+                }
+                assert_eq!(seen, posted);
+
+                let draft = super::DraftTweet::new("one more");
+                let last = draft.send(&token).await.unwrap().id;
+                assert_eq!(watch.try_next().await.unwrap().unwrap().id, last);
+            })
+            .await;
+
+        // the first page, the rate-limited poll, two pages to catch up (and an empty one to make
+        // sure nothing older is left), and one for the last tweet
+        let polls = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.path == HOME_TIMELINE)
+            .count();
+        assert_eq!(polls, 6);
+    }
+
+    #[tokio::test]
+    async fn watch_fills_gaps_behind_short_pages() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let timeline = super::user_timeline("rustlang", false, true, &token);
+                let (timeline, _) = timeline.with_page_size(5).start().await.unwrap();
+
+                let mut posted = Vec::new();
+                for i in 0..3 {
+                    let draft = super::DraftTweet::new(format!("new tweet {}", i));
+                    posted.push(draft.send(&token).await.unwrap().id);
+                }
+                // the replies fill up the next page, which comes back with only the last tweet
+                for i in 0..4 {
+                    let draft = super::DraftTweet::new(format!("reply {}", i));
+                    draft.in_reply_to(posted[2]).send(&token).await.unwrap();
+                }
+                let draft = super::DraftTweet::new("last tweet");
+                posted.push(draft.send(&token).await.unwrap().id);
+
+                let watch = timeline.watch(Duration::from_secs(30));
+                let seen = watch
+                    .take(4)
+                    .map_ok(|tweet| tweet.id)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(seen, posted);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn watch_starts_from_empty_timeline() {
+        tokio::time::pause();
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let (_, page) = super::home_timeline(&token)
+                    .with_page_size(200)
+                    .start()
+                    .await
+                    .unwrap();
+                for tweet in page.iter() {
+                    super::delete(tweet.id, &token).await.unwrap();
+                }
+
+                let timeline = super::home_timeline(&token);
+                let mut watch = Box::pin(timeline.watch(Duration::from_secs(30)));
+                // the first poll finds nothing, so the tweet posted after it is new
+                let post = async {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    let draft = super::DraftTweet::new("first tweet");
+                    draft.send(&token).await.unwrap().id
+                };
+                let (seen, first) = futures::join!(watch.try_next(), post);
+                assert_eq!(seen.unwrap().unwrap().id, first);
+            })
+            .await;
+    }
+
+    #[test]
+    fn watch_respects_rate_limit() {
+        let reset = chrono::Utc::now().timestamp() as i32 + 600;
+        let limit = |remaining| RateLimit {
+            limit: 900,
+            remaining,
+            reset,
+        };
+        let interval = Duration::from_secs(5);
+
+        assert_eq!(watch_interval(interval, &limit(500), 1), interval);
+        let wait = watch_interval(interval, &limit(10), 2);
+        assert!(wait > Duration::from_secs(100) && wait <= Duration::from_secs(120));
+        assert!(watch_interval(interval, &limit(0), 1) > Duration::from_secs(590));
+        assert_eq!(watch_interval(interval, &limit(-1), 1), interval);
+    }
+
+    #[tokio::test]
+    async fn resume_from_checkpoint() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let timeline = super::user_timeline("rustlang", true, true, &token);
+                let (timeline, first) = timeline.with_page_size(5).start().await.unwrap();
+                let (timeline, second) = timeline.older(None).await.unwrap();
+
+                let json = serde_json::to_string(&timeline.checkpoint()).unwrap();
+                let checkpoint: TimelineCheckpoint = serde_json::from_str(&json).unwrap();
+                assert_eq!(checkpoint.min_id, second.last().map(|tweet| tweet.id));
+
+                let resumed = super::user_timeline("rustlang", true, true, &token)
+                    .with_checkpoint(checkpoint);
+                assert_eq!(resumed.count, 5);
+                let (_, third) = resumed.older(None).await.unwrap();
+                let (_, expected) = timeline.older(None).await.unwrap();
+                assert_eq!(
+                    third.iter().map(|tweet| tweet.id).collect::<Vec<_>>(),
+                    expected.iter().map(|tweet| tweet.id).collect::<Vec<_>>()
+                );
+
+                let (_, newer) = super::user_timeline("rustlang", true, true, &token)
+                    .with_checkpoint(checkpoint)
+                    .newer(None)
+                    .await
+                    .unwrap();
+                // the tracked IDs are from the second page, so the newer tweets are the first page
+                assert_eq!(
+                    newer.iter().map(|tweet| tweet.id).collect::<Vec<_>>(),
+                    first.iter().map(|tweet| tweet.id).collect::<Vec<_>>()
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn timeline_stream() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter
+            .run(async {
+                let all = super::home_timeline(&token)
+                    .with_page_size(6)
+                    .into_stream()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(all.len(), 20);
+                assert!(all.windows(2).all(|w| w[0].id > w[1].id));
+
+                let newer = super::home_timeline(&token)
+                    .with_page_size(6)
+                    .into_stream_until(StopAt::Id(all[10].id))
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(newer.len(), 10);
+
+                let since = all[12].created_at;
+                let recent = super::home_timeline(&token)
+                    .with_page_size(6)
+                    .into_stream_until(StopAt::Date(since))
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert!(recent.len() >= 13);
+                assert!(recent.iter().all(|tweet| tweet.created_at >= since));
+
+                let first = super::home_timeline(&token)
+                    .with_page_size(6)
+                    .into_stream_until(StopAt::Count(8))
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(first.len(), 8);
+                assert_eq!(first[7].id, all[7].id);
+            })
+            .await;
+    }
 }
@@ -0,0 +1,294 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `fields` and `expansions` parameters that select what API v2 includes in its responses.
+
+use std::fmt;
+
+use crate::common::*;
+
+field_enum! {
+    /// The fields that can be requested for each `Tweet`, with the `tweet.fields` parameter.
+    ///
+    /// The `id` and `text` fields are always included.
+    pub enum TweetField {
+        /// The media keys and poll IDs attached to the tweet.
+        Attachments => "attachments",
+        /// The ID of the user who posted the tweet.
+        AuthorId => "author_id",
+        /// The topics Twitter has annotated the tweet with.
+        ContextAnnotations => "context_annotations",
+        /// The ID of the tweet that started the conversation this tweet belongs to.
+        ConversationId => "conversation_id",
+        /// When the tweet was posted.
+        CreatedAt => "created_at",
+        /// The hashtags, links, and mentions parsed from the tweet's text.
+        Entities => "entities",
+        /// The place the tweet was tagged with.
+        Geo => "geo",
+        /// The ID of the tweet.
+        Id => "id",
+        /// The ID of the user this tweet is a reply to.
+        InReplyToUserId => "in_reply_to_user_id",
+        /// The language Twitter has detected the tweet to be written in.
+        Lang => "lang",
+        /// Engagement counts that are only available to the tweet's author.
+        NonPublicMetrics => "non_public_metrics",
+        /// Engagement counts from organic (not promoted) contexts, only available to the tweet's
+        /// author.
+        OrganicMetrics => "organic_metrics",
+        /// Whether the tweet's links may lead to sensitive content.
+        PossiblySensitive => "possibly_sensitive",
+        /// Engagement counts from promoted contexts, only available to the tweet's author.
+        PromotedMetrics => "promoted_metrics",
+        /// Public engagement counts: retweets, replies, likes, and quotes.
+        PublicMetrics => "public_metrics",
+        /// The tweets this tweet retweets, quotes, or replies to.
+        ReferencedTweets => "referenced_tweets",
+        /// Who is allowed to reply to the tweet.
+        ReplySettings => "reply_settings",
+        /// The name of the app the tweet was posted with.
+        Source => "source",
+        /// The text of the tweet.
+        Text => "text",
+        /// Where the tweet has been withheld, if anywhere.
+        Withheld => "withheld",
+    }
+}
+
+field_enum! {
+    /// The fields that can be requested for each `User`, with the `user.fields` parameter.
+    ///
+    /// The `id`, `name`, and `username` fields are always included.
+    pub enum UserField {
+        /// When the account was created.
+        CreatedAt => "created_at",
+        /// The text of the user's profile description.
+        Description => "description",
+        /// The links, hashtags, and mentions parsed from the user's description and URL.
+        Entities => "entities",
+        /// The ID of the user.
+        Id => "id",
+        /// The location given in the user's profile.
+        Location => "location",
+        /// The user's display name.
+        Name => "name",
+        /// The ID of the tweet pinned to the user's profile.
+        PinnedTweetId => "pinned_tweet_id",
+        /// The URL of the user's profile image.
+        ProfileImageUrl => "profile_image_url",
+        /// Whether the user's tweets are protected.
+        Protected => "protected",
+        /// Follower, following, tweet, and list counts.
+        PublicMetrics => "public_metrics",
+        /// The URL given in the user's profile.
+        Url => "url",
+        /// The user's screen name.
+        Username => "username",
+        /// Whether the user is verified.
+        Verified => "verified",
+        /// Where the user has been withheld, if anywhere.
+        Withheld => "withheld",
+    }
+}
+
+field_enum! {
+    /// The fields that can be requested for each `Media`, with the `media.fields` parameter.
+    ///
+    /// The `media_key` and `type` fields are always included.
+    pub enum MediaField {
+        /// The alternative text given for the media.
+        AltText => "alt_text",
+        /// The length of a video, in milliseconds.
+        DurationMs => "duration_ms",
+        /// The height of the media, in pixels.
+        Height => "height",
+        /// The key that identifies the media.
+        MediaKey => "media_key",
+        /// Engagement counts that are only available to the media's owner.
+        NonPublicMetrics => "non_public_metrics",
+        /// Engagement counts from organic contexts, only available to the media's owner.
+        OrganicMetrics => "organic_metrics",
+        /// The URL of a still image for videos and GIFs.
+        PreviewImageUrl => "preview_image_url",
+        /// Engagement counts from promoted contexts, only available to the media's owner.
+        PromotedMetrics => "promoted_metrics",
+        /// Public engagement counts, like the number of views of a video.
+        PublicMetrics => "public_metrics",
+        /// The kind of media: `photo`, `animated_gif`, or `video`.
+        Type => "type",
+        /// The URL of a photo.
+        Url => "url",
+        /// The width of the media, in pixels.
+        Width => "width",
+    }
+}
+
+field_enum! {
+    /// The fields that can be requested for each `Poll`, with the `poll.fields` parameter.
+    ///
+    /// The `id` and `options` fields are always included.
+    pub enum PollField {
+        /// How long the poll runs for, in minutes.
+        DurationMinutes => "duration_minutes",
+        /// When the poll closes.
+        EndDatetime => "end_datetime",
+        /// The ID of the poll.
+        Id => "id",
+        /// The choices in the poll, with their vote counts.
+        Options => "options",
+        /// Whether the poll is still open.
+        VotingStatus => "voting_status",
+    }
+}
+
+field_enum! {
+    /// The fields that can be requested for each `Place`, with the `place.fields` parameter.
+    ///
+    /// The `id` and `full_name` fields are always included.
+    pub enum PlaceField {
+        /// The IDs of the places this place is within.
+        ContainedWithin => "contained_within",
+        /// The name of the country the place is in.
+        Country => "country",
+        /// The ISO code of the country the place is in.
+        CountryCode => "country_code",
+        /// The full name of the place, like "Manhattan, NY".
+        FullName => "full_name",
+        /// The bounding box of the place, as GeoJSON.
+        Geo => "geo",
+        /// The ID of the place.
+        Id => "id",
+        /// The short name of the place, like "Manhattan".
+        Name => "name",
+        /// The kind of place, like `city` or `poi`.
+        PlaceType => "place_type",
+    }
+}
+
+field_enum! {
+    /// The objects that can be loaded alongside tweets, with the `expansions` parameter.
+    ///
+    /// Each expansion loads the objects referenced by a field of the returned tweets into the
+    /// `includes` of the response. See [`Includes`] for how to match them back up with their
+    /// tweets.
+    ///
+    /// [`Includes`]: struct.Includes.html
+    pub enum Expansion {
+        /// Loads the `Media` attached to each tweet.
+        AttachmentsMediaKeys => "attachments.media_keys",
+        /// Loads the `Poll` attached to each tweet.
+        AttachmentsPollIds => "attachments.poll_ids",
+        /// Loads the `User` who posted each tweet.
+        AuthorId => "author_id",
+        /// Loads the `User`s mentioned in each tweet.
+        EntitiesMentionsUsername => "entities.mentions.username",
+        /// Loads the `Place` each tweet was tagged with.
+        GeoPlaceId => "geo.place_id",
+        /// Loads the `User` each tweet replies to.
+        InReplyToUserId => "in_reply_to_user_id",
+        /// Loads the `Tweet`s each tweet retweets, quotes, or replies to.
+        ReferencedTweetsId => "referenced_tweets.id",
+        /// Loads the `User`s who posted the tweets each tweet retweets, quotes, or replies to.
+        ReferencedTweetsIdAuthorId => "referenced_tweets.id.author_id",
+    }
+}
+
+/// The set of fields and expansions to request from an API v2 endpoint.
+///
+/// By default, API v2 only returns a bare minimum of information about each object - a tweet only
+/// comes with its ID and text, for example. Everything else needs to be asked for by name, and
+/// related objects (like the author of a tweet) need to be "expanded" to be loaded at all. A
+/// `Fields` collects these requests so they can be handed to each call:
+///
+/// ```rust
+/// use egg_mode::v2::{Expansion, Fields, TweetField, UserField};
+///
+/// let fields = Fields::new()
+///     .tweet_fields([TweetField::CreatedAt, TweetField::PublicMetrics])
+///     .expansions([Expansion::AuthorId])
+///     .user_fields([UserField::Username, UserField::Verified]);
+/// ```
+///
+/// Fields for users, media, polls, and places only affect the objects loaded by an expansion, so
+/// make sure to request the matching `Expansion` as well.
+#[derive(Debug, Clone, Default)]
+pub struct Fields {
+    tweet: Vec<TweetField>,
+    user: Vec<UserField>,
+    media: Vec<MediaField>,
+    poll: Vec<PollField>,
+    place: Vec<PlaceField>,
+    expansions: Vec<Expansion>,
+}
+
+impl Fields {
+    /// Creates a new, empty `Fields`, which only requests the default fields.
+    pub fn new() -> Fields {
+        Fields::default()
+    }
+
+    /// Requests the given fields for each tweet.
+    pub fn tweet_fields(mut self, fields: impl IntoIterator<Item = TweetField>) -> Self {
+        self.tweet.extend(fields);
+        self
+    }
+
+    /// Requests the given fields for each user.
+    pub fn user_fields(mut self, fields: impl IntoIterator<Item = UserField>) -> Self {
+        self.user.extend(fields);
+        self
+    }
+
+    /// Requests the given fields for each piece of media.
+    pub fn media_fields(mut self, fields: impl IntoIterator<Item = MediaField>) -> Self {
+        self.media.extend(fields);
+        self
+    }
+
+    /// Requests the given fields for each poll.
+    pub fn poll_fields(mut self, fields: impl IntoIterator<Item = PollField>) -> Self {
+        self.poll.extend(fields);
+        self
+    }
+
+    /// Requests the given fields for each place.
+    pub fn place_fields(mut self, fields: impl IntoIterator<Item = PlaceField>) -> Self {
+        self.place.extend(fields);
+        self
+    }
+
+    /// Loads the objects referenced by the given fields into the response's `includes`.
+    pub fn expansions(mut self, expansions: impl IntoIterator<Item = Expansion>) -> Self {
+        self.expansions.extend(expansions);
+        self
+    }
+
+    /// Adds the requested fields and expansions to the given `ParamList`.
+    pub(crate) fn add_params(&self, params: ParamList) -> ParamList {
+        params
+            .add_opt_param("tweet.fields", join(&self.tweet))
+            .add_opt_param("user.fields", join(&self.user))
+            .add_opt_param("media.fields", join(&self.media))
+            .add_opt_param("poll.fields", join(&self.poll))
+            .add_opt_param("place.fields", join(&self.place))
+            .add_opt_param("expansions", join(&self.expansions))
+    }
+}
+
+/// Joins the given values with commas, or returns `None` if there aren't any.
+fn join<T: fmt::Display>(values: &[T]) -> Option<String> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut joined = String::new();
+    for value in values {
+        if !joined.is_empty() {
+            joined.push(',');
+        }
+        joined.push_str(&value.to_string());
+    }
+    Some(joined)
+}
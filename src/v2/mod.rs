@@ -0,0 +1,405 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Structs and functions for working with version 2 of the Twitter API.
+//!
+//! The rest of egg-mode is written against version 1.1 of the Twitter API. Version 2 returns tweets
+//! and users in a different shape, so this module has its own types for them, which can't be
+//! used interchangeably with the ones in [`tweet`] and [`user`]. The same `Token`s work with both
+//! versions, though, and calls to API v2 return the same `Response` wrapper with rate-limit
+//! information.
+//!
+//! [`tweet`]: ../tweet/index.html
+//! [`user`]: ../user/index.html
+//!
+//! ## Fields and expansions
+//!
+//! Where API v1.1 returns everything it knows about a tweet, API v2 only returns the fields you
+//! ask for. Each call in this module takes a [`Fields`], which lists the fields you want and the
+//! related objects you want "expanded" alongside the tweets you asked for - the author of each
+//! tweet, the media attached to it, the tweets it quotes, and so on. Every field that isn't
+//! requested is left as `None` (or empty) in the returned types.
+//!
+//! [`Fields`]: struct.Fields.html
+//!
+//! Expanded objects aren't placed inside the tweets that reference them. Instead, they're returned
+//! once each in the [`Includes`] of the response, and the tweets only hold their IDs. `Includes`
+//! has methods to look up the objects referenced by a given tweet:
+//!
+//! [`Includes`]: struct.Includes.html
+//!
+//! ```rust,no_run
+//! # use egg_mode::Token;
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let token: Token = unimplemented!();
+//! use egg_mode::v2::{self, Expansion, Fields, TweetField};
+//!
+//! let fields = Fields::new()
+//!     .tweet_fields([TweetField::CreatedAt])
+//!     .expansions([Expansion::AuthorId]);
+//! let resp = v2::tweet::lookup([1261326399320715264], &fields, &token).await.unwrap();
+//!
+//! for tweet in &resp.data {
+//!     let author = resp.includes.author_of(tweet).unwrap();
+//!     println!("<@{}> {}", author.username, tweet.text);
+//! }
+//! # }
+//! ```
+//!
+//! ## Modules
+//!
+//! - `tweet`: Looking up tweets, loading user and mention timelines, and searching the full
+//!   archive of tweets.
+//! - `stream`: Managing the rules for the filtered stream, and connecting to it.
+
+use chrono;
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+
+mod fields;
+pub mod stream;
+pub mod tweet;
+
+pub use self::fields::*;
+
+use self::stream::RuleSummary;
+use self::tweet::{ReferenceKind, Tweet};
+
+/// The body of a response from API v2.
+///
+/// Every call to API v2 wraps the objects it returns (the `data`) with a few other pieces of
+/// information: the objects loaded with `expansions`, metadata about the collection being loaded,
+/// and any errors encountered while loading individual objects.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Payload<T> {
+    /// The objects that were loaded.
+    ///
+    /// If none of the requested objects could be loaded, this will be empty, and `errors` will
+    /// describe what went wrong.
+    #[serde(default)]
+    pub data: T,
+    /// The objects loaded by the requested expansions.
+    #[serde(default)]
+    pub includes: Includes,
+    /// Information about the collection being loaded, like the tokens to load the next or
+    /// previous page.
+    pub meta: Option<Meta>,
+    /// The errors encountered when loading individual objects, like tweets that have been deleted
+    /// or can't be seen by the authenticated user.
+    ///
+    /// Unlike API v1.1, API v2 returns the objects it could load even if some of the requested
+    /// ones couldn't be loaded. If the request failed as a whole, an `Error` is returned instead.
+    #[serde(default)]
+    pub errors: Vec<PartialError>,
+}
+
+/// Information about a collection returned by API v2.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Meta {
+    /// The number of objects returned.
+    #[serde(default)]
+    pub result_count: u32,
+    /// The ID of the newest tweet returned.
+    #[serde(default, with = "opt_id")]
+    pub newest_id: Option<u64>,
+    /// The ID of the oldest tweet returned.
+    #[serde(default, with = "opt_id")]
+    pub oldest_id: Option<u64>,
+    /// The token to load the next (older) page of results, if there is one.
+    pub next_token: Option<String>,
+    /// The token to load the previous (newer) page of results, if there is one.
+    pub previous_token: Option<String>,
+    /// When the request was received, for calls that manage filtered stream rules.
+    pub sent: Option<chrono::DateTime<chrono::Utc>>,
+    /// How many rules were affected, for calls that add or delete filtered stream rules.
+    pub summary: Option<RuleSummary>,
+}
+
+/// An error encountered when loading an individual object from API v2.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PartialError {
+    /// A short summary of the error, like "Not Found Error".
+    pub title: String,
+    /// A longer description of what went wrong.
+    pub detail: Option<String>,
+    /// A URL identifying the kind of error.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// The kind of object that couldn't be loaded, like `tweet` or `user`.
+    pub resource_type: Option<String>,
+    /// The ID of the object that couldn't be loaded.
+    pub resource_id: Option<String>,
+    /// The name of the parameter the object was requested with.
+    pub parameter: Option<String>,
+    /// The value of the parameter the object was requested with.
+    pub value: Option<String>,
+}
+
+/// The objects loaded by the `expansions` of an API v2 call.
+///
+/// Each kind of object is returned in its own list, in no particular order. To match them up with
+/// the tweets that reference them, use the lookup methods on this type.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Includes {
+    /// The tweets referenced by the returned tweets.
+    #[serde(default)]
+    pub tweets: Vec<Tweet>,
+    /// The users referenced by the returned tweets.
+    #[serde(default)]
+    pub users: Vec<User>,
+    /// The media attached to the returned tweets.
+    #[serde(default)]
+    pub media: Vec<Media>,
+    /// The polls attached to the returned tweets.
+    #[serde(default)]
+    pub polls: Vec<Poll>,
+    /// The places the returned tweets were tagged with.
+    #[serde(default)]
+    pub places: Vec<Place>,
+}
+
+impl Includes {
+    /// Returns the included tweet with the given ID.
+    pub fn tweet(&self, id: u64) -> Option<&Tweet> {
+        self.tweets.iter().find(|t| t.id == id)
+    }
+
+    /// Returns the included user with the given ID.
+    pub fn user(&self, id: u64) -> Option<&User> {
+        self.users.iter().find(|u| u.id == id)
+    }
+
+    /// Returns the included media with the given media key.
+    pub fn media(&self, media_key: &str) -> Option<&Media> {
+        self.media.iter().find(|m| m.media_key == media_key)
+    }
+
+    /// Returns the included poll with the given ID.
+    pub fn poll(&self, id: &str) -> Option<&Poll> {
+        self.polls.iter().find(|p| p.id == id)
+    }
+
+    /// Returns the included place with the given ID.
+    pub fn place(&self, id: &str) -> Option<&Place> {
+        self.places.iter().find(|p| p.id == id)
+    }
+
+    /// Returns the user who posted the given tweet.
+    ///
+    /// This requires the `AuthorId` expansion (or the `ReferencedTweetsIdAuthorId` expansion, for
+    /// tweets in `self.tweets`).
+    pub fn author_of(&self, tweet: &Tweet) -> Option<&User> {
+        self.user(tweet.author_id?)
+    }
+
+    /// Returns the user the given tweet is a reply to.
+    ///
+    /// This requires the `InReplyToUserId` expansion.
+    pub fn in_reply_to_user_of(&self, tweet: &Tweet) -> Option<&User> {
+        self.user(tweet.in_reply_to_user_id?)
+    }
+
+    /// Returns the tweets that the given tweet retweets, quotes, or replies to, along with how
+    /// each one is referenced.
+    ///
+    /// This requires the `ReferencedTweetsId` expansion.
+    pub fn referenced_tweets_of<'a>(
+        &'a self,
+        tweet: &'a Tweet,
+    ) -> impl Iterator<Item = (ReferenceKind, &'a Tweet)> + 'a {
+        tweet
+            .referenced_tweets
+            .iter()
+            .filter_map(move |r| Some((r.kind, self.tweet(r.id)?)))
+    }
+
+    /// Returns the media attached to the given tweet.
+    ///
+    /// This requires the `AttachmentsMediaKeys` expansion.
+    pub fn media_of<'a>(&'a self, tweet: &'a Tweet) -> impl Iterator<Item = &'a Media> + 'a {
+        tweet
+            .attachments
+            .iter()
+            .flat_map(|a| a.media_keys.iter())
+            .filter_map(move |key| self.media(key))
+    }
+
+    /// Returns the poll attached to the given tweet.
+    ///
+    /// This requires the `AttachmentsPollIds` expansion.
+    pub fn poll_of(&self, tweet: &Tweet) -> Option<&Poll> {
+        let attachments = tweet.attachments.as_ref()?;
+        attachments.poll_ids.iter().find_map(|id| self.poll(id))
+    }
+
+    /// Returns the place the given tweet was tagged with.
+    ///
+    /// This requires the `GeoPlaceId` expansion.
+    pub fn place_of(&self, tweet: &Tweet) -> Option<&Place> {
+        self.place(tweet.geo.as_ref()?.place_id.as_ref()?)
+    }
+}
+
+/// A Twitter user, as returned by API v2.
+///
+/// Only `id`, `name`, and `username` are returned by default. The other fields are only present if
+/// they were requested with [`UserField`]s.
+///
+/// [`UserField`]: enum.UserField.html
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct User {
+    /// The ID of the user.
+    #[serde(with = "serde_via_string")]
+    pub id: u64,
+    /// The user's display name.
+    pub name: String,
+    /// The user's screen name, without the leading `@`.
+    pub username: String,
+    /// When the account was created.
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// The text of the user's profile description.
+    pub description: Option<String>,
+    /// The links, hashtags, and mentions parsed from the user's description and URL.
+    pub entities: Option<serde_json::Value>,
+    /// The location given in the user's profile. This is free-form text, and may not be a real
+    /// place.
+    pub location: Option<String>,
+    /// The ID of the tweet pinned to the user's profile.
+    #[serde(default, with = "opt_id")]
+    pub pinned_tweet_id: Option<u64>,
+    /// The URL of the user's profile image.
+    pub profile_image_url: Option<String>,
+    /// Whether the user's tweets are protected.
+    pub protected: Option<bool>,
+    /// Follower, following, tweet, and list counts.
+    pub public_metrics: Option<UserMetrics>,
+    /// The URL given in the user's profile.
+    pub url: Option<String>,
+    /// Whether the user is verified.
+    pub verified: Option<bool>,
+    /// Where the user has been withheld, if anywhere.
+    pub withheld: Option<serde_json::Value>,
+}
+
+/// Public counts about a `User`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct UserMetrics {
+    /// The number of users following this user.
+    pub followers_count: u64,
+    /// The number of users this user follows.
+    pub following_count: u64,
+    /// The number of tweets this user has posted, including retweets.
+    pub tweet_count: u64,
+    /// The number of lists that include this user.
+    pub listed_count: u64,
+}
+
+/// A photo, GIF, or video attached to a tweet, as returned by API v2.
+///
+/// Only `media_key` and `kind` are returned by default. The other fields are only present if they
+/// were requested with [`MediaField`]s.
+///
+/// [`MediaField`]: enum.MediaField.html
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Media {
+    /// The key that identifies this media.
+    pub media_key: String,
+    /// The kind of media: `photo`, `animated_gif`, or `video`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The URL of a photo.
+    pub url: Option<String>,
+    /// The length of a video, in milliseconds.
+    pub duration_ms: Option<u64>,
+    /// The height of the media, in pixels.
+    pub height: Option<u32>,
+    /// The width of the media, in pixels.
+    pub width: Option<u32>,
+    /// The URL of a still image for videos and GIFs.
+    pub preview_image_url: Option<String>,
+    /// The alternative text given for the media.
+    pub alt_text: Option<String>,
+    /// Public engagement counts, like the number of views of a video.
+    pub public_metrics: Option<serde_json::Value>,
+}
+
+/// A poll attached to a tweet, as returned by API v2.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Poll {
+    /// The ID of the poll.
+    pub id: String,
+    /// The choices in the poll.
+    pub options: Vec<PollOption>,
+    /// How long the poll runs for, in minutes.
+    pub duration_minutes: Option<u32>,
+    /// When the poll closes.
+    pub end_datetime: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether the poll is still `open` or has `closed`.
+    pub voting_status: Option<String>,
+}
+
+/// One of the choices in a `Poll`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PollOption {
+    /// The position of this choice in the poll, starting at 1.
+    pub position: u32,
+    /// The text of this choice.
+    pub label: String,
+    /// The number of votes for this choice.
+    pub votes: u64,
+}
+
+/// A place a tweet was tagged with, as returned by API v2.
+///
+/// Only `id` and `full_name` are returned by default. The other fields are only present if they
+/// were requested with [`PlaceField`]s.
+///
+/// [`PlaceField`]: enum.PlaceField.html
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Place {
+    /// The ID of the place.
+    pub id: String,
+    /// The full name of the place, like "Manhattan, NY".
+    pub full_name: String,
+    /// The short name of the place, like "Manhattan".
+    pub name: Option<String>,
+    /// The kind of place, like `city` or `poi`.
+    pub place_type: Option<String>,
+    /// The name of the country the place is in.
+    pub country: Option<String>,
+    /// The ISO code of the country the place is in.
+    pub country_code: Option<String>,
+    /// The IDs of the places this place is within.
+    #[serde(default)]
+    pub contained_within: Vec<String>,
+    /// The bounding box of the place, as GeoJSON.
+    pub geo: Option<serde_json::Value>,
+}
+
+/// Loads and saves an optional numeric ID as a string, the way API v2 sends them.
+mod opt_id {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn deserialize<'de, D>(de: D) -> Result<Option<u64>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(de)?
+            .map(|id| id.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+
+    pub fn serialize<S>(id: &Option<u64>, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match id {
+            Some(id) => ser.collect_str(id),
+            None => ser.serialize_none(),
+        }
+    }
+}
@@ -0,0 +1,411 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Managing the rules for the API v2 filtered stream, and connecting to it.
+//!
+//! Unlike the v1.1 [`stream::filter`], which takes the keywords and users to follow as part of the
+//! request that opens the stream, the API v2 filtered stream is set up ahead of time with a set of
+//! rules that are saved with your app. Each rule is a search query, optionally with a tag to
+//! identify it. Once connected, the stream delivers every tweet that matches any of the rules,
+//! along with the rules that it matched.
+//!
+//! [`stream::filter`]: ../../stream/fn.filter.html
+//!
+//! ```rust,no_run
+//! # use egg_mode::Token;
+//! # #[tokio::main]
+//! # async fn main() {
+//! # let token: Token = unimplemented!();
+//! use egg_mode::v2::stream::{self, NewRule, StreamMessage};
+//! use egg_mode::v2::Fields;
+//! use futures::TryStreamExt;
+//!
+//! stream::add_rules(
+//!     vec![
+//!         NewRule::new("rustlang -is:retweet").tag("rust"),
+//!         NewRule::new("from:TwitterDev").tag("devs"),
+//!     ],
+//!     &token,
+//! )
+//! .await
+//! .unwrap();
+//!
+//! stream::filtered_stream(&Fields::new(), &token)
+//!     .try_for_each(|m| {
+//!         if let StreamMessage::Tweet(tweet) = m {
+//!             for rule in &tweet.matching_rules {
+//!                 println!("[{}] {}", rule.tag.as_deref().unwrap_or("?"), tweet.data.text);
+//!             }
+//!         }
+//!         futures::future::ok(())
+//!     })
+//!     .await
+//!     .expect("Stream error");
+//! # }
+//! ```
+//!
+//! The filtered stream, and its rules, can only be used with app-only authentication, i.e. with a
+//! `Token::Bearer`.
+
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+use hyper::{Body, Method, Request};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::raw::RequestBuilder;
+use crate::common::*;
+use crate::error::{self, Result};
+use crate::stream::{RawTwitterStream, TwitterStream};
+use crate::{auth, links};
+
+use super::tweet::Tweet;
+use super::{Fields, Includes, PartialError, Payload};
+
+/// A rule saved for the filtered stream.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rule {
+    /// The ID Twitter assigned to the rule.
+    #[serde(with = "serde_via_string")]
+    pub id: u64,
+    /// The query tweets are matched against.
+    pub value: String,
+    /// The tag given to the rule, if any.
+    pub tag: Option<String>,
+}
+
+/// A rule to add to the filtered stream.
+///
+/// The rule's `value` uses the same operators as a search query; see [Twitter's documentation on
+/// building rules][rules] for the full list. The `tag` is returned alongside each tweet the rule
+/// matches, so it can be used to tell rules apart without keeping track of their IDs.
+///
+/// [rules]: https://developer.twitter.com/en/docs/twitter-api/tweets/filtered-stream/integrate/build-a-rule
+#[derive(Debug, Clone, Serialize)]
+pub struct NewRule {
+    /// The query tweets will be matched against.
+    pub value: String,
+    /// The tag to give the rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+impl NewRule {
+    /// Creates a new rule with the given query, and no tag.
+    pub fn new(value: impl Into<String>) -> NewRule {
+        NewRule {
+            value: value.into(),
+            tag: None,
+        }
+    }
+
+    /// Sets the tag for this rule.
+    pub fn tag(self, tag: impl Into<String>) -> NewRule {
+        NewRule {
+            tag: Some(tag.into()),
+            ..self
+        }
+    }
+}
+
+/// A count of the rules affected by a call to `add_rules`, `validate_rules`, or `delete_rules`.
+///
+/// Only the counts that apply to the call are given; the others are left as zero.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct RuleSummary {
+    /// The number of rules that were added.
+    #[serde(default)]
+    pub created: u32,
+    /// The number of rules that couldn't be added.
+    #[serde(default)]
+    pub not_created: u32,
+    /// The number of rules that were valid.
+    #[serde(default)]
+    pub valid: u32,
+    /// The number of rules that were invalid.
+    #[serde(default)]
+    pub invalid: u32,
+    /// The number of rules that were deleted.
+    #[serde(default)]
+    pub deleted: u32,
+    /// The number of rules that couldn't be deleted.
+    #[serde(default)]
+    pub not_deleted: u32,
+}
+
+/// Load the rules currently saved for the filtered stream.
+pub async fn rules(token: &auth::Token) -> Result<Response<Payload<Vec<Rule>>>> {
+    let req = get(links::v2::SEARCH_STREAM_RULES, token, None);
+    request_with_json_response(req).await
+}
+
+/// Add the given rules to the filtered stream.
+///
+/// The returned `data` contains the rules that were added, with the IDs Twitter assigned to them.
+/// Rules that couldn't be added - because they were invalid, or duplicated an existing rule - are
+/// described in `errors` instead.
+pub async fn add_rules<I: IntoIterator<Item = NewRule>>(
+    rules: I,
+    token: &auth::Token,
+) -> Result<Response<Payload<Vec<Rule>>>> {
+    let req = rules_request(add_body(rules), false, token);
+    request_with_json_response(req).await
+}
+
+/// Check whether the given rules could be added to the filtered stream, without adding them.
+///
+/// This returns the same response as `add_rules` would, which can be used to check the syntax of
+/// a rule before saving it.
+pub async fn validate_rules<I: IntoIterator<Item = NewRule>>(
+    rules: I,
+    token: &auth::Token,
+) -> Result<Response<Payload<Vec<Rule>>>> {
+    let req = rules_request(add_body(rules), true, token);
+    request_with_json_response(req).await
+}
+
+/// Delete the rules with the given IDs from the filtered stream.
+///
+/// The number of rules that were deleted is given in the `summary` of the returned `meta`.
+pub async fn delete_rules<I: IntoIterator<Item = u64>>(
+    ids: I,
+    token: &auth::Token,
+) -> Result<Response<Payload<Vec<Rule>>>> {
+    let ids = ids.into_iter().map(|id| id.to_string()).collect::<Vec<_>>();
+    let body = serde_json::json!({ "delete": { "ids": ids } });
+    let req = rules_request(body, false, token);
+    request_with_json_response(req).await
+}
+
+fn add_body<I: IntoIterator<Item = NewRule>>(rules: I) -> serde_json::Value {
+    serde_json::json!({ "add": rules.into_iter().collect::<Vec<_>>() })
+}
+
+fn rules_request(body: serde_json::Value, dry_run: bool, token: &auth::Token) -> Request<Body> {
+    let mut request = RequestBuilder::new(Method::POST, links::v2::SEARCH_STREAM_RULES);
+    if dry_run {
+        request = request.with_query_params(&ParamList::new().add_param("dry_run", "true"));
+    }
+    request.with_body_json(body).request_token(token)
+}
+
+/// Connect to the filtered stream, requesting the given fields and expansions for each tweet.
+///
+/// Only tweets matching the rules saved with `add_rules` are delivered. If no rules have been
+/// saved, the stream stays connected but delivers nothing but keep-alive messages.
+pub fn filtered_stream(fields: &Fields, token: &auth::Token) -> FilteredStream {
+    let params = fields.add_params(ParamList::new());
+    let req = get(links::v2::SEARCH_STREAM, token, Some(&params));
+    FilteredStream {
+        stream: TwitterStream::new(req).raw(),
+    }
+}
+
+/// A tweet delivered by the filtered stream, along with the rules it matched.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatchedTweet {
+    /// The tweet that matched.
+    pub data: Tweet,
+    /// The objects loaded by the requested expansions.
+    #[serde(default)]
+    pub includes: Includes,
+    /// The rules the tweet matched.
+    #[serde(default)]
+    pub matching_rules: Vec<MatchingRule>,
+}
+
+impl MatchedTweet {
+    /// Returns whether the tweet matched a rule with the given tag.
+    pub fn matched_tag(&self, tag: &str) -> bool {
+        self.matching_rules
+            .iter()
+            .any(|r| r.tag.as_deref() == Some(tag))
+    }
+}
+
+/// A rule that a tweet from the filtered stream matched.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatchingRule {
+    /// The ID of the rule.
+    #[serde(with = "serde_via_string")]
+    pub id: u64,
+    /// The tag given to the rule, if any.
+    pub tag: Option<String>,
+}
+
+/// Represents the kinds of messages that can be sent over the filtered stream.
+#[derive(Debug, Clone)]
+pub enum StreamMessage {
+    /// A blank line, sent periodically to keep the connection alive.
+    Ping,
+    /// A tweet that matched one or more of the stream's rules.
+    Tweet(Box<MatchedTweet>),
+    /// Errors reported by Twitter over the stream, for example just before Twitter closes the
+    /// connection.
+    Errors(Vec<PartialError>),
+}
+
+impl FromStr for StreamMessage {
+    type Err = error::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct RawMessage {
+            data: Option<Tweet>,
+            #[serde(default)]
+            includes: Includes,
+            #[serde(default)]
+            matching_rules: Vec<MatchingRule>,
+            #[serde(default)]
+            errors: Vec<PartialError>,
+        }
+
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(StreamMessage::Ping);
+        }
+
+        let raw: RawMessage = serde_json::from_str(input)?;
+        match raw.data {
+            Some(data) => Ok(StreamMessage::Tweet(Box::new(MatchedTweet {
+                data,
+                includes: raw.includes,
+                matching_rules: raw.matching_rules,
+            }))),
+            None if !raw.errors.is_empty() => Ok(StreamMessage::Errors(raw.errors)),
+            None => Err(error::Error::InvalidResponse(
+                "unexpected filtered stream message",
+                Some(input.to_string()),
+            )),
+        }
+    }
+}
+
+/// A `Stream` that represents a connection to the API v2 filtered stream.
+///
+/// This is returned by [`filtered_stream`]. Like the v1.1 `TwitterStream`, the connection isn't
+/// opened until the stream is first polled.
+///
+/// [`filtered_stream`]: fn.filtered_stream.html
+#[must_use = "Streams are lazy and do nothing unless polled"]
+pub struct FilteredStream {
+    stream: RawTwitterStream,
+}
+
+impl Stream for FilteredStream {
+    type Item = Result<StreamMessage>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream)
+            .poll_next(cx)
+            .map(|msg| msg.map(|msg| msg.and_then(|msg| msg.as_str()?.parse())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tests::load_file;
+    use crate::testing::MockTwitter;
+    use crate::v2::Expansion;
+
+    use futures::TryStreamExt;
+    use hyper::StatusCode;
+
+    #[test]
+    fn parse_stream_messages() {
+        let sample = load_file("sample_payloads/v2-stream-tweet.json");
+        match sample.parse::<StreamMessage>().unwrap() {
+            StreamMessage::Tweet(tweet) => {
+                assert_eq!(tweet.data.id, 1338923691497959425);
+                assert_eq!(tweet.matching_rules.len(), 2);
+                assert_eq!(tweet.matching_rules[0].id, 1273026480692322304);
+                assert!(tweet.matched_tag("devs"));
+                assert!(!tweet.matched_tag("rust"));
+                let author = tweet.includes.author_of(&tweet.data).unwrap();
+                assert_eq!(author.username, "TwitterDev");
+            }
+            msg => panic!("Not a tweet: {:?}", msg),
+        }
+
+        let disconnect = r#"{"errors":[{"title":"operational-disconnect","disconnect_type":"UpstreamOperationalDisconnect","detail":"This stream has been disconnected upstream for operational reasons.","type":"https://api.twitter.com/2/problems/operational-disconnect"}]}"#;
+        match disconnect.parse::<StreamMessage>().unwrap() {
+            StreamMessage::Errors(errors) => assert_eq!(errors[0].title, "operational-disconnect"),
+            msg => panic!("Not an error: {:?}", msg),
+        }
+
+        assert!(matches!("\r\n".parse::<StreamMessage>(), Ok(StreamMessage::Ping)));
+        assert!("{}".parse::<StreamMessage>().is_err());
+    }
+
+    #[tokio::test]
+    async fn manage_rules() {
+        let twitter = MockTwitter::new();
+        let token = MockTwitter::token();
+        twitter.respond(
+            Method::POST,
+            "/2/tweets/search/stream/rules",
+            StatusCode::OK,
+            load_file("sample_payloads/v2-stream-rules.json"),
+        );
+
+        let rules = vec![
+            NewRule::new("#TwitterAPI has:links").tag("twitterapi"),
+            NewRule::new("from:TwitterDev"),
+        ];
+        let resp = twitter
+            .run(validate_rules(rules.clone(), &token))
+            .await
+            .unwrap();
+        assert_eq!(resp.data[1].tag.as_deref(), Some("devs"));
+        assert_eq!(resp.meta.as_ref().unwrap().summary.unwrap().valid, 2);
+        twitter.run(add_rules(rules, &token)).await.unwrap();
+        twitter
+            .run(delete_rules([1273026480692322304], &token))
+            .await
+            .unwrap();
+
+        let requests = twitter.requests();
+        let bodies = requests
+            .iter()
+            .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(requests[0].params["dry_run"], "true");
+        assert!(!requests[1].params.contains_key("dry_run"));
+        assert_eq!(
+            bodies[0],
+            serde_json::json!({ "add": [
+                { "value": "#TwitterAPI has:links", "tag": "twitterapi" },
+                { "value": "from:TwitterDev" },
+            ]})
+        );
+        assert_eq!(bodies[0], bodies[1]);
+        assert_eq!(
+            bodies[2],
+            serde_json::json!({ "delete": { "ids": ["1273026480692322304"] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn read_filtered_stream() {
+        let twitter = MockTwitter::new();
+        let tweet = load_file("sample_payloads/v2-stream-tweet.json").replace('\n', "");
+        twitter.respond(
+            Method::GET,
+            "/2/tweets/search/stream",
+            StatusCode::OK,
+            format!("{}\r\n\r\n", tweet),
+        );
+
+        let fields = Fields::new().expansions([Expansion::AuthorId]);
+        let msgs = twitter
+            .run(filtered_stream(&fields, &MockTwitter::token()).try_collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert!(matches!(msgs[..], [StreamMessage::Tweet(_), StreamMessage::Ping]));
+        assert_eq!(twitter.requests()[0].params["expansions"], "author_id");
+    }
+}
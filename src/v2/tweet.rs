@@ -0,0 +1,640 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Looking up tweets, loading timelines, and searching tweets with API v2.
+
+use chrono;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use hyper::{Body, Request};
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+use crate::error::Result;
+use crate::{auth, links};
+
+use super::{opt_id, Fields, Includes, Payload};
+
+/// A single tweet, as returned by API v2.
+///
+/// Only `id` and `text` are returned by default. The other fields are only present if they were
+/// requested with [`TweetField`]s. Objects referenced by a tweet, like its author or attached
+/// media, are returned separately in the `includes` of the response; see [`Includes`] for how to
+/// look them up.
+///
+/// [`TweetField`]: ../enum.TweetField.html
+/// [`Includes`]: ../struct.Includes.html
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Tweet {
+    /// The ID of the tweet.
+    #[serde(with = "serde_via_string")]
+    pub id: u64,
+    /// The text of the tweet.
+    pub text: String,
+    /// The ID of the user who posted the tweet.
+    #[serde(default, with = "opt_id")]
+    pub author_id: Option<u64>,
+    /// The ID of the tweet that started the conversation this tweet belongs to.
+    #[serde(default, with = "opt_id")]
+    pub conversation_id: Option<u64>,
+    /// When the tweet was posted.
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// The ID of the user this tweet is a reply to.
+    #[serde(default, with = "opt_id")]
+    pub in_reply_to_user_id: Option<u64>,
+    /// The tweets this tweet retweets, quotes, or replies to.
+    #[serde(default)]
+    pub referenced_tweets: Vec<ReferencedTweet>,
+    /// The media and polls attached to the tweet.
+    pub attachments: Option<Attachments>,
+    /// The location the tweet was tagged with.
+    pub geo: Option<Geo>,
+    /// The topics Twitter has annotated the tweet with.
+    pub context_annotations: Option<serde_json::Value>,
+    /// The hashtags, links, and mentions parsed from the tweet's text.
+    pub entities: Option<serde_json::Value>,
+    /// The language Twitter has detected the tweet to be written in, as a BCP 47 language tag.
+    pub lang: Option<String>,
+    /// Whether the tweet's links may lead to sensitive content.
+    pub possibly_sensitive: Option<bool>,
+    /// Public engagement counts for the tweet.
+    pub public_metrics: Option<TweetMetrics>,
+    /// Who is allowed to reply to the tweet: `everyone`, `mentionedUsers`, or `following`.
+    pub reply_settings: Option<String>,
+    /// The name of the app the tweet was posted with.
+    pub source: Option<String>,
+    /// Where the tweet has been withheld, if anywhere.
+    pub withheld: Option<serde_json::Value>,
+}
+
+/// A reference from one tweet to another.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ReferencedTweet {
+    /// How the other tweet is referenced.
+    #[serde(rename = "type")]
+    pub kind: ReferenceKind,
+    /// The ID of the other tweet.
+    #[serde(with = "serde_via_string")]
+    pub id: u64,
+}
+
+/// The ways a tweet can reference another tweet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceKind {
+    /// The tweet is a retweet of the other tweet.
+    Retweeted,
+    /// The tweet quotes the other tweet.
+    Quoted,
+    /// The tweet is a reply to the other tweet.
+    RepliedTo,
+}
+
+/// The media and polls attached to a tweet.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Attachments {
+    /// The keys of the attached media.
+    #[serde(default)]
+    pub media_keys: Vec<String>,
+    /// The IDs of the attached polls.
+    #[serde(default)]
+    pub poll_ids: Vec<String>,
+}
+
+/// The location a tweet was tagged with.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Geo {
+    /// The ID of the place the tweet was tagged with.
+    pub place_id: Option<String>,
+    /// The exact coordinates the tweet was tagged with, as GeoJSON.
+    pub coordinates: Option<serde_json::Value>,
+}
+
+/// Public engagement counts for a `Tweet`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct TweetMetrics {
+    /// The number of times the tweet has been retweeted.
+    pub retweet_count: u64,
+    /// The number of replies to the tweet.
+    pub reply_count: u64,
+    /// The number of times the tweet has been liked.
+    pub like_count: u64,
+    /// The number of times the tweet has been quoted.
+    pub quote_count: u64,
+}
+
+/// Look up the tweets with the given IDs.
+///
+/// Up to 100 tweets can be loaded at once. Tweets that couldn't be loaded, because they don't
+/// exist or can't be seen by the authenticated user, are left out of `data`, and an entry for each
+/// is added to `errors` instead.
+pub async fn lookup<I: IntoIterator<Item = u64>>(
+    ids: I,
+    fields: &Fields,
+    token: &auth::Token,
+) -> Result<Response<Payload<Vec<Tweet>>>> {
+    let id_param = ids.into_iter().fold(String::new(), |mut acc, x| {
+        if !acc.is_empty() {
+            acc.push(',');
+        }
+        acc.push_str(&x.to_string());
+        acc
+    });
+    let params = fields.add_params(ParamList::new().add_param("ids", id_param));
+
+    let req = get(links::v2::TWEETS, token, Some(&params));
+    request_with_json_response(req).await
+}
+
+/// Make a `Timeline` struct for navigating the collection of tweets posted by the user with the
+/// given ID.
+///
+/// This method has a default page size of 10 tweets, with a minimum of 5 and a maximum of 100.
+///
+/// Twitter will only return the most recent 3200 tweets by navigating this method.
+pub fn user_timeline(user_id: u64, fields: &Fields, token: &auth::Token) -> Timeline {
+    let link = format!("{}/{}/tweets", links::v2::USERS_STEM, user_id);
+    Timeline::new(link, fields, token)
+}
+
+/// Make a `Timeline` struct for navigating the collection of tweets that mention the user with the
+/// given ID.
+///
+/// This method has a default page size of 10 tweets, with a minimum of 5 and a maximum of 100.
+///
+/// Twitter will only return the most recent 800 tweets by navigating this method.
+pub fn mentions_timeline(user_id: u64, fields: &Fields, token: &auth::Token) -> Timeline {
+    let link = format!("{}/{}/mentions", links::v2::USERS_STEM, user_id);
+    Timeline::new(link, fields, token)
+}
+
+/// Helper struct to navigate collections of tweets from API v2.
+///
+/// Unlike the [`Timeline`] for API v1.1, which steps through tweets by their IDs, API v2 gives
+/// each page of results a token that can be used to load the pages on either side of it. This
+/// `Timeline` keeps track of those tokens for you:
+///
+/// [`Timeline`]: ../../tweet/struct.Timeline.html
+///
+/// ```rust,no_run
+/// # use egg_mode::Token;
+/// # #[tokio::main]
+/// # async fn main() {
+/// # let token: Token = unimplemented!();
+/// use egg_mode::v2::{self, Fields};
+///
+/// let timeline = v2::tweet::user_timeline(12, &Fields::new(), &token).with_page_size(50);
+///
+/// let (mut timeline, mut feed) = timeline.start().await.unwrap();
+/// loop {
+///     for tweet in &feed.data {
+///         println!("{}", tweet.text);
+///     }
+///
+///     if timeline.next_token.is_none() {
+///         break;
+///     }
+///     let (next_timeline, next_feed) = timeline.older().await.unwrap();
+///     timeline = next_timeline;
+///     feed = next_feed;
+/// }
+/// # }
+/// ```
+///
+/// `older` loads the page after the one that was last loaded, and `newer` loads the page before
+/// it. If the timeline hasn't been started yet, they load the newest page instead, like `start`.
+/// Once there's no token for the requested page, they return an empty page without calling
+/// Twitter, carrying the rate-limit information from the last page loaded. Check `next_token` and
+/// `previous_token` to see whether there's another page to load.
+pub struct Timeline {
+    ///The URL to request tweets from.
+    link: String,
+    ///The token to authorize requests with.
+    token: auth::Token,
+    ///The fields and expansions to request for each page.
+    fields: Fields,
+    ///The maximum number of tweets to return in a single call.
+    pub max_results: u32,
+    ///The token for the page after the one last loaded by `start`, `older`, or `newer`.
+    pub next_token: Option<String>,
+    ///The token for the page before the one last loaded by `start`, `older`, or `newer`.
+    pub previous_token: Option<String>,
+    ///The rate-limit information from the last page loaded, if one has been loaded.
+    last_rate_limit: Option<RateLimit>,
+}
+
+impl Timeline {
+    ///Clear the saved page tokens on this timeline.
+    pub fn reset(&mut self) {
+        self.next_token = None;
+        self.previous_token = None;
+        self.last_rate_limit = None;
+    }
+
+    ///Clear the saved page tokens on this timeline, and return the most recent set of tweets.
+    pub async fn start(mut self) -> Result<(Timeline, Response<Payload<Vec<Tweet>>>)> {
+        self.reset();
+        self.load(None).await
+    }
+
+    ///Return the page of tweets older than the last page loaded, or an empty page if there are no
+    ///older tweets.
+    pub async fn older(self) -> Result<(Timeline, Response<Payload<Vec<Tweet>>>)> {
+        let token = self.next_token.clone();
+        self.load_next(token).await
+    }
+
+    ///Return the page of tweets newer than the last page loaded, or an empty page if there are no
+    ///newer tweets.
+    pub async fn newer(self) -> Result<(Timeline, Response<Payload<Vec<Tweet>>>)> {
+        let token = self.previous_token.clone();
+        self.load_next(token).await
+    }
+
+    ///Return the page of tweets given by the pagination token, or the newest page if no token is
+    ///given.
+    ///
+    ///Note that this doesn't update the saved page tokens, so you'll have to set those yourself
+    ///if you want to follow up with `older` or `newer`.
+    pub async fn call(
+        &self,
+        pagination_token: Option<&str>,
+    ) -> Result<Response<Payload<Vec<Tweet>>>> {
+        request_with_json_response(self.request(pagination_token)).await
+    }
+
+    ///Helper builder function to set the page size.
+    pub fn with_page_size(self, page_size: u32) -> Self {
+        Timeline {
+            max_results: page_size,
+            ..self
+        }
+    }
+
+    ///Loads the page given by the token, the newest page if the timeline hasn't been started, or
+    ///an empty page if a page has been loaded but there's no token.
+    async fn load_next(
+        self,
+        pagination_token: Option<String>,
+    ) -> Result<(Timeline, Response<Payload<Vec<Tweet>>>)> {
+        match (pagination_token, self.last_rate_limit) {
+            (None, Some(rate_limit)) => {
+                let page = Payload {
+                    data: Vec::new(),
+                    includes: Includes::default(),
+                    meta: None,
+                    errors: Vec::new(),
+                };
+                Ok((self, Response::new(rate_limit, page)))
+            }
+            (pagination_token, _) => self.load(pagination_token).await,
+        }
+    }
+
+    ///Loads the given page, and saves its page tokens.
+    async fn load(
+        mut self,
+        pagination_token: Option<String>,
+    ) -> Result<(Timeline, Response<Payload<Vec<Tweet>>>)> {
+        let resp = self.call(pagination_token.as_deref()).await?;
+        let meta = resp.meta.as_ref();
+        self.next_token = meta.and_then(|m| m.next_token.clone());
+        self.previous_token = meta.and_then(|m| m.previous_token.clone());
+        self.last_rate_limit = Some(resp.rate_limit_status);
+        Ok((self, resp))
+    }
+
+    ///Helper function to construct a `Request` from the current state.
+    fn request(&self, pagination_token: Option<&str>) -> Request<Body> {
+        let params = ParamList::new()
+            .add_param("max_results", self.max_results.to_string())
+            .add_opt_param("pagination_token", pagination_token.map(String::from));
+        let params = self.fields.add_params(params);
+
+        get(&self.link, &self.token, Some(&params))
+    }
+
+    ///Create an instance of `Timeline` with the given link and tokens.
+    fn new(link: String, fields: &Fields, token: &auth::Token) -> Self {
+        Timeline {
+            link,
+            token: token.clone(),
+            fields: fields.clone(),
+            max_results: 10,
+            next_token: None,
+            previous_token: None,
+            last_rate_limit: None,
+        }
+    }
+}
+
+/// Make a `SearchAll` struct for searching the full archive of tweets with the given query.
+///
+/// This uses the full-archive search endpoint, which is only available to projects with Academic
+/// Research access, and only accepts Bearer tokens. The query uses the same operators as the
+/// filtered stream's rules, which are described in [Twitter's documentation][query-doc].
+///
+/// [query-doc]: https://developer.twitter.com/en/docs/twitter-api/tweets/search/integrate/build-a-query
+///
+/// This method has a default page size of 10 tweets, with a minimum of 10 and a maximum of 500.
+pub fn search_all(query: impl Into<String>, fields: &Fields, token: &auth::Token) -> SearchAll {
+    SearchAll {
+        query: query.into(),
+        token: token.clone(),
+        fields: fields.clone(),
+        start_time: None,
+        end_time: None,
+        since_id: None,
+        until_id: None,
+        max_results: 10,
+        next_token: None,
+    }
+}
+
+/// Helper struct to search the full archive of tweets with API v2.
+///
+/// Set the search's bounds with the builder methods, then page through the results with `call`
+/// and `next_token`, or let `into_stream` do that for you:
+///
+/// ```rust,no_run
+/// # use egg_mode::Token;
+/// # #[tokio::main]
+/// # async fn main() {
+/// # let token: Token = unimplemented!();
+/// use chrono::TimeZone;
+/// use egg_mode::v2::{self, Fields};
+/// use futures::TryStreamExt;
+///
+/// let tweets = v2::tweet::search_all("from:rustlang", &Fields::new(), &token)
+///     .start_time(chrono::Utc.with_ymd_and_hms(2015, 5, 1, 0, 0, 0).unwrap())
+///     .end_time(chrono::Utc.with_ymd_and_hms(2015, 6, 1, 0, 0, 0).unwrap())
+///     .with_page_size(500)
+///     .into_stream()
+///     .try_collect::<Vec<_>>()
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+///
+/// Since the objects requested with `expansions` are returned alongside each page, they aren't
+/// available from `into_stream`. To get them, use `into_page_stream`, which yields each page
+/// whole.
+#[derive(Debug, Clone)]
+#[must_use = "SearchAll is lazy and won't do anything unless `call`ed"]
+pub struct SearchAll {
+    ///The query to search for.
+    query: String,
+    ///The token to authorize requests with.
+    token: auth::Token,
+    ///The fields and expansions to request for each page.
+    fields: Fields,
+    start_time: Option<chrono::DateTime<chrono::Utc>>,
+    end_time: Option<chrono::DateTime<chrono::Utc>>,
+    since_id: Option<u64>,
+    until_id: Option<u64>,
+    ///The maximum number of tweets to return in a single call.
+    pub max_results: u32,
+    ///The token for the page to load with `call`, or `None` to load the first page.
+    pub next_token: Option<String>,
+}
+
+impl SearchAll {
+    ///Restricts results to those posted at or after the given time. The default is 30 days before
+    ///`end_time`.
+    pub fn start_time(self, start_time: chrono::DateTime<chrono::Utc>) -> Self {
+        SearchAll {
+            start_time: Some(start_time),
+            ..self
+        }
+    }
+
+    ///Restricts results to those posted before the given time. The default is the current time.
+    pub fn end_time(self, end_time: chrono::DateTime<chrono::Utc>) -> Self {
+        SearchAll {
+            end_time: Some(end_time),
+            ..self
+        }
+    }
+
+    ///Restricts results to those with higher IDs than (i.e. that were posted after) the given
+    ///tweet ID.
+    pub fn since_id(self, since_id: u64) -> Self {
+        SearchAll {
+            since_id: Some(since_id),
+            ..self
+        }
+    }
+
+    ///Restricts results to those with lower IDs than (i.e. that were posted before) the given
+    ///tweet ID.
+    pub fn until_id(self, until_id: u64) -> Self {
+        SearchAll {
+            until_id: Some(until_id),
+            ..self
+        }
+    }
+
+    ///Helper builder function to set the page size.
+    pub fn with_page_size(self, page_size: u32) -> Self {
+        SearchAll {
+            max_results: page_size,
+            ..self
+        }
+    }
+
+    ///Return the page of tweets given by `next_token`, or the first page if it isn't set.
+    ///
+    ///Note that this doesn't update `next_token`, so you'll have to set it yourself from the
+    ///response's `meta` to load the next page.
+    pub async fn call(&self) -> Result<Response<Payload<Vec<Tweet>>>> {
+        let time = |time: chrono::DateTime<chrono::Utc>| {
+            time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        };
+        let params = ParamList::new()
+            .add_param("query", self.query.clone())
+            .add_param("max_results", self.max_results.to_string())
+            .add_opt_param("start_time", self.start_time.map(time))
+            .add_opt_param("end_time", self.end_time.map(time))
+            .add_opt_param("since_id", self.since_id.map_string())
+            .add_opt_param("until_id", self.until_id.map_string())
+            .add_opt_param("next_token", self.next_token.clone());
+        let params = self.fields.add_params(params);
+
+        let req = get(links::v2::SEARCH_ALL, &self.token, Some(&params));
+        request_with_json_response(req).await
+    }
+
+    ///Converts this search into a `Stream` of pages of tweets, which automatically loads the next
+    ///page as needed until there are no more results.
+    pub fn into_page_stream(self) -> impl Stream<Item = Result<Response<Payload<Vec<Tweet>>>>> {
+        stream::try_unfold(Some(self), |search| async move {
+            let mut search = match search {
+                Some(search) => search,
+                None => return Ok::<_, crate::error::Error>(None),
+            };
+            let page = search.call().await?;
+            search.next_token = page.meta.as_ref().and_then(|m| m.next_token.clone());
+            let next = if search.next_token.is_some() {
+                Some(search)
+            } else {
+                None
+            };
+            Ok(Some((page, next)))
+        })
+    }
+
+    ///Converts this search into a `Stream` of tweets, which automatically loads the next page as
+    ///needed until there are no more results.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Response<Tweet>>> {
+        self.into_page_stream()
+            .map_ok(|page| stream::iter(Response::map(page, |page| page.data)).map(Ok))
+            .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tests::load_file;
+    use crate::testing::MockTwitter;
+    use crate::v2::{Expansion, TweetField, UserField};
+
+    use chrono::TimeZone;
+    use futures::TryStreamExt;
+    use hyper::{Method, StatusCode};
+
+    #[test]
+    fn parse_lookup() {
+        let sample = load_file("sample_payloads/v2-tweets-lookup.json");
+        let payload: Payload<Vec<Tweet>> = serde_json::from_str(&sample).unwrap();
+
+        assert_eq!(payload.data.len(), 2);
+        assert_eq!(payload.errors.len(), 1);
+        assert_eq!(
+            payload.errors[0].resource_id.as_deref(),
+            Some("1276230436478386177")
+        );
+
+        let tweet = &payload.data[0];
+        assert_eq!(tweet.id, 1261326399320715264);
+        assert_eq!(tweet.public_metrics.unwrap().like_count, 36);
+        assert_eq!(payload.includes.author_of(tweet).unwrap().username, "TwitterDev");
+        let media = payload.includes.media_of(tweet).collect::<Vec<_>>();
+        assert_eq!(media.len(), 1);
+        assert_eq!(media[0].kind, "photo");
+
+        let retweet = &payload.data[1];
+        let (kind, original) = payload.includes.referenced_tweets_of(retweet).next().unwrap();
+        assert_eq!(kind, ReferenceKind::Retweeted);
+        assert_eq!(original.id, 1293593516040269825);
+        assert_eq!(payload.includes.author_of(original).unwrap().id, 2244994945);
+        assert_eq!(
+            payload.includes.place_of(retweet).unwrap().full_name,
+            "Manhattan, NY"
+        );
+        assert!(payload.includes.poll_of(retweet).is_none());
+    }
+
+    #[tokio::test]
+    async fn lookup_sends_fields() {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::GET,
+            "/2/tweets",
+            StatusCode::OK,
+            load_file("sample_payloads/v2-tweets-lookup.json"),
+        );
+        let fields = Fields::new()
+            .tweet_fields([TweetField::CreatedAt, TweetField::PublicMetrics])
+            .expansions([Expansion::AuthorId])
+            .user_fields([UserField::Verified]);
+        let resp = twitter
+            .run(lookup([1, 2], &fields, &MockTwitter::token()))
+            .await
+            .unwrap();
+        assert_eq!(resp.data.len(), 2);
+
+        let params = &twitter.requests()[0].params;
+        assert_eq!(params["ids"], "1,2");
+        assert_eq!(params["tweet.fields"], "created_at,public_metrics");
+        assert_eq!(params["expansions"], "author_id");
+        assert_eq!(params["user.fields"], "verified");
+        assert!(!params.contains_key("media.fields"));
+    }
+
+    #[tokio::test]
+    async fn timeline_follows_page_tokens() {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::GET,
+            "/2/users/2244994945/tweets",
+            StatusCode::OK,
+            load_file("sample_payloads/v2-user-timeline.json"),
+        );
+        let timeline = user_timeline(2244994945, &Fields::new(), &MockTwitter::token())
+            .with_page_size(5);
+
+        let (timeline, feed) = twitter.run(timeline.start()).await.unwrap();
+        assert_eq!(feed.data.len(), 2);
+        assert_eq!(feed.meta.as_ref().unwrap().newest_id, Some(1338971066773905408));
+        assert_eq!(
+            timeline.next_token.as_deref(),
+            Some("7140dibdnow9c7btw3w29grvxfcgvpb9n9coehpk7xz5i")
+        );
+        let _ = twitter.run(timeline.older()).await.unwrap();
+
+        let requests = twitter.requests();
+        assert_eq!(requests[0].params["max_results"], "5");
+        assert!(!requests[0].params.contains_key("pagination_token"));
+        assert_eq!(
+            requests[1].params["pagination_token"],
+            "7140dibdnow9c7btw3w29grvxfcgvpb9n9coehpk7xz5i"
+        );
+    }
+
+    #[tokio::test]
+    async fn timeline_stops_after_last_page() {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::GET,
+            "/2/users/2244994945/tweets",
+            StatusCode::OK,
+            r#"{"meta": {"result_count": 0, "previous_token": "77qp8"}}"#,
+        );
+        let timeline = user_timeline(2244994945, &Fields::new(), &MockTwitter::token());
+
+        let (timeline, _) = twitter.run(timeline.start()).await.unwrap();
+        assert_eq!(timeline.next_token, None);
+        let (_, feed) = twitter.run(timeline.older()).await.unwrap();
+        assert!(feed.data.is_empty());
+        assert_eq!(twitter.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn search_all_sends_bounds() {
+        let twitter = MockTwitter::new();
+        twitter.respond(
+            Method::GET,
+            "/2/tweets/search/all",
+            StatusCode::OK,
+            r#"{"data":[{"id":"2","text":"two"},{"id":"1","text":"one"}],"meta":{"result_count":2}}"#,
+        );
+        let search = search_all("from:rustlang", &Fields::new(), &MockTwitter::token())
+            .start_time(chrono::Utc.with_ymd_and_hms(2015, 5, 1, 0, 0, 0).unwrap())
+            .until_id(3)
+            .with_page_size(100);
+
+        let tweets = twitter
+            .run(search.into_stream().try_collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(tweets.iter().map(|t| t.id).collect::<Vec<_>>(), [2, 1]);
+
+        let params = &twitter.requests()[0].params;
+        assert_eq!(params["query"], "from:rustlang");
+        assert_eq!(params["start_time"], "2015-05-01T00:00:00Z");
+        assert_eq!(params["until_id"], "3");
+        assert_eq!(params["max_results"], "100");
+        assert!(!params.contains_key("next_token"));
+    }
+}